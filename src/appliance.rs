@@ -0,0 +1,136 @@
+//! OVF/OVA appliance import and export.
+
+use regex::Regex;
+use std::rc::Rc;
+
+/// Settings applied to imported machine, unset fields keep values from appliance.
+#[derive(Clone, Default)]
+pub struct ImportOptions {
+    pub name: Option<String>,
+    /// Directory machine folder is created in.
+    pub base_folder: Option<String>,
+    pub cpus: Option<u32>,
+    pub memory_mb: Option<u64>,
+    pub accept_eulas: bool,
+    on_progress: Option<Rc<dyn Fn(u32)>>,
+}
+
+impl ImportOptions {
+    pub fn new() -> Self {
+        ImportOptions::default()
+    }
+
+    pub fn name<T: Into<String>>(mut self, name: T) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn base_folder<T: Into<String>>(mut self, base_folder: T) -> Self {
+        self.base_folder = Some(base_folder.into());
+        self
+    }
+
+    pub fn cpus(mut self, cpus: u32) -> Self {
+        self.cpus = Some(cpus);
+        self
+    }
+
+    pub fn memory_mb(mut self, memory_mb: u64) -> Self {
+        self.memory_mb = Some(memory_mb);
+        self
+    }
+
+    pub fn accept_eulas(mut self) -> Self {
+        self.accept_eulas = true;
+        self
+    }
+
+    /// Called with completion percentage while import runs.
+    pub fn on_progress<F: Fn(u32) + 'static>(mut self, on_progress: F) -> Self {
+        self.on_progress = Some(Rc::new(on_progress));
+        self
+    }
+
+    /// Forwards percentages found in tool output to progress callback.
+    #[cfg(any(test, feature = "vmware", feature = "virtualbox"))]
+    pub(crate) fn report(&self, output: &str) {
+        if let Some(ref on_progress) = self.on_progress {
+            for percent in progress_parse(output) {
+                on_progress(percent)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Descriptor with disks as separate files, path must end with `.ovf`.
+    Ovf,
+    /// Single tar archive, path must end with `.ova`.
+    Ova,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Ovf => "ovf",
+            ExportFormat::Ova => "ova",
+        }
+    }
+
+    /// Whether `path` has extension of this format.
+    pub fn matches(self, path: &str) -> bool {
+        path.to_ascii_lowercase()
+            .ends_with(&format!(".{}", self.extension()))
+    }
+}
+
+/// What import would create, as reported by hypervisor without importing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportPlan {
+    pub name: String,
+    pub os_type: Option<String>,
+    pub cpus: Option<u32>,
+    pub memory_mb: Option<u64>,
+    pub settings_file: Option<String>,
+    /// Target paths of disk images.
+    pub disks: Vec<String>,
+}
+
+/// Percentages in progress output of `vboxmanage` (`0%...10%...`) and
+/// `ovftool` (`Disk progress: 10%`).
+pub fn progress_parse(output: &str) -> Vec<u32> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(\d{1,3})%").unwrap();
+    }
+
+    RE.captures_iter(output)
+        .filter_map(|caps| caps[1].parse().ok())
+        .filter(|percent| *percent <= 100)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_progress() {
+        assert_eq!(progress_parse("0%...10%...20%"), vec![0, 10, 20]);
+        assert_eq!(progress_parse("\rDisk progress: 45%"), vec![45]);
+        assert!(progress_parse("Interpreting x.ova...").is_empty());
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = seen.clone();
+        let options = ImportOptions::new().on_progress(move |p| sink.borrow_mut().push(p));
+        options.report("90%...100%");
+        assert_eq!(*seen.borrow(), vec![90, 100]);
+    }
+
+    #[test]
+    fn test_format() {
+        assert!(ExportFormat::Ova.matches("/tmp/golden.OVA"));
+        assert!(!ExportFormat::Ovf.matches("/tmp/golden.ova"));
+    }
+}
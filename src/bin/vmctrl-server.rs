@@ -0,0 +1,957 @@
+#[macro_use]
+extern crate error_chain;
+extern crate clap;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate tiny_http;
+extern crate vmctrl;
+
+use clap::{App, Arg};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+use vmctrl::appliance::{ExportFormat, ImportOptions};
+use vmctrl::config::MachineConfig;
+use vmctrl::disk::{DiskFormat, DiskOptions, DiskSlot};
+use vmctrl::error::*;
+use vmctrl::guest::Credentials;
+use vmctrl::http::{
+    CommandRequest, CommandResponse, ErrorResponse, COMMAND_PATH, DEFAULT_PORT, TOKEN_VAR,
+};
+use vmctrl::inventory::Inventory;
+use vmctrl::keyboard::KeyCombo;
+use vmctrl::network::{NicConfig, PortForward};
+use vmctrl::serial::SerialMode;
+use vmctrl::shared_folder::SharedFolder;
+use vmctrl::uri::{DriverRepo, Listing, VmUri};
+use vmctrl::{CommandRunner, Driver, Machine};
+
+/// Text is typed before key combos are pressed.
+#[derive(Deserialize)]
+struct KeyboardRequest {
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    keys: Vec<String>,
+}
+
+/// Guest credentials, with process to kill for `/v1/machine/processes/kill`.
+#[derive(Deserialize)]
+struct ProcessRequest {
+    user: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    pid: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ExecRequest {
+    user: String,
+    #[serde(default)]
+    password: String,
+    program: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// Subcommands `/v1/command` runs unless `--allow-command` is given.
+const DEFAULT_COMMAND_RULES: &[&str] = &[
+    "vmrun",
+    "vmrun list",
+    "vmrun start",
+    "vmrun stop",
+    "vmrun reset",
+    "vmrun suspend",
+    "vmrun pause",
+    "vmrun unpause",
+    "vmrun listSnapshots",
+    "vmrun snapshot",
+    "vmrun deleteSnapshot",
+    "vmrun revertToSnapshot",
+    "vmrun readVariable",
+    "vmrun writeVariable",
+    "vmrun getGuestIPAddress",
+    "vmrun checkToolsState",
+    "vboxmanage --version",
+    "vboxmanage list",
+    "vboxmanage showvminfo",
+    "vboxmanage startvm",
+    "vboxmanage controlvm * poweroff",
+    "vboxmanage controlvm * acpipowerbutton",
+    "vboxmanage controlvm * pause",
+    "vboxmanage controlvm * resume",
+    "vboxmanage controlvm * reset",
+    "vboxmanage controlvm * savestate",
+    "vboxmanage snapshot",
+    "vboxmanage guestproperty",
+];
+
+/// vmrun global options, each followed by its value.
+const VMRUN_OPTIONS: &[&str] = &["-T", "-h", "-P", "-u", "-p", "-vp", "-gu", "-gp"];
+
+/// `/v1/command` allowlist entry, e.g. `vmrun start` or `vboxmanage controlvm * poweroff`.
+/// Operands are matched as prefix, `*` matches any; entry without operands only
+/// matches a run without any (`vmrun` printing its help).
+struct CommandRule {
+    command: String,
+    operands: Vec<String>,
+}
+
+impl CommandRule {
+    fn parse(rule: &str) -> Result<Self> {
+        let mut words = rule.split_whitespace().map(|word| word.to_string());
+        match words.next() {
+            Some(command) => Ok(CommandRule {
+                command,
+                operands: words.collect(),
+            }),
+            None => bail!(ErrorKind::InvalidConfig("empty command rule".into())),
+        }
+    }
+
+    /// Configured binary may be a full path, clients name it by file name.
+    fn matches(&self, command: &str, args: &[String]) -> bool {
+        let operands = command_operands(args);
+        let command_matches = self.command == command
+            || Path::new(&self.command).file_name() == Some(command.as_ref());
+        let operands_match = if self.operands.is_empty() {
+            operands.is_empty()
+        } else {
+            operands.len() >= self.operands.len()
+                && self
+                    .operands
+                    .iter()
+                    .zip(&operands)
+                    .all(|(rule, operand)| rule == "*" || rule == operand)
+        };
+        command_matches && operands_match
+    }
+}
+
+struct Shared {
+    server: Server,
+    inventory: Option<Inventory>,
+    token: String,
+    /// `None` when `/v1/command` is disabled.
+    command_rules: Option<Vec<CommandRule>>,
+    locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl Shared {
+    /// Lock serializing operations on single machine.
+    fn lock_for(&self, key: &str) -> Arc<Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(())))
+            .clone()
+    }
+
+    /// Binary to run for `/v1/command` request, as configured by the matching rule.
+    fn allowed_command(&self, command: &str, args: &[String]) -> Result<&str> {
+        let rules = match self.command_rules {
+            Some(ref rules) => rules,
+            None => bail!(ErrorKind::Http(403, "command proxy disabled".into())),
+        };
+        match rules.iter().find(|rule| rule.matches(command, args)) {
+            Some(rule) => Ok(&rule.command),
+            None => bail!(ErrorKind::Http(
+                403,
+                format!(
+                    "command '{} {}' not allowed",
+                    command,
+                    command_operands(args).first().unwrap_or(&"")
+                )
+            )),
+        }
+    }
+
+    /// Compares whole token regardless of where it differs.
+    fn is_authorized(&self, request: &Request) -> bool {
+        let expected = format!("Bearer {}", self.token);
+        request
+            .headers()
+            .iter()
+            .filter(|header| header.field.equiv("Authorization"))
+            .any(|header| {
+                let given = header.value.as_str().as_bytes();
+                given.len() == expected.len()
+                    && given
+                        .iter()
+                        .zip(expected.as_bytes())
+                        .fold(0, |diff, (a, b)| diff | (a ^ b))
+                        == 0
+            })
+    }
+}
+
+/// Arguments after vmrun global options (`-T ws`, `-gu user`, ...), subcommand first.
+fn command_operands(args: &[String]) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if operands.is_empty() && VMRUN_OPTIONS.contains(&arg.as_str()) {
+            let _ = it.next();
+            continue;
+        }
+        operands.push(arg.as_str());
+    }
+    operands
+}
+
+/// Operand naming the machine, for both `vmrun` and `vboxmanage` it follows
+/// subcommand, except for `vboxmanage guestproperty get <vm>`.
+fn command_target(args: &[String]) -> Option<&str> {
+    let operands = command_operands(args);
+    match operands.first() {
+        Some(&"guestproperty") => operands.get(2).cloned(),
+        _ => operands.get(1).cloned(),
+    }
+}
+
+/// Lock key naming machine the same way whether it was given by path, name,
+/// uuid or inventory entry: VirtualBox uuid or machine path, with its host.
+fn machine_key(machine: &dyn Machine) -> String {
+    let uri = machine.uri();
+    let uuid = if uri.scheme().ends_with("virtualbox") {
+        machine.info().ok().and_then(|info| info.uuid)
+    } else {
+        None
+    };
+    format!(
+        "{}:{}",
+        uri.host().unwrap_or(""),
+        uuid.as_deref().unwrap_or_else(|| uri.path())
+    )
+}
+
+/// Lock key of machine named in `/v1/command` arguments, same as REST routes use.
+fn command_key(repo: &DriverRepo, command: &str, target: &str) -> String {
+    let scheme = if command.to_lowercase().contains("vmrun") {
+        "vmware"
+    } else {
+        "virtualbox"
+    };
+    let uri = VmUri::new(scheme, target);
+    match repo.apply(scheme, |driver| driver.machine_for_uri(&uri)) {
+        Ok(machine) => machine_key(&*machine),
+        Err(_) => format!(":{}", target),
+    }
+}
+
+fn param<'a>(url: &'a VmUri, name: &str) -> Result<&'a str> {
+    match url.query_param(name) {
+        Some(value) => Ok(value),
+        None => bail!(ErrorKind::Http(
+            400,
+            format!("missing '{}' parameter", name)
+        )),
+    }
+}
+
+/// Hardware changes from `cpus`, `memory`, `firmware` and `boot` query parameters.
+fn machine_config(url: &VmUri) -> Result<MachineConfig> {
+    let invalid = |name: &str| ErrorKind::Http(400, format!("invalid '{}' parameter", name));
+    let mut config = MachineConfig::new();
+    if let Some(cpus) = url.query_param("cpus") {
+        config = config.cpus(cpus.parse().chain_err(|| invalid("cpus"))?);
+    }
+    if let Some(memory) = url.query_param("memory") {
+        config = config.memory_mb(memory.parse().chain_err(|| invalid("memory"))?);
+    }
+    if let Some(firmware) = url.query_param("firmware") {
+        config = config.firmware(firmware.parse()?);
+    }
+    if let Some(boot) = url.query_param("boot") {
+        let devices = boot
+            .split(',')
+            .filter(|device| !device.is_empty())
+            .map(|device| device.parse())
+            .collect::<Result<Vec<_>>>()?;
+        config = config.boot_order(&devices);
+    }
+    Ok(config)
+}
+
+/// Size in MB from `size` query parameter.
+fn disk_size(url: &VmUri) -> Result<u64> {
+    param(url, "size")?
+        .parse()
+        .chain_err(|| ErrorKind::Http(400, "invalid 'size' parameter".into()))
+}
+
+/// New disk from `size`, `format` (defaults to `path` extension) and `fixed`.
+fn disk_options(url: &VmUri) -> Result<DiskOptions> {
+    let format = match url.query_param("format") {
+        Some(format) => format.parse()?,
+        None => DiskFormat::for_path(param(url, "path")?)
+            .chain_err(|| ErrorKind::Http(400, "missing 'format' parameter".into()))?,
+    };
+    let mut options = DiskOptions::new(disk_size(url)?).format(format);
+    if url.query_param("fixed") == Some("true") {
+        options = options.fixed();
+    }
+    Ok(options)
+}
+
+/// Adapter slot from `slot` query parameter.
+fn slot(url: &VmUri) -> Result<u32> {
+    param(url, "slot")?
+        .parse()
+        .chain_err(|| ErrorKind::Http(400, "invalid 'slot' parameter".into()))
+}
+
+fn serial_port(url: &VmUri) -> Result<u32> {
+    param(url, "port")?
+        .parse()
+        .chain_err(|| ErrorKind::Http(400, "invalid 'port' parameter".into()))
+}
+
+/// Adapter changes from `attachment`, `network`, `mac` and `cable` query parameters.
+fn nic_config(url: &VmUri) -> Result<NicConfig> {
+    let mut config = NicConfig::new();
+    if let Some(attachment) = url.query_param("attachment") {
+        config = config.attachment(attachment.parse()?);
+    }
+    if let Some(network) = url.query_param("network") {
+        config = config.network(network);
+    }
+    if let Some(mac) = url.query_param("mac") {
+        config = config.mac(mac);
+    }
+    match url.query_param("cable") {
+        Some("true") => config = config.cable_connected(true),
+        Some("false") => config = config.cable_connected(false),
+        Some(_) => bail!(ErrorKind::Http(400, "invalid 'cable' parameter".into())),
+        None => (),
+    }
+    Ok(config)
+}
+
+fn port_forward_json(rule: &PortForward) -> serde_json::Value {
+    json!({
+        "name": rule.name,
+        "protocol": rule.protocol.as_str(),
+        "host_ip": rule.host_ip,
+        "host_port": rule.host_port,
+        "guest_ip": rule.guest_ip,
+        "guest_port": rule.guest_port,
+    })
+}
+
+/// Folder from `name`, `host_path`, `writable`, `auto_mount`, `mount_point`
+/// and `transient` query parameters.
+fn shared_folder(url: &VmUri) -> Result<SharedFolder> {
+    let mut folder = SharedFolder::new(param(url, "name")?, param(url, "host_path")?);
+    if url.query_param("writable") == Some("false") {
+        folder = folder.read_only();
+    }
+    if url.query_param("auto_mount") == Some("true") {
+        folder = folder.auto_mount();
+    }
+    if let Some(mount_point) = url.query_param("mount_point") {
+        folder = folder.mount_point(mount_point);
+    }
+    if url.query_param("transient") == Some("true") {
+        folder = folder.transient();
+    }
+    Ok(folder)
+}
+
+fn shared_folder_json(folder: &SharedFolder) -> serde_json::Value {
+    json!({
+        "name": folder.name,
+        "host_path": folder.host_path,
+        "writable": folder.writable,
+        "auto_mount": folder.auto_mount,
+        "mount_point": folder.mount_point,
+        "transient": folder.transient,
+    })
+}
+
+/// Runs `f` holding machine lock for the whole call, so compound operations
+/// (revert, then start) don't interleave with other requests for the machine.
+fn with_machine<F, T>(shared: &Shared, repo: &DriverRepo, url: &VmUri, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Box<dyn Machine>) -> Result<T>,
+{
+    let mut machine = repo.from_path(param(url, "uri")?)?;
+    let lock = shared.lock_for(&machine_key(&*machine));
+    let _guard = lock.lock().unwrap();
+
+    f(&mut machine)
+}
+
+fn run_command(shared: &Shared, repo: &DriverRepo, body: &str) -> Result<serde_json::Value> {
+    let request: CommandRequest = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(e) => bail!(ErrorKind::Http(400, e.to_string())),
+    };
+    let command = shared.allowed_command(&request.command, &request.args)?;
+
+    let lock = command_target(&request.args)
+        .map(|target| shared.lock_for(&command_key(repo, command, target)));
+    let _guard = lock.as_ref().map(|lock| lock.lock().unwrap());
+    let output = match request.stdin {
+        Some(ref input) => vmctrl::local().run_with_input(command, &request.args, input)?,
+        None => vmctrl::local().run_with_output(command, &request.args)?,
+    };
+    let stdout = output.into_iter().collect();
+
+    Ok(json!(CommandResponse { stdout }))
+}
+
+fn route(
+    shared: &Shared,
+    repo: &DriverRepo,
+    method: &Method,
+    url: &VmUri,
+    body: &str,
+) -> Result<serde_json::Value> {
+    let ok = json!({ "ok": true });
+
+    // clients get machines that were listed, skipped lines go to server log.
+    let listing = |listing: Listing<Box<dyn Machine>>| {
+        for e in &listing.skipped {
+            eprintln!("{}: skipped {}", url, e);
+        }
+        let list: Vec<_> = listing
+            .machines
+            .iter()
+            .map(|m| json!({"name": m.name(), "uri": m.uri().to_string()}))
+            .collect();
+        json!(list)
+    };
+
+    match (method, url.path()) {
+        (&Method::Get, "/v1/running") => Ok(listing(repo.list_running_at(param(url, "uri")?)?)),
+        (&Method::Get, "/v1/machines") => Ok(listing(repo.list_all_at(param(url, "uri")?)?)),
+        (&Method::Get, "/v1/version") => {
+            let version = repo.version_at(param(url, "uri")?)?;
+            Ok(json!({ "version": version.to_string() }))
+        }
+        (&Method::Get, "/v1/machine/state") => with_machine(shared, repo, url, |m| {
+            Ok(json!({ "state": m.state()?.as_str() }))
+        }),
+        (&Method::Post, "/v1/machine/start") => with_machine(shared, repo, url, |m| {
+            m.start()?;
+            Ok(ok)
+        }),
+        (&Method::Post, "/v1/machine/stop") => with_machine(shared, repo, url, |m| {
+            m.stop()?;
+            Ok(ok)
+        }),
+        (&Method::Get, "/v1/machine/snapshots") => {
+            with_machine(shared, repo, url, |m| Ok(json!(m.list_snapshots()?)))
+        }
+        (&Method::Post, "/v1/machine/snapshots") => with_machine(shared, repo, url, |m| {
+            m.create_snapshot(param(url, "name")?)?;
+            Ok(ok)
+        }),
+        (&Method::Delete, "/v1/machine/snapshots") => with_machine(shared, repo, url, |m| {
+            m.delete_snapshot(param(url, "name")?)?;
+            Ok(ok)
+        }),
+        (&Method::Post, "/v1/machine/revert") => with_machine(shared, repo, url, |m| {
+            m.revert_to(param(url, "name")?)?;
+            Ok(ok)
+        }),
+        (&Method::Post, "/v1/machine/clone") => with_machine(shared, repo, url, |m| {
+            let clone = m.clone_to(
+                param(url, "target")?,
+                url.query_param("snapshot"),
+                url.query_param("linked") == Some("true"),
+            )?;
+            Ok(json!({"name": clone.name(), "uri": clone.uri().to_string()}))
+        }),
+        (&Method::Post, "/v1/register") => {
+            let machine = repo.register_at(param(url, "uri")?)?;
+            Ok(json!({"name": machine.name(), "uri": machine.uri().to_string()}))
+        }
+        (&Method::Post, "/v1/import") => {
+            let mut options = ImportOptions::new();
+            if let Some(name) = url.query_param("name") {
+                options = options.name(name);
+            }
+            if let Some(base_folder) = url.query_param("base_folder") {
+                options = options.base_folder(base_folder);
+            }
+            if url.query_param("accept_eulas") == Some("true") {
+                options = options.accept_eulas();
+            }
+            let machine = repo.import_appliance_at(param(url, "uri")?, &options)?;
+            Ok(json!({"name": machine.name(), "uri": machine.uri().to_string()}))
+        }
+        (&Method::Post, "/v1/machine/configure") => {
+            let config = machine_config(url)?;
+            with_machine(shared, repo, url, |m| {
+                m.configure(&config)?;
+                Ok(ok)
+            })
+        }
+        (&Method::Post, "/v1/machine/nic") => {
+            let (slot, config) = (slot(url)?, nic_config(url)?);
+            with_machine(shared, repo, url, |m| {
+                m.configure_nic(slot, &config)?;
+                Ok(ok)
+            })
+        }
+        (&Method::Get, "/v1/machine/forwards") => with_machine(shared, repo, url, |m| {
+            let rules = m.port_forwards(slot(url)?)?;
+            Ok(json!(rules
+                .iter()
+                .map(port_forward_json)
+                .collect::<Vec<_>>()))
+        }),
+        (&Method::Post, "/v1/machine/forwards") => with_machine(shared, repo, url, |m| {
+            let rule: PortForward = param(url, "rule")?.parse()?;
+            m.add_port_forward(slot(url)?, &rule)?;
+            Ok(ok)
+        }),
+        (&Method::Delete, "/v1/machine/forwards") => with_machine(shared, repo, url, |m| {
+            m.remove_port_forward(slot(url)?, param(url, "name")?)?;
+            Ok(ok)
+        }),
+        (&Method::Post, "/v1/disks") => {
+            let options = disk_options(url)?;
+            let store = repo.disk_store_at(param(url, "uri")?)?;
+            store.create(param(url, "path")?, &options)?;
+            Ok(ok)
+        }
+        (&Method::Post, "/v1/disks/resize") => {
+            let store = repo.disk_store_at(param(url, "uri")?)?;
+            store.resize(param(url, "path")?, disk_size(url)?)?;
+            Ok(ok)
+        }
+        (&Method::Post, "/v1/disks/compact") => {
+            let store = repo.disk_store_at(param(url, "uri")?)?;
+            store.compact(param(url, "path")?)?;
+            Ok(ok)
+        }
+        (&Method::Delete, "/v1/disks") => {
+            let store = repo.disk_store_at(param(url, "uri")?)?;
+            store.delete(param(url, "path")?)?;
+            Ok(ok)
+        }
+        (&Method::Post, "/v1/machine/disks") => with_machine(shared, repo, url, |m| {
+            let slot: DiskSlot = param(url, "slot")?.parse()?;
+            m.attach_disk(&slot, param(url, "path")?)?;
+            Ok(ok)
+        }),
+        (&Method::Delete, "/v1/machine/disks") => with_machine(shared, repo, url, |m| {
+            let slot: DiskSlot = param(url, "slot")?.parse()?;
+            m.detach_disk(&slot)?;
+            Ok(ok)
+        }),
+        (&Method::Get, "/v1/machine/shared_folders") => with_machine(shared, repo, url, |m| {
+            let folders = m.shared_folders()?;
+            Ok(json!(folders
+                .iter()
+                .map(shared_folder_json)
+                .collect::<Vec<_>>()))
+        }),
+        (&Method::Post, "/v1/machine/shared_folders") => {
+            let folder = shared_folder(url)?;
+            with_machine(shared, repo, url, |m| {
+                m.add_shared_folder(&folder)?;
+                Ok(ok)
+            })
+        }
+        (&Method::Delete, "/v1/machine/shared_folders") => with_machine(shared, repo, url, |m| {
+            m.remove_shared_folder(param(url, "name")?)?;
+            Ok(ok)
+        }),
+        (&Method::Post, "/v1/machine/shared_folders/state") => {
+            with_machine(shared, repo, url, |m| {
+                m.set_shared_folders_enabled(param(url, "enabled")? == "true")?;
+                Ok(ok)
+            })
+        }
+        (&Method::Post, "/v1/machine/export") => with_machine(shared, repo, url, |m| {
+            let path = param(url, "path")?;
+            let format = if ExportFormat::Ova.matches(path) {
+                ExportFormat::Ova
+            } else {
+                ExportFormat::Ovf
+            };
+            m.export(path, format)?;
+            Ok(ok)
+        }),
+        (&Method::Post, "/v1/machine/unregister") => with_machine(shared, repo, url, |m| {
+            m.unregister()?;
+            Ok(ok)
+        }),
+        (&Method::Delete, "/v1/machine") => with_machine(shared, repo, url, |m| {
+            m.delete(url.query_param("disks") != Some("false"))?;
+            Ok(ok)
+        }),
+        (&Method::Get, "/v1/machine/serial") => with_machine(shared, repo, url, |m| {
+            Ok(json!({ "mode": m.serial_mode(serial_port(url)?)?.to_string() }))
+        }),
+        (&Method::Post, "/v1/machine/serial") => with_machine(shared, repo, url, |m| {
+            let mode: SerialMode = param(url, "mode")?.parse()?;
+            m.configure_serial(serial_port(url)?, &mode)?;
+            Ok(ok)
+        }),
+        (&Method::Get, "/v1/machine/properties") => with_machine(shared, repo, url, |m| {
+            let props: Vec<_> = m
+                .guest_properties(url.query_param("pattern").unwrap_or("*"))?
+                .iter()
+                .map(|p| json!({"name": p.name, "value": p.value}))
+                .collect();
+            Ok(json!(props))
+        }),
+        (&Method::Get, "/v1/machine/property") => with_machine(shared, repo, url, |m| {
+            Ok(json!({ "value": m.guest_property(param(url, "name")?)? }))
+        }),
+        (&Method::Post, "/v1/machine/property") => with_machine(shared, repo, url, |m| {
+            m.set_guest_property(param(url, "name")?, param(url, "value")?)?;
+            Ok(ok)
+        }),
+        (&Method::Delete, "/v1/machine/property") => with_machine(shared, repo, url, |m| {
+            m.delete_guest_property(param(url, "name")?)?;
+            Ok(ok)
+        }),
+        (&Method::Post, "/v1/machine/property/wait") => {
+            let timeout = param(url, "timeout")?
+                .parse()
+                .chain_err(|| ErrorKind::Http(400, "invalid 'timeout' parameter".into()))?;
+            with_machine(shared, repo, url, |m| {
+                let prop =
+                    m.wait_for_property(param(url, "pattern")?, Duration::from_secs(timeout))?;
+                Ok(json!(
+                    prop.map(|p| json!({"name": p.name, "value": p.value}))
+                ))
+            })
+        }
+        (&Method::Post, "/v1/machine/keyboard") => {
+            let request: KeyboardRequest = match serde_json::from_str(body) {
+                Ok(request) => request,
+                Err(e) => bail!(ErrorKind::Http(400, e.to_string())),
+            };
+            let combos = request
+                .keys
+                .iter()
+                .map(|combo| combo.parse())
+                .collect::<Result<Vec<KeyCombo>>>()?;
+            with_machine(shared, repo, url, |m| {
+                if let Some(ref text) = request.text {
+                    m.send_text(text)?;
+                }
+                for combo in &combos {
+                    m.send_keys(combo)?;
+                }
+                Ok(ok)
+            })
+        }
+        (&Method::Post, "/v1/machine/exec") => {
+            let request: ExecRequest = match serde_json::from_str(body) {
+                Ok(request) => request,
+                Err(e) => bail!(ErrorKind::Http(400, e.to_string())),
+            };
+            with_machine(shared, repo, url, |m| {
+                let credentials = Credentials::new(request.user, request.password);
+                let args: Vec<&str> = request.args.iter().map(|s| s.as_str()).collect();
+                let stdout: Vec<String> = m
+                    .guest_session(&credentials)?
+                    .exec(&request.program, &args)?
+                    .into_iter()
+                    .collect();
+                Ok(json!({ "stdout": stdout }))
+            })
+        }
+        (&Method::Post, "/v1/machine/processes")
+        | (&Method::Post, "/v1/machine/processes/kill") => {
+            let request: ProcessRequest = match serde_json::from_str(body) {
+                Ok(request) => request,
+                Err(e) => bail!(ErrorKind::Http(400, e.to_string())),
+            };
+            with_machine(shared, repo, url, |m| {
+                let credentials = Credentials::new(request.user, request.password);
+                let session = m.guest_session(&credentials)?;
+                if url.path() == "/v1/machine/processes" {
+                    let list: Vec<_> = session
+                        .list_processes()?
+                        .iter()
+                        .map(|p| json!({"pid": p.pid, "owner": p.owner, "command": p.command}))
+                        .collect();
+                    return Ok(json!(list));
+                }
+                match request.pid {
+                    Some(pid) => session.kill(pid)?,
+                    None => bail!(ErrorKind::Http(400, "missing 'pid'".into())),
+                }
+                Ok(ok)
+            })
+        }
+        (&Method::Post, COMMAND_PATH) => run_command(shared, repo, body),
+        (method, path) => bail!(ErrorKind::Http(
+            404,
+            format!("no route for {} {}", method, path)
+        )),
+    }
+}
+
+fn status_for(e: &Error) -> u16 {
+    match *e.kind() {
+        ErrorKind::Http(status, _) => status,
+        ErrorKind::UnknownScheme(_) | ErrorKind::InvalidUri(_) | ErrorKind::InvalidConfig(_) => 400,
+        ErrorKind::UnknownMachine(_) | ErrorKind::UnknownHost(_) => 404,
+        ErrorKind::MachineRunning(_)
+        | ErrorKind::MachineSuspended(_)
+        | ErrorKind::HasLinkedClones(..) => 409,
+        ErrorKind::NotSupported(_) => 501,
+        _ => 500,
+    }
+}
+
+fn json_response(status: u16, body: &serde_json::Value) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    Response::from_data(body.to_string().into_bytes())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+fn data_response(content_type: &str, data: Vec<u8>) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap();
+    Response::from_data(data).with_header(content_type)
+}
+
+fn handle(shared: &Shared, repo: &DriverRepo, request: &mut Request) -> Response<Cursor<Vec<u8>>> {
+    if !shared.is_authorized(request) {
+        eprintln!("{} {}: unauthorized", request.method(), request.url());
+        let e = Error::from(ErrorKind::Http(401, "missing or invalid token".into()));
+        return json_response(401, &json!(ErrorResponse::from(&e)));
+    }
+
+    let mut body = String::new();
+    let result = request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(Error::from)
+        .and_then(|_| VmUri::parse(&format!("http://vmctrl{}", request.url())))
+        .and_then(|url| match (request.method(), url.path()) {
+            // routes answering with something else than JSON
+            (&Method::Get, "/v1/machine/screenshot") => {
+                with_machine(shared, repo, &url, |m| m.screenshot(param(&url, "path")?))
+                    .map(|image| data_response("image/png", image))
+            }
+            (&Method::Get, "/v1/machine/serial/output") => {
+                let offset = match url.query_param("offset") {
+                    Some(offset) => offset
+                        .parse()
+                        .chain_err(|| ErrorKind::Http(400, "invalid 'offset' parameter".into()))?,
+                    None => 0,
+                };
+                with_machine(shared, repo, &url, |m| {
+                    m.read_serial(serial_port(&url)?, offset)
+                })
+                .map(|output| data_response("application/octet-stream", output))
+            }
+            (method, _) => {
+                route(shared, repo, method, &url, &body).map(|value| json_response(200, &value))
+            }
+        });
+
+    match result {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("{} {}: {}", request.method(), request.url(), e);
+            json_response(status_for(&e), &json!(ErrorResponse::from(&e)))
+        }
+    }
+}
+
+/// Every worker owns its driver repo, machine locks are shared.
+fn worker(shared: Arc<Shared>) {
+    let repo = match shared.inventory {
+        Some(ref inventory) => DriverRepo::from_config(inventory.clone()),
+        None => vmctrl::driver_repo(),
+    };
+
+    for mut request in shared.server.incoming_requests() {
+        let response = handle(&shared, &repo, &mut request);
+        if let Err(e) = request.respond(response) {
+            eprintln!("sending response: {}", e);
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let default_listen = format!("127.0.0.1:{}", DEFAULT_PORT);
+    let matches = App::new("vmctrl-server")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Exposes vmctrl machines over HTTP/JSON")
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .short("l")
+                .takes_value(true)
+                .default_value(&default_listen)
+                .help("address to listen on"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("c")
+                .takes_value(true)
+                .help("inventory file"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .takes_value(true)
+                .default_value("4")
+                .help("number of worker threads"),
+        )
+        .arg(
+            Arg::with_name("token-file")
+                .long("token-file")
+                .takes_value(true)
+                .help("file holding token clients must send (default: $VMCTRL_TOKEN)"),
+        )
+        .arg(
+            Arg::with_name("command-proxy")
+                .long("command-proxy")
+                .help("let http+ clients run allowed vmrun/vboxmanage subcommands"),
+        )
+        .arg(
+            Arg::with_name("allow-command")
+                .long("allow-command")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("command-proxy")
+                .help("subcommand http+ clients may run, e.g. 'vmrun start' (replaces defaults)"),
+        )
+        .get_matches();
+
+    let inventory = match matches.value_of("config") {
+        Some(path) => {
+            Some(Inventory::load(path).chain_err(|| format!("loading inventory {}", path))?)
+        }
+        None => None,
+    };
+    let token = match matches.value_of("token-file") {
+        Some(path) => fs::read_to_string(path)
+            .chain_err(|| format!("reading token {}", path))?
+            .trim()
+            .to_string(),
+        None => env::var(TOKEN_VAR).unwrap_or_default(),
+    };
+    if token.is_empty() {
+        bail!("no token, set {} or pass --token-file", TOKEN_VAR);
+    }
+    let command_rules = if matches.is_present("command-proxy") {
+        let rules: Result<Vec<_>> = match matches.values_of("allow-command") {
+            Some(values) => values.map(CommandRule::parse).collect(),
+            None => DEFAULT_COMMAND_RULES
+                .iter()
+                .map(|rule| CommandRule::parse(rule))
+                .collect(),
+        };
+        Some(rules?)
+    } else {
+        None
+    };
+    let threads: usize = matches
+        .value_of("threads")
+        .unwrap()
+        .parse()
+        .chain_err(|| "invalid --threads")?;
+    let listen = matches.value_of("listen").unwrap();
+    let server = match Server::http(listen) {
+        Ok(server) => server,
+        Err(e) => bail!("listening on {}: {}", listen, e),
+    };
+
+    let shared = Arc::new(Shared {
+        server,
+        inventory,
+        token,
+        command_rules,
+        locks: Mutex::new(HashMap::new()),
+    });
+
+    eprintln!("vmctrl-server listening on {}", listen);
+    let workers: Vec<_> = (0..threads.max(1))
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || worker(shared))
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        for cause in e.iter().skip(1) {
+            eprintln!("caused by: {}", cause);
+        }
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_command_target() {
+        let args = |s: &str| -> Vec<String> { s.split(' ').map(|s| s.to_string()).collect() };
+
+        assert_eq!(command_target(&args("list")), None);
+        assert_eq!(
+            command_target(&args("start /vms/x.vmx nogui")),
+            Some("/vms/x.vmx")
+        );
+        assert_eq!(
+            command_target(&args(
+                "-T ws -gu u -gp p runProgramInGuest /vms/x.vmx /bin/ls"
+            )),
+            Some("/vms/x.vmx")
+        );
+        assert_eq!(
+            command_target(&args("snapshot ubuntu-a take clean")),
+            Some("ubuntu-a")
+        );
+        assert_eq!(
+            command_target(&args("guestproperty get ubuntu-a /VirtualBox/GuestInfo")),
+            Some("ubuntu-a")
+        );
+    }
+
+    #[test]
+    fn test_command_rule() {
+        let args =
+            |s: &str| -> Vec<String> { s.split_whitespace().map(|s| s.to_string()).collect() };
+        let rule = |s: &str| CommandRule::parse(s).unwrap();
+
+        assert!(rule("vmrun start").matches("vmrun", &args("-T ws start /vms/x.vmx nogui")));
+        assert!(!rule("vmrun start").matches("vmrun", &args("deleteVM /vms/x.vmx")));
+        assert!(!rule("vmrun start").matches("/tmp/vmrun", &args("start /vms/x.vmx")));
+        assert!(rule("/usr/bin/vmrun start").matches("vmrun", &args("start /vms/x.vmx")));
+        assert!(rule("vmrun").matches("vmrun", &[]));
+        assert!(!rule("vmrun").matches("vmrun", &args("deleteVM /vms/x.vmx")));
+
+        let poweroff = rule("vboxmanage controlvm * poweroff");
+        assert!(poweroff.matches("vboxmanage", &args("controlvm ubuntu-a poweroff")));
+        assert!(!poweroff.matches("vboxmanage", &args("controlvm ubuntu-a screenshotpng /x")));
+        assert!(!poweroff.matches("vboxmanage", &args("--nologo modifyvm ubuntu-a")));
+    }
+}
@@ -0,0 +1,1121 @@
+#[macro_use]
+extern crate error_chain;
+extern crate clap;
+#[macro_use]
+extern crate serde_json;
+extern crate vmctrl;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::time::Duration;
+use vmctrl::appliance::{ExportFormat, ImportOptions};
+use vmctrl::config::MachineConfig;
+use vmctrl::disk::{DiskFormat, DiskOptions, DiskSlot};
+use vmctrl::error::*;
+use vmctrl::guest::Credentials;
+use vmctrl::inventory::Inventory;
+use vmctrl::keyboard::KeyCombo;
+use vmctrl::network::{NicConfig, PortForward};
+use vmctrl::serial::{self, SerialMode};
+use vmctrl::shared_folder::SharedFolder;
+use vmctrl::uri::DriverRepo;
+use vmctrl::{Driver, Machine};
+
+type MachinePtr = Box<dyn Machine>;
+
+fn app() -> App<'static, 'static> {
+    let uri = || {
+        Arg::with_name("URI")
+            .required(true)
+            .help("machine uri, e.g. vmware:/vms/x.vmx or name:build-win10")
+    };
+    let snapshot = || Arg::with_name("SNAPSHOT").required(true);
+    let host_uri = || {
+        Arg::with_name("URI")
+            .required(true)
+            .help("host uri, e.g. vmware: or ssh+virtualbox://host")
+    };
+    let disk_path = || {
+        Arg::with_name("PATH")
+            .required(true)
+            .help("disk image on hypervisor host")
+    };
+    let disk_slot = || {
+        Arg::with_name("SLOT")
+            .required(true)
+            .help("controller:port:device, e.g. SATA:1:0 or scsi0:1")
+    };
+    let size = || Arg::with_name("SIZE").required(true).help("size in MB");
+    let slot = || {
+        Arg::with_name("SLOT")
+            .required(true)
+            .help("adapter slot as numbered by hypervisor (nic1, ethernet0)")
+    };
+    let serial_port = || {
+        Arg::with_name("PORT")
+            .required(true)
+            .help("serial port as numbered by hypervisor (uart1, serial0)")
+    };
+    let property_name = || {
+        Arg::with_name("NAME")
+            .required(true)
+            .help("property, e.g. /job/id or for VMware guestVar:job-id")
+    };
+    let credentials = || {
+        vec![
+            Arg::with_name("user")
+                .short("u")
+                .long("user")
+                .takes_value(true)
+                .help("guest user, defaults to `gu` uri parameter"),
+            Arg::with_name("password")
+                .short("p")
+                .long("password")
+                .takes_value(true)
+                .help("guest password, defaults to inventory or $VMCTRL_GUEST_PASSWORD"),
+        ]
+    };
+
+    App::new("vmctrl")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Controls VMware and VirtualBox machines")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("c")
+                .takes_value(true)
+                .global(true)
+                .help("inventory file, defaults to $VMCTRL_CONFIG"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("print machine readable output"),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("lists running machines")
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .short("a")
+                        .help("include powered off machines"),
+                )
+                .arg(
+                    Arg::with_name("URI")
+                        .required(true)
+                        .help("host uri, e.g. vmware: or ssh+virtualbox://host"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("version")
+                .about("shows version of vmrun or VBoxManage on host")
+                .arg(
+                    Arg::with_name("URI")
+                        .required(true)
+                        .help("host uri, e.g. vmware: or ssh+virtualbox://host"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("shows machine state")
+                .arg(uri()),
+        )
+        .subcommand(
+            SubCommand::with_name("start")
+                .about("starts machine")
+                .arg(uri()),
+        )
+        .subcommand(
+            SubCommand::with_name("stop")
+                .about("powers machine off")
+                .arg(uri()),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("manages snapshots")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").arg(uri()))
+                .subcommand(SubCommand::with_name("create").arg(uri()).arg(snapshot()))
+                .subcommand(SubCommand::with_name("revert").arg(uri()).arg(snapshot()))
+                .subcommand(SubCommand::with_name("delete").arg(uri()).arg(snapshot())),
+        )
+        .subcommand(
+            SubCommand::with_name("clone")
+                .about("clones machine, prints uri of the clone")
+                .arg(
+                    Arg::with_name("snapshot")
+                        .long("snapshot")
+                        .short("s")
+                        .takes_value(true)
+                        .help("snapshot to clone from, required for linked clones"),
+                )
+                .arg(
+                    Arg::with_name("linked")
+                        .long("linked")
+                        .help("share disks with the source snapshot"),
+                )
+                .arg(uri())
+                .arg(
+                    Arg::with_name("TARGET")
+                        .required(true)
+                        .help(".vmx path (VMware) or machine name (VirtualBox)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("register")
+                .about("adds machine file to hypervisor inventory")
+                .arg(
+                    Arg::with_name("URI")
+                        .required(true)
+                        .help("machine file uri, e.g. virtualbox:/vms/a/a.vbox"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("unregister")
+                .about("removes machine from hypervisor inventory, keeps files")
+                .arg(uri()),
+        )
+        .subcommand(
+            SubCommand::with_name("delete")
+                .about("deletes powered off machine with its disks")
+                .arg(
+                    Arg::with_name("keep-disks")
+                        .long("keep-disks")
+                        .help("remove only machine configuration"),
+                )
+                .arg(uri()),
+        )
+        .subcommand(
+            SubCommand::with_name("configure")
+                .about("changes hardware of powered off machine")
+                .arg(Arg::with_name("cpus").long("cpus").takes_value(true))
+                .arg(
+                    Arg::with_name("memory")
+                        .long("memory")
+                        .takes_value(true)
+                        .help("memory size in MB"),
+                )
+                .arg(
+                    Arg::with_name("firmware")
+                        .long("firmware")
+                        .takes_value(true)
+                        .possible_values(&["bios", "efi"]),
+                )
+                .arg(
+                    Arg::with_name("boot")
+                        .long("boot")
+                        .takes_value(true)
+                        .help("comma separated boot order, e.g. disk,net (floppy, dvd, disk, net)"),
+                )
+                .arg(uri()),
+        )
+        .subcommand(
+            SubCommand::with_name("nic")
+                .about("changes network adapter")
+                .arg(
+                    Arg::with_name("attachment")
+                        .long("attachment")
+                        .takes_value(true)
+                        .possible_values(&["none", "nat", "natnetwork", "bridged", "hostonly", "internal"]),
+                )
+                .arg(
+                    Arg::with_name("network")
+                        .long("network")
+                        .takes_value(true)
+                        .help("bridged interface, host-only adapter or internal network name"),
+                )
+                .arg(Arg::with_name("mac").long("mac").takes_value(true))
+                .arg(
+                    Arg::with_name("cable")
+                        .long("cable")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"]),
+                )
+                .arg(uri())
+                .arg(slot()),
+        )
+        .subcommand(
+            SubCommand::with_name("forward")
+                .about("manages NAT port forwarding")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").arg(uri()).arg(slot()))
+                .subcommand(
+                    SubCommand::with_name("add").arg(uri()).arg(slot()).arg(
+                        Arg::with_name("RULE")
+                            .required(true)
+                            .help("name,protocol,host_ip,host_port,guest_ip,guest_port, e.g. ssh,tcp,,2222,,22"),
+                    ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .arg(uri())
+                        .arg(slot())
+                        .arg(Arg::with_name("NAME").required(true)),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("disk")
+                .about("manages virtual disks")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .arg(
+                            Arg::with_name("format")
+                                .long("format")
+                                .takes_value(true)
+                                .possible_values(&["vdi", "vmdk", "vhd"])
+                                .help("defaults to PATH extension"),
+                        )
+                        .arg(
+                            Arg::with_name("fixed")
+                                .long("fixed")
+                                .help("allocate whole size up front"),
+                        )
+                        .arg(host_uri())
+                        .arg(disk_path())
+                        .arg(size()),
+                )
+                .subcommand(
+                    SubCommand::with_name("resize")
+                        .arg(host_uri())
+                        .arg(disk_path())
+                        .arg(size()),
+                )
+                .subcommand(
+                    SubCommand::with_name("compact")
+                        .arg(host_uri())
+                        .arg(disk_path()),
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .arg(host_uri())
+                        .arg(disk_path()),
+                )
+                .subcommand(
+                    SubCommand::with_name("attach")
+                        .arg(uri())
+                        .arg(disk_slot())
+                        .arg(disk_path()),
+                )
+                .subcommand(SubCommand::with_name("detach").arg(uri()).arg(disk_slot())),
+        )
+        .subcommand(
+            SubCommand::with_name("share")
+                .about("manages folders shared with the guest")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").arg(uri()))
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .arg(Arg::with_name("read-only").long("read-only"))
+                        .arg(Arg::with_name("auto-mount").long("auto-mount"))
+                        .arg(
+                            Arg::with_name("mount-point")
+                                .long("mount-point")
+                                .takes_value(true)
+                                .help("guest directory, implies --auto-mount (VirtualBox)"),
+                        )
+                        .arg(
+                            Arg::with_name("transient")
+                                .long("transient")
+                                .help("share until power off, machine must be running (VirtualBox)"),
+                        )
+                        .arg(uri())
+                        .arg(Arg::with_name("NAME").required(true))
+                        .arg(Arg::with_name("HOST_PATH").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .arg(uri())
+                        .arg(Arg::with_name("NAME").required(true)),
+                )
+                .subcommand(SubCommand::with_name("enable").arg(uri()))
+                .subcommand(SubCommand::with_name("disable").arg(uri())),
+        )
+        .subcommand(
+            SubCommand::with_name("serial")
+                .about("manages serial ports and reads guest console output")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .arg(uri())
+                        .arg(serial_port()),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("connects port, machine must be powered off")
+                        .arg(uri())
+                        .arg(serial_port())
+                        .arg(
+                            Arg::with_name("MODE")
+                                .required(true)
+                                .help("off, disconnected, file:<host path> or pipe:<host path>"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("tail")
+                        .about("prints output of port connected to file")
+                        .arg(
+                            Arg::with_name("follow")
+                                .short("f")
+                                .long("follow")
+                                .help("keeps printing output as guest writes it"),
+                        )
+                        .arg(uri())
+                        .arg(serial_port()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("property")
+                .about("reads and writes guest properties (VMware guest variables)")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("get")
+                        .arg(uri())
+                        .arg(property_name()),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .arg(uri())
+                        .arg(property_name())
+                        .arg(Arg::with_name("VALUE").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .arg(uri())
+                        .arg(property_name()),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .arg(uri())
+                        .arg(Arg::with_name("PATTERN").default_value("*")),
+                )
+                .subcommand(
+                    SubCommand::with_name("wait")
+                        .about("waits until property matching PATTERN is set, prints it")
+                        .arg(
+                            Arg::with_name("timeout")
+                                .long("timeout")
+                                .takes_value(true)
+                                .default_value("60")
+                                .help("seconds to wait"),
+                        )
+                        .arg(uri())
+                        .arg(
+                            Arg::with_name("PATTERN")
+                                .required(true)
+                                .help("property name, * ? and | wildcards for VirtualBox"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("imports OVF/OVA appliance, prints uri of the new machine")
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .takes_value(true)
+                        .help("machine name, defaults to name from appliance"),
+                )
+                .arg(
+                    Arg::with_name("base-folder")
+                        .long("base-folder")
+                        .takes_value(true)
+                        .help("directory machine is created in, required for VMware"),
+                )
+                .arg(Arg::with_name("cpus").long("cpus").takes_value(true))
+                .arg(
+                    Arg::with_name("memory")
+                        .long("memory")
+                        .takes_value(true)
+                        .help("memory size in MB"),
+                )
+                .arg(Arg::with_name("accept-eulas").long("accept-eulas"))
+                .arg(
+                    Arg::with_name("URI")
+                        .required(true)
+                        .help("appliance file uri, e.g. virtualbox:/images/golden.ova"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("exports powered off machine, format follows PATH extension")
+                .arg(uri())
+                .arg(
+                    Arg::with_name("PATH")
+                        .required(true)
+                        .help(".ovf or .ova file on hypervisor host"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("screenshot")
+                .about("captures display of running machine as PNG")
+                .arg(uri())
+                .arg(
+                    Arg::with_name("PATH")
+                        .required(true)
+                        .help("temporary file on hypervisor host screen is captured to"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("local file image is copied to, defaults to stdout"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("type")
+                .about("types text on console of running machine, US layout")
+                .arg(uri())
+                .arg(Arg::with_name("TEXT").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("keys")
+                .about("presses key combos on console of running machine")
+                .arg(uri())
+                .arg(
+                    Arg::with_name("COMBO")
+                        .required(true)
+                        .multiple(true)
+                        .help("keys joined by +, e.g. ctrl+alt+del, enter or f12"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("exec")
+                .about("runs program inside guest")
+                .setting(AppSettings::TrailingVarArg)
+                .args(&credentials())
+                .arg(uri())
+                .arg(
+                    Arg::with_name("COMMAND")
+                        .required(true)
+                        .multiple(true)
+                        .help("program path followed by its arguments"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ps")
+                .about("lists processes running in guest")
+                .args(&credentials())
+                .arg(uri()),
+        )
+        .subcommand(
+            SubCommand::with_name("kill")
+                .about("kills process running in guest")
+                .args(&credentials())
+                .arg(uri())
+                .arg(Arg::with_name("PID").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("cp")
+                .about("copies file between hypervisor host and guest")
+                .args(&credentials())
+                .arg(
+                    Arg::with_name("from-guest")
+                        .long("from-guest")
+                        .help("copy SRC in guest to DST on host (default is host to guest)"),
+                )
+                .arg(uri())
+                .arg(Arg::with_name("SRC").required(true))
+                .arg(Arg::with_name("DST").required(true)),
+        )
+}
+
+struct Ctx {
+    repo: DriverRepo,
+    json: bool,
+}
+
+impl Ctx {
+    fn new(matches: &ArgMatches) -> Result<Self> {
+        let config = match matches.value_of("config") {
+            Some(path) => Some(path.to_string()),
+            None => env::var("VMCTRL_CONFIG").ok(),
+        };
+        let repo = match config {
+            Some(path) => DriverRepo::from_config(
+                Inventory::load(&path).chain_err(|| format!("loading inventory {}", path))?,
+            ),
+            None => vmctrl::driver_repo(),
+        };
+
+        Ok(Ctx {
+            repo,
+            json: matches.is_present("json"),
+        })
+    }
+
+    fn machine(&self, matches: &ArgMatches) -> Result<MachinePtr> {
+        self.repo.from_path(matches.value_of("URI").unwrap())
+    }
+
+    fn done(&self) {
+        if self.json {
+            println!("{}", json!({ "ok": true }))
+        }
+    }
+}
+
+fn credentials(machine: &MachinePtr, matches: &ArgMatches) -> Result<Credentials> {
+    let from_uri = machine.guest_credentials();
+    let user = match (matches.value_of("user"), from_uri.as_ref()) {
+        (Some(user), _) => user.to_string(),
+        (None, Some(c)) => c.user.clone(),
+        (None, None) => bail!("guest user required, use --user or `gu` uri parameter"),
+    };
+    let password = match (matches.value_of("password"), from_uri) {
+        (Some(password), _) => password.to_string(),
+        (None, Some(c)) => c.password,
+        (None, None) => String::new(),
+    };
+    Ok(Credentials::new(user, password))
+}
+
+fn slot(matches: &ArgMatches) -> Result<u32> {
+    matches
+        .value_of("SLOT")
+        .unwrap()
+        .parse()
+        .chain_err(|| "invalid SLOT")
+}
+
+fn serial_port(matches: &ArgMatches) -> Result<u32> {
+    matches
+        .value_of("PORT")
+        .unwrap()
+        .parse()
+        .chain_err(|| "invalid PORT")
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let ctx = Ctx::new(matches)?;
+
+    match matches.subcommand() {
+        ("list", Some(m)) => {
+            let uri = m.value_of("URI").unwrap();
+            let listing = if m.is_present("all") {
+                ctx.repo.list_all_at(uri)?
+            } else {
+                ctx.repo.list_running_at(uri)?
+            };
+            for e in &listing.skipped {
+                eprintln!("warning: skipped {}", e);
+            }
+            let machines = listing.into_machines()?;
+            if ctx.json {
+                let list: Vec<_> = machines
+                    .iter()
+                    .map(|m| json!({"name": m.name(), "uri": m.uri().to_string()}))
+                    .collect();
+                println!("{}", json!(list));
+            } else {
+                for m in machines {
+                    println!("{}", m.uri());
+                }
+            }
+        }
+        ("version", Some(m)) => {
+            let version = ctx.repo.version_at(m.value_of("URI").unwrap())?;
+            if ctx.json {
+                println!("{}", json!({ "version": version.to_string() }));
+            } else {
+                println!("{}", version);
+            }
+        }
+        ("status", Some(m)) => {
+            let machine = ctx.machine(m)?;
+            let state = machine.state()?;
+            if ctx.json {
+                println!(
+                    "{}",
+                    json!({
+                        "name": machine.name(),
+                        "uri": machine.uri().to_string(),
+                        "state": state.as_str(),
+                    })
+                );
+            } else {
+                println!("{}: {}", machine.name(), state);
+            }
+        }
+        ("start", Some(m)) => {
+            ctx.machine(m)?.start()?;
+            ctx.done();
+        }
+        ("stop", Some(m)) => {
+            ctx.machine(m)?.stop()?;
+            ctx.done();
+        }
+        ("snapshot", Some(m)) => match m.subcommand() {
+            ("list", Some(m)) => {
+                let snapshots = ctx.machine(m)?.list_snapshots()?;
+                if ctx.json {
+                    println!("{}", json!(snapshots));
+                } else {
+                    for s in snapshots {
+                        println!("{}", s);
+                    }
+                }
+            }
+            ("create", Some(m)) => {
+                ctx.machine(m)?
+                    .create_snapshot(m.value_of("SNAPSHOT").unwrap())?;
+                ctx.done();
+            }
+            ("revert", Some(m)) => {
+                ctx.machine(m)?.revert_to(m.value_of("SNAPSHOT").unwrap())?;
+                ctx.done();
+            }
+            ("delete", Some(m)) => {
+                ctx.machine(m)?
+                    .delete_snapshot(m.value_of("SNAPSHOT").unwrap())?;
+                ctx.done();
+            }
+            _ => unreachable!(),
+        },
+        ("clone", Some(m)) => {
+            let clone = ctx.machine(m)?.clone_to(
+                m.value_of("TARGET").unwrap(),
+                m.value_of("snapshot"),
+                m.is_present("linked"),
+            )?;
+            if ctx.json {
+                println!(
+                    "{}",
+                    json!({"name": clone.name(), "uri": clone.uri().to_string()})
+                );
+            } else {
+                println!("{}", clone.uri());
+            }
+        }
+        ("register", Some(m)) => {
+            let machine = ctx.repo.register_at(m.value_of("URI").unwrap())?;
+            if ctx.json {
+                println!(
+                    "{}",
+                    json!({"name": machine.name(), "uri": machine.uri().to_string()})
+                );
+            } else {
+                println!("{}", machine.uri());
+            }
+        }
+        ("unregister", Some(m)) => {
+            ctx.machine(m)?.unregister()?;
+            ctx.done();
+        }
+        ("delete", Some(m)) => {
+            ctx.machine(m)?.delete(!m.is_present("keep-disks"))?;
+            ctx.done();
+        }
+        ("configure", Some(m)) => {
+            let mut config = MachineConfig::new();
+            if let Some(cpus) = m.value_of("cpus") {
+                config = config.cpus(cpus.parse().chain_err(|| "invalid --cpus")?);
+            }
+            if let Some(memory) = m.value_of("memory") {
+                config = config.memory_mb(memory.parse().chain_err(|| "invalid --memory")?);
+            }
+            if let Some(firmware) = m.value_of("firmware") {
+                config = config.firmware(firmware.parse()?);
+            }
+            if let Some(boot) = m.value_of("boot") {
+                let devices = boot
+                    .split(',')
+                    .filter(|device| !device.is_empty())
+                    .map(|device| device.parse())
+                    .collect::<Result<Vec<_>>>()?;
+                config = config.boot_order(&devices);
+            }
+            ctx.machine(m)?.configure(&config)?;
+            ctx.done();
+        }
+        ("nic", Some(m)) => {
+            let mut config = NicConfig::new();
+            if let Some(attachment) = m.value_of("attachment") {
+                config = config.attachment(attachment.parse()?);
+            }
+            if let Some(network) = m.value_of("network") {
+                config = config.network(network);
+            }
+            if let Some(mac) = m.value_of("mac") {
+                config = config.mac(mac);
+            }
+            if let Some(cable) = m.value_of("cable") {
+                config = config.cable_connected(cable == "on");
+            }
+            ctx.machine(m)?.configure_nic(slot(m)?, &config)?;
+            ctx.done();
+        }
+        ("forward", Some(m)) => match m.subcommand() {
+            ("list", Some(m)) => {
+                let rules = ctx.machine(m)?.port_forwards(slot(m)?)?;
+                if ctx.json {
+                    let list: Vec<String> = rules.iter().map(|r| r.to_string()).collect();
+                    println!("{}", json!(list));
+                } else {
+                    for rule in rules {
+                        println!("{}", rule);
+                    }
+                }
+            }
+            ("add", Some(m)) => {
+                let rule: PortForward = m.value_of("RULE").unwrap().parse()?;
+                ctx.machine(m)?.add_port_forward(slot(m)?, &rule)?;
+                ctx.done();
+            }
+            ("remove", Some(m)) => {
+                ctx.machine(m)?
+                    .remove_port_forward(slot(m)?, m.value_of("NAME").unwrap())?;
+                ctx.done();
+            }
+            _ => unreachable!(),
+        },
+        ("disk", Some(m)) => match m.subcommand() {
+            ("attach", Some(m)) => {
+                let slot: DiskSlot = m.value_of("SLOT").unwrap().parse()?;
+                ctx.machine(m)?
+                    .attach_disk(&slot, m.value_of("PATH").unwrap())?;
+                ctx.done();
+            }
+            ("detach", Some(m)) => {
+                let slot: DiskSlot = m.value_of("SLOT").unwrap().parse()?;
+                ctx.machine(m)?.detach_disk(&slot)?;
+                ctx.done();
+            }
+            (command, Some(m)) => {
+                let store = ctx.repo.disk_store_at(m.value_of("URI").unwrap())?;
+                let path = m.value_of("PATH").unwrap();
+                let size = || -> Result<u64> {
+                    m.value_of("SIZE")
+                        .unwrap()
+                        .parse()
+                        .chain_err(|| "invalid SIZE")
+                };
+                match command {
+                    "create" => {
+                        let mut options = DiskOptions::new(size()?);
+                        let format = match m.value_of("format") {
+                            Some(format) => format.parse()?,
+                            None => DiskFormat::for_path(path).chain_err(|| {
+                                format!("unknown format of {}, use --format", path)
+                            })?,
+                        };
+                        options = options.format(format);
+                        if m.is_present("fixed") {
+                            options = options.fixed();
+                        }
+                        store.create(path, &options)?
+                    }
+                    "resize" => store.resize(path, size()?)?,
+                    "compact" => store.compact(path)?,
+                    "delete" => store.delete(path)?,
+                    _ => unreachable!(),
+                }
+                ctx.done();
+            }
+            _ => unreachable!(),
+        },
+        ("share", Some(m)) => match m.subcommand() {
+            ("list", Some(m)) => {
+                let folders = ctx.machine(m)?.shared_folders()?;
+                if ctx.json {
+                    let list: Vec<_> = folders
+                        .iter()
+                        .map(|f| {
+                            json!({
+                                "name": f.name,
+                                "host_path": f.host_path,
+                                "writable": f.writable,
+                                "auto_mount": f.auto_mount,
+                                "mount_point": f.mount_point,
+                                "transient": f.transient,
+                            })
+                        })
+                        .collect();
+                    println!("{}", json!(list));
+                } else {
+                    for f in folders {
+                        let mode = if f.writable { "rw" } else { "ro" };
+                        println!("{}\t{}\t{}", f.name, f.host_path, mode);
+                    }
+                }
+            }
+            ("add", Some(m)) => {
+                let mut folder = SharedFolder::new(
+                    m.value_of("NAME").unwrap(),
+                    m.value_of("HOST_PATH").unwrap(),
+                );
+                if m.is_present("read-only") {
+                    folder = folder.read_only();
+                }
+                if m.is_present("auto-mount") {
+                    folder = folder.auto_mount();
+                }
+                if let Some(mount_point) = m.value_of("mount-point") {
+                    folder = folder.mount_point(mount_point);
+                }
+                if m.is_present("transient") {
+                    folder = folder.transient();
+                }
+                ctx.machine(m)?.add_shared_folder(&folder)?;
+                ctx.done();
+            }
+            ("remove", Some(m)) => {
+                ctx.machine(m)?
+                    .remove_shared_folder(m.value_of("NAME").unwrap())?;
+                ctx.done();
+            }
+            ("enable", Some(m)) => {
+                ctx.machine(m)?.set_shared_folders_enabled(true)?;
+                ctx.done();
+            }
+            ("disable", Some(m)) => {
+                ctx.machine(m)?.set_shared_folders_enabled(false)?;
+                ctx.done();
+            }
+            _ => unreachable!(),
+        },
+        ("serial", Some(m)) => match m.subcommand() {
+            ("show", Some(m)) => {
+                let mode = ctx.machine(m)?.serial_mode(serial_port(m)?)?;
+                if ctx.json {
+                    println!("{}", json!({ "mode": mode.to_string() }));
+                } else {
+                    println!("{}", mode);
+                }
+            }
+            ("set", Some(m)) => {
+                let mode: SerialMode = m.value_of("MODE").unwrap().parse()?;
+                ctx.machine(m)?.configure_serial(serial_port(m)?, &mode)?;
+                ctx.done();
+            }
+            ("tail", Some(m)) => {
+                let machine = ctx.machine(m)?;
+                let port = serial_port(m)?;
+                let stdout = io::stdout();
+                let mut stdout = stdout.lock();
+                if !m.is_present("follow") {
+                    stdout.write_all(&machine.read_serial(port, 0)?)?;
+                    return Ok(());
+                }
+                let mut result = Ok(());
+                let _ = serial::follow(&*machine, port, 0, Duration::from_secs(1), &mut |chunk| {
+                    result = stdout.write_all(chunk).and_then(|_| stdout.flush());
+                    result.is_ok()
+                })?;
+                result?;
+            }
+            _ => unreachable!(),
+        },
+        ("property", Some(m)) => match m.subcommand() {
+            ("get", Some(m)) => {
+                let value = ctx
+                    .machine(m)?
+                    .guest_property(m.value_of("NAME").unwrap())?;
+                if ctx.json {
+                    println!("{}", json!({ "value": value }));
+                } else if let Some(value) = value {
+                    println!("{}", value);
+                }
+            }
+            ("set", Some(m)) => {
+                ctx.machine(m)?.set_guest_property(
+                    m.value_of("NAME").unwrap(),
+                    m.value_of("VALUE").unwrap(),
+                )?;
+                ctx.done();
+            }
+            ("delete", Some(m)) => {
+                ctx.machine(m)?
+                    .delete_guest_property(m.value_of("NAME").unwrap())?;
+                ctx.done();
+            }
+            ("list", Some(m)) => {
+                let props = ctx
+                    .machine(m)?
+                    .guest_properties(m.value_of("PATTERN").unwrap())?;
+                if ctx.json {
+                    let list: Vec<_> = props
+                        .iter()
+                        .map(|p| json!({"name": p.name, "value": p.value}))
+                        .collect();
+                    println!("{}", json!(list));
+                } else {
+                    for p in props {
+                        println!("{}\t{}", p.name, p.value);
+                    }
+                }
+            }
+            ("wait", Some(m)) => {
+                let timeout: u64 = m
+                    .value_of("timeout")
+                    .unwrap()
+                    .parse()
+                    .chain_err(|| "invalid --timeout")?;
+                let pattern = m.value_of("PATTERN").unwrap();
+                let prop = match ctx
+                    .machine(m)?
+                    .wait_for_property(pattern, Duration::from_secs(timeout))?
+                {
+                    Some(prop) => prop,
+                    None => bail!("no property matching '{}' set in {}s", pattern, timeout),
+                };
+                if ctx.json {
+                    println!("{}", json!({"name": prop.name, "value": prop.value}));
+                } else {
+                    println!("{}\t{}", prop.name, prop.value);
+                }
+            }
+            _ => unreachable!(),
+        },
+        ("import", Some(m)) => {
+            let mut options = ImportOptions::new();
+            if let Some(name) = m.value_of("name") {
+                options = options.name(name);
+            }
+            if let Some(base_folder) = m.value_of("base-folder") {
+                options = options.base_folder(base_folder);
+            }
+            if let Some(cpus) = m.value_of("cpus") {
+                options = options.cpus(cpus.parse().chain_err(|| "invalid --cpus")?);
+            }
+            if let Some(memory) = m.value_of("memory") {
+                options = options.memory_mb(memory.parse().chain_err(|| "invalid --memory")?);
+            }
+            if m.is_present("accept-eulas") {
+                options = options.accept_eulas();
+            }
+            if !ctx.json {
+                options = options.on_progress(|percent| {
+                    eprint!("\rimporting: {}%", percent);
+                    let _ = io::stderr().flush();
+                });
+            }
+
+            let machine = ctx
+                .repo
+                .import_appliance_at(m.value_of("URI").unwrap(), &options)?;
+            if ctx.json {
+                println!(
+                    "{}",
+                    json!({"name": machine.name(), "uri": machine.uri().to_string()})
+                );
+            } else {
+                eprintln!();
+                println!("{}", machine.uri());
+            }
+        }
+        ("export", Some(m)) => {
+            let path = m.value_of("PATH").unwrap();
+            let format = if ExportFormat::Ova.matches(path) {
+                ExportFormat::Ova
+            } else {
+                ExportFormat::Ovf
+            };
+            ctx.machine(m)?.export(path, format)?;
+            ctx.done();
+        }
+        ("screenshot", Some(m)) => {
+            let image = ctx.machine(m)?.screenshot(m.value_of("PATH").unwrap())?;
+            match m.value_of("output") {
+                Some(output) => {
+                    fs::write(output, image)?;
+                    ctx.done();
+                }
+                None => io::stdout().write_all(&image)?,
+            }
+        }
+        ("type", Some(m)) => {
+            ctx.machine(m)?.send_text(m.value_of("TEXT").unwrap())?;
+            ctx.done();
+        }
+        ("keys", Some(m)) => {
+            let combos = m
+                .values_of("COMBO")
+                .unwrap()
+                .map(|combo| combo.parse())
+                .collect::<Result<Vec<KeyCombo>>>()?;
+            let machine = ctx.machine(m)?;
+            for combo in &combos {
+                machine.send_keys(combo)?;
+            }
+            ctx.done();
+        }
+        ("exec", Some(m)) => {
+            let machine = ctx.machine(m)?;
+            let credentials = credentials(&machine, m)?;
+            let command: Vec<&str> = m.values_of("COMMAND").unwrap().collect();
+            let output: Vec<String> = machine
+                .guest_session(&credentials)?
+                .exec(command[0], &command[1..])?
+                .into_iter()
+                .collect();
+            if ctx.json {
+                println!("{}", json!({ "stdout": output }));
+            } else {
+                for line in output {
+                    println!("{}", line);
+                }
+            }
+        }
+        ("ps", Some(m)) => {
+            let machine = ctx.machine(m)?;
+            let credentials = credentials(&machine, m)?;
+            let processes = machine.guest_session(&credentials)?.list_processes()?;
+            if ctx.json {
+                let list: Vec<_> = processes
+                    .iter()
+                    .map(|p| json!({"pid": p.pid, "owner": p.owner, "command": p.command}))
+                    .collect();
+                println!("{}", json!(list));
+            } else {
+                for p in processes {
+                    println!("{}\t{}\t{}", p.pid, p.owner, p.command);
+                }
+            }
+        }
+        ("kill", Some(m)) => {
+            let pid = m
+                .value_of("PID")
+                .unwrap()
+                .parse()
+                .chain_err(|| "invalid PID")?;
+            let machine = ctx.machine(m)?;
+            let credentials = credentials(&machine, m)?;
+            machine.guest_session(&credentials)?.kill(pid)?;
+            ctx.done();
+        }
+        ("cp", Some(m)) => {
+            let machine = ctx.machine(m)?;
+            let credentials = credentials(&machine, m)?;
+            let session = machine.guest_session(&credentials)?;
+            let (src, dst) = (m.value_of("SRC").unwrap(), m.value_of("DST").unwrap());
+            if m.is_present("from-guest") {
+                session.copy_from_guest(src, dst)?;
+            } else {
+                session.copy_to_guest(src, dst)?;
+            }
+            ctx.done();
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn main() {
+    let matches = app().get_matches();
+
+    if let Err(e) = run(&matches) {
+        eprintln!("error: {}", e);
+        for cause in e.iter().skip(1) {
+            eprintln!("caused by: {}", cause);
+        }
+        process::exit(1);
+    }
+}
@@ -1,13 +1,22 @@
 use super::error::*;
+use super::uri::VmUri;
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::env;
 use std::ffi::{OsStr, OsString};
-use std::process::{Command, Stdio};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
 use std::str::{from_utf8, Utf8Error};
+use std::sync::mpsc;
+use std::thread;
 
 pub trait FromCommandRunner {
     type Command: CommandRunner;
     type Output;
 
+    #[allow(clippy::wrong_self_convention)]
     fn from_cmd(&self, cmd: Self::Command) -> Self::Output;
 }
 
@@ -17,16 +26,129 @@ pub trait CommandRunner {
         C: AsRef<OsStr>,
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>;
+
+    /// Same as `run_with_output`, but passes stdout and stderr chunks to `on_output`
+    /// as they arrive. Runners unable to stream report all output at the end.
+    fn run_with_progress<C, I, S>(
+        &self,
+        cmd: C,
+        args: I,
+        on_output: &mut dyn FnMut(&str),
+    ) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let lines: Vec<String> = self.run_with_output(cmd, args)?.into_iter().collect();
+        for line in &lines {
+            on_output(line);
+        }
+        Ok(Output::new(lines))
+    }
+
+    /// Same as `run_with_output`, but writes `input` to stdin of the command.
+    /// Secrets are passed this way, arguments are visible to every user of
+    /// the host in process listings.
+    fn run_with_input<C, I, S>(&self, cmd: C, _args: I, _input: &str) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        bail!(ErrorKind::NotSupported(format!(
+            "passing input to {}",
+            cmd.as_ref().to_string_lossy()
+        )))
+    }
+
+    /// Uri of the host commands are executed on (e.g. `ssh://user@host:22`),
+    /// `None` for local host.
+    fn transport(&self) -> Option<VmUri> {
+        None
+    }
+
+    /// Whether the runner maps tool names to binaries itself, so `Tool` sends
+    /// its usual name instead of searching well-known locations.
+    fn resolves_tools(&self) -> bool {
+        false
+    }
+
+    /// Reads text file on the host commands are executed on.
+    fn read_file(&self, path: &str) -> Result<String> {
+        Ok(self.run_with_output("cat", [path])?.into_text())
+    }
+
+    /// Reads binary file on the host commands are executed on. Output of
+    /// runners is text, so by default contents are transferred hex encoded by `od`.
+    fn read_bytes(&self, path: &str) -> Result<Vec<u8>> {
+        let output = self.run_with_output("od", ["-An", "-v", "-tx1", path])?;
+        od_parse(output)
+    }
+
+    /// Same as `read_bytes`, but skips first `offset` bytes of the file.
+    fn read_bytes_from(&self, path: &str, offset: u64) -> Result<Vec<u8>> {
+        let offset = offset.to_string();
+        let output = self.run_with_output("od", ["-An", "-v", "-tx1", "-j", &offset, path])?;
+        od_parse(output)
+    }
+
+    /// Replaces text file on the host commands are executed on. Contents go
+    /// to `path.tmp` renamed over `path`, so readers never see partial file.
+    /// Contents are sent on stdin, argv is limited in size and readable by
+    /// everyone on the host.
+    fn write_file(&self, path: &str, contents: &str) -> Result<()> {
+        let _ = self.run_with_input(
+            "sh",
+            [
+                "-c",
+                "if [ -e \"$1\" ]; then cp -p \"$1\" \"$1.tmp\"; fi; \
+                 cat > \"$1.tmp\" && mv -f \"$1.tmp\" \"$1\"",
+                "sh",
+                path,
+            ],
+            contents,
+        )?;
+        Ok(())
+    }
+
+    /// Files ending with `.extension` in any case, at most `depth` directories
+    /// below `dir` on the host commands are executed on. Uses `find`, which
+    /// Windows hosts lack.
+    fn find_files(&self, dir: &str, depth: usize, extension: &str) -> Result<Vec<String>> {
+        let depth = depth.to_string();
+        let pattern = format!("*.{}", extension);
+        let output =
+            self.run_with_output("find", [dir, "-maxdepth", &depth, "-iname", &pattern])?;
+        Ok(output.into_iter().collect())
+    }
+
+    /// Whether file exists on the host commands are executed on.
+    fn file_exists(&self, path: &str) -> Result<bool> {
+        match self.run_with_output("test", ["-e", path]) {
+            Ok(_) => Ok(true),
+            Err(Error(ErrorKind::Exec(1, _, _), _)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Removes file on the host commands are executed on, missing file is not an error.
+    fn remove_file(&self, path: &str) -> Result<()> {
+        let _ = self.run_with_output("rm", ["-f", path])?;
+        Ok(())
+    }
 }
 
 pub struct Output {
     inner: Vec<String>,
+    /// Whether output ended with newline, dropped from `inner`.
+    newline: bool,
 }
 
 impl Output {
-    fn new(mut v: Vec<String>) -> Self {
+    pub(crate) fn new(mut v: Vec<String>) -> Self {
         let empty_last = match v.last() {
-            Some(it) => it.len() == 0,
+            Some(it) => it.is_empty(),
             None => false,
         };
 
@@ -34,7 +156,24 @@ impl Output {
             let _ = v.pop();
         }
 
-        Output { inner: v }
+        Output {
+            newline: empty_last && !v.is_empty(),
+            inner: v,
+        }
+    }
+
+    /// Output as printed by the command, lines joined back with newlines.
+    pub(crate) fn into_text(self) -> String {
+        let mut text = self.inner.join("\n");
+        if self.newline {
+            text.push('\n');
+        }
+        text
+    }
+
+    #[cfg(feature = "vmware")]
+    pub(crate) fn lines(&self) -> &[String] {
+        &self.inner
     }
 }
 
@@ -48,6 +187,251 @@ impl IntoIterator for Output {
     }
 }
 
+/// Hypervisor tool (`vmrun`, `VBoxManage`) on target host, found on first run.
+///
+/// Explicit path is used as is. Otherwise the environment variable wins for
+/// local hosts, then the first of well-known locations the host can execute.
+/// Runners resolving tools themselves get the first candidate, the usual name.
+#[derive(Clone, Debug)]
+pub struct Tool {
+    env_var: Option<&'static str>,
+    candidates: Vec<Cow<'static, OsStr>>,
+    probe_args: &'static [&'static str],
+    resolved: RefCell<Option<Cow<'static, OsStr>>>,
+}
+
+impl Tool {
+    /// Tool found in `candidates`, bare command names are searched in `PATH`.
+    /// Candidates are tried by running them with harmless `probe_args`
+    /// (`--version`), never with the actual command.
+    pub fn discover(
+        env_var: &'static str,
+        candidates: &[&'static str],
+        probe_args: &'static [&'static str],
+    ) -> Self {
+        Tool {
+            env_var: Some(env_var),
+            candidates: candidates
+                .iter()
+                .map(|&candidate| Cow::Borrowed(OsStr::new(candidate)))
+                .collect(),
+            probe_args,
+            resolved: RefCell::new(None),
+        }
+    }
+
+    pub fn explicit<P: Into<OsString>>(path: P) -> Self {
+        let path: Cow<'static, OsStr> = Cow::Owned(path.into());
+        Tool {
+            env_var: None,
+            candidates: vec![path.clone()],
+            probe_args: &[],
+            resolved: RefCell::new(Some(path)),
+        }
+    }
+
+    pub fn run_with_output<C, I, S>(&self, runner: &C, args: I) -> Result<Output>
+    where
+        C: CommandRunner,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let args: Vec<OsString> = args.into_iter().map(|a| a.as_ref().into()).collect();
+        self.run(runner, |path| runner.run_with_output(path, &args))
+    }
+
+    pub fn run_with_input<C, I, S>(&self, runner: &C, args: I, input: &str) -> Result<Output>
+    where
+        C: CommandRunner,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let args: Vec<OsString> = args.into_iter().map(|a| a.as_ref().into()).collect();
+        self.run(runner, |path| runner.run_with_input(path, &args, input))
+    }
+
+    pub fn run_with_progress<C, I, S>(
+        &self,
+        runner: &C,
+        args: I,
+        on_output: &mut dyn FnMut(&str),
+    ) -> Result<Output>
+    where
+        C: CommandRunner,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let args: Vec<OsString> = args.into_iter().map(|a| a.as_ref().into()).collect();
+        self.run(runner, |path| {
+            runner.run_with_progress(path, &args, &mut *on_output)
+        })
+    }
+
+    /// Runs `f` with resolved path.
+    fn run<C, F>(&self, runner: &C, f: F) -> Result<Output>
+    where
+        C: CommandRunner,
+        F: FnOnce(&OsStr) -> Result<Output>,
+    {
+        let resolved = self.resolved.borrow().clone();
+        let path = match resolved {
+            Some(path) => path,
+            None => {
+                let path = self.resolve(runner)?;
+                *self.resolved.borrow_mut() = Some(path.clone());
+                path
+            }
+        };
+        f(&path)
+    }
+
+    /// First candidate which exists on runner's host, failing probe with
+    /// anything else than "not found" still counts.
+    fn resolve<C: CommandRunner>(&self, runner: &C) -> Result<Cow<'static, OsStr>> {
+        if runner.resolves_tools() {
+            if let Some(name) = self.candidates.first() {
+                return Ok(name.clone());
+            }
+        }
+        let overridden = match self.env_var {
+            Some(var) if runner.transport().is_none() => env::var_os(var),
+            _ => None,
+        };
+        if let Some(path) = overridden {
+            return Ok(Cow::Owned(path));
+        }
+
+        let mut missing = None;
+        for candidate in &self.candidates {
+            match runner.run_with_output(candidate, self.probe_args) {
+                Err(e) if is_not_found(&e) => {
+                    // report missing tool under its usual name.
+                    missing = missing.or(Some(e));
+                }
+                _ => return Ok(candidate.clone()),
+            }
+        }
+        Err(missing.unwrap_or_else(|| "no candidate path for tool".into()))
+    }
+}
+
+/// Whether command failed because the program does not exist, locally or in
+/// remote shell.
+fn is_not_found(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::Io(ref e) => e.kind() == io::ErrorKind::NotFound,
+        ErrorKind::Exec(127, _, _) => true,
+        _ => false,
+    }
+}
+
+/// Bytes of `od -An -tx1` hex dump.
+fn od_parse(output: Output) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for line in output {
+        for byte in line.split_whitespace() {
+            bytes.push(
+                u8::from_str_radix(byte, 16)
+                    .chain_err(|| ErrorKind::InvalidResponse(line.clone()))?,
+            );
+        }
+    }
+    Ok(bytes)
+}
+
+fn into_output(status: ExitStatus, stdout: Vec<u8>, stderr: Vec<u8>) -> Result<Output> {
+    if status.success() {
+        let ret: ::std::result::Result<Vec<String>, Utf8Error> = stdout
+            .split(|t| *t == b'\n')
+            .map(|it| from_utf8(it).map(|it| it.to_string()))
+            .collect();
+
+        return Ok(Output::new(ret?));
+    }
+    bail!(ErrorKind::Exec(
+        status.code().unwrap_or(0i32),
+        stderr.into(),
+        stdout.into()
+    ))
+}
+
+/// Runs `command` with `input` as its stdin.
+fn input_output(command: &mut Command, input: &str) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // stdin is closed when dropped, after the whole input is written.
+    let written = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output()?;
+    // command exiting without reading its input is reported by its status.
+    if output.status.success() {
+        written?;
+    }
+    into_output(output.status, output.stdout, output.stderr)
+}
+
+/// `Local::find_files` walking the tree itself, unreadable subdirectories
+/// are skipped.
+fn find_local(dir: &Path, depth: usize, extension: &str, found: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if depth > 1 {
+                let _ = find_local(&path, depth - 1, extension, found);
+            }
+        } else if let Some(ext) = path.extension() {
+            if ext.to_string_lossy().eq_ignore_ascii_case(extension) {
+                found.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs `command` forwarding output chunks from both streams to `on_output`.
+fn stream_output(command: &mut Command, on_output: &mut dyn FnMut(&str)) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let (tx, rx) = mpsc::channel();
+    let spawn_reader = |is_stdout: bool, mut stream: Box<dyn Read + Send>| {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 || tx.send((is_stdout, buf[..n].to_vec())).is_err() {
+                    break;
+                }
+            }
+        })
+    };
+    let readers = vec![
+        spawn_reader(true, Box::new(child.stdout.take().unwrap())),
+        spawn_reader(false, Box::new(child.stderr.take().unwrap())),
+    ];
+    drop(tx);
+
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    for (is_stdout, chunk) in rx {
+        on_output(&String::from_utf8_lossy(&chunk));
+        if is_stdout {
+            stdout.extend(chunk)
+        } else {
+            stderr.extend(chunk)
+        }
+    }
+    for reader in readers {
+        let _ = reader.join();
+    }
+
+    into_output(child.wait()?, stdout, stderr)
+}
+
 struct Local;
 
 impl CommandRunner for Local {
@@ -58,26 +442,98 @@ impl CommandRunner for Local {
         S: AsRef<OsStr>,
     {
         let output = Command::new(cmd).args(args).stdin(Stdio::null()).output()?;
-        let status = output.status;
-        if status.success() {
-            let ret: ::std::result::Result<Vec<String>, Utf8Error> = output
-                .stdout
-                .split(|t| *t == '\n' as u8)
-                .map(|it| from_utf8(it).map(|it| it.to_string()))
-                .collect();
+        into_output(output.status, output.stdout, output.stderr)
+    }
+
+    fn run_with_input<C, I, S>(&self, cmd: C, args: I, input: &str) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        input_output(Command::new(cmd).args(args), input)
+    }
+
+    fn run_with_progress<C, I, S>(
+        &self,
+        cmd: C,
+        args: I,
+        on_output: &mut dyn FnMut(&str),
+    ) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        stream_output(Command::new(cmd).args(args), on_output)
+    }
+
+    fn read_file(&self, path: &str) -> Result<String> {
+        Ok(fs::read_to_string(path)?)
+    }
+
+    fn read_bytes(&self, path: &str) -> Result<Vec<u8>> {
+        Ok(fs::read(path)?)
+    }
 
-            return Ok(Output::new(ret?));
+    fn read_bytes_from(&self, path: &str, offset: u64) -> Result<Vec<u8>> {
+        let mut file = fs::File::open(path)?;
+        let _ = file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::new();
+        let _ = file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn write_file(&self, path: &str, contents: &str) -> Result<()> {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, contents)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&tmp_path, metadata.permissions())?;
+        }
+        Ok(fs::rename(&tmp_path, path)?)
+    }
+
+    fn find_files(&self, dir: &str, depth: usize, extension: &str) -> Result<Vec<String>> {
+        let mut found = Vec::new();
+        find_local(Path::new(dir), depth, extension, &mut found)?;
+        Ok(found)
+    }
+
+    fn file_exists(&self, path: &str) -> Result<bool> {
+        Ok(Path::new(path).exists())
+    }
+
+    fn remove_file(&self, path: &str) -> Result<()> {
+        match fs::remove_file(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            other => Ok(other?),
         }
-        bail!(ErrorKind::Exec(
-            status.code().unwrap_or(0i32),
-            output.stderr.into(),
-            output.stdout.into()
-        ))
     }
 }
 
 pub struct Ssh {
     host: String,
+    user: Option<String>,
+    port: Option<u16>,
+    options: Vec<String>,
+}
+
+impl Ssh {
+    pub fn user<T: Into<String>>(mut self, user: T) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Extra argument passed to `ssh` before destination, e.g. `-i` or `-oProxyJump=gw`.
+    pub fn option<T: Into<String>>(mut self, option: T) -> Self {
+        self.options.push(option.into());
+        self
+    }
 }
 
 fn escape_shell_chars<'a>(s: &'a OsStr) -> Cow<'a, OsStr> {
@@ -103,8 +559,8 @@ fn escape_shell_chars<'a>(s: &'a OsStr) -> Cow<'a, OsStr> {
     Cow::Owned(result.into())
 }
 
-impl CommandRunner for Ssh {
-    fn run_with_output<C, I, S>(&self, cmd: C, args: I) -> Result<Output>
+impl Ssh {
+    fn command<C, I, S>(&self, cmd: C, args: I) -> Command
     where
         C: AsRef<OsStr>,
         I: IntoIterator<Item = S>,
@@ -116,25 +572,82 @@ impl CommandRunner for Ssh {
             shell_command.push(" ");
             shell_command.push(escape_shell_chars(arg.as_ref()))
         }
-        let host: &OsStr = self.host.as_ref();
-        let output = Command::new("ssh")
-            .args(&["-oBatchMode=yes".as_ref(), host, shell_command.as_ref()])
+        let mut command = Command::new("ssh");
+        command.arg("-oBatchMode=yes").args(&self.options);
+        if let Some(ref user) = self.user {
+            command.arg("-l").arg(user);
+        }
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        command.arg(&self.host).arg(shell_command);
+        command
+    }
+}
+
+impl CommandRunner for Ssh {
+    fn run_with_output<C, I, S>(&self, cmd: C, args: I) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self.command(cmd, args).stdin(Stdio::null()).output()?;
+        into_output(output.status, output.stdout, output.stderr)
+    }
+
+    /// Remote command gets `input` through stdin of `ssh`.
+    fn run_with_input<C, I, S>(&self, cmd: C, args: I, input: &str) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        input_output(&mut self.command(cmd, args), input)
+    }
+
+    fn run_with_progress<C, I, S>(
+        &self,
+        cmd: C,
+        args: I,
+        on_output: &mut dyn FnMut(&str),
+    ) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        stream_output(&mut self.command(cmd, args), on_output)
+    }
+
+    /// Copies file over ssh as is, without hex encoding.
+    fn read_bytes(&self, path: &str) -> Result<Vec<u8>> {
+        self.read_bytes_from(path, 0)
+    }
+
+    fn read_bytes_from(&self, path: &str, offset: u64) -> Result<Vec<u8>> {
+        let start = format!("+{}", offset + 1);
+        let output = self
+            .command("tail", ["-c", &start, path])
             .stdin(Stdio::null())
             .output()?;
-        let status = output.status;
-        if status.success() {
-            let ret: ::std::result::Result<Vec<String>, Utf8Error> = output
-                .stdout
-                .split(|t| *t == '\n' as u8)
-                .map(|it| from_utf8(it).map(|it| it.to_string()))
-                .collect();
-            return Ok(Output::new(ret?));
+        if !output.status.success() {
+            bail!(ErrorKind::Exec(
+                output.status.code().unwrap_or(0i32),
+                output.stderr.into(),
+                Vec::new().into()
+            ))
         }
-        bail!(ErrorKind::Exec(
-            status.code().unwrap_or(0i32),
-            output.stderr.into(),
-            output.stdout.into()
-        ))
+        Ok(output.stdout)
+    }
+
+    fn transport(&self) -> Option<VmUri> {
+        let (user, host) = match (self.user.as_ref(), self.host.rfind('@')) {
+            (Some(user), _) => (Some(user.as_str()), self.host.as_str()),
+            (None, Some(pos)) => (Some(&self.host[..pos]), &self.host[pos + 1..]),
+            (None, None) => (None, self.host.as_str()),
+        };
+        Some(VmUri::new("ssh", "").with_authority(user, host, self.port))
     }
 }
 
@@ -143,7 +656,12 @@ pub fn local() -> impl CommandRunner {
 }
 
 pub fn ssh<T: Into<String>>(host: T) -> Ssh {
-    Ssh { host: host.into() }
+    Ssh {
+        host: host.into(),
+        user: None,
+        port: None,
+        options: Vec::new(),
+    }
 }
 
 #[cfg(test)]
@@ -151,6 +669,178 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_run_with_input() {
+        let output: Vec<String> = local()
+            .run_with_input("sh", ["-c", "read -r p; echo \"got $p\""], "s3cret")
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        assert_eq!(output, vec!["got s3cret"]);
+    }
+
+    #[test]
+    fn test_run_with_progress() {
+        let mut chunks = String::new();
+        let output: Vec<String> = local()
+            .run_with_progress(
+                "sh",
+                ["-c", "echo 0%...50%... >&2; echo done"],
+                &mut |chunk| chunks.push_str(chunk),
+            )
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        assert_eq!(output, vec!["done"]);
+        assert!(chunks.contains("50%"));
+    }
+
+    #[test]
+    fn test_tool_discover() {
+        let tool = Tool::discover(
+            "VMCTRL_TEST_TOOL",
+            &["vmctrl-missing-tool", "echo"],
+            &["--version"],
+        );
+        let output = tool.run_with_output(&Local, ["hi"]).unwrap();
+
+        assert_eq!(output.into_iter().collect::<Vec<_>>(), vec!["hi"]);
+        assert_eq!(tool.resolved.borrow().as_deref(), Some(OsStr::new("echo")));
+        assert!(
+            Tool::discover("VMCTRL_TEST_TOOL", &["vmctrl-missing-tool"], &[])
+                .run_with_output(&Local, ["hi"])
+                .is_err()
+        );
+    }
+
+    /// Records commands, resolving tools like `vmctrl-server` does.
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<String>>);
+
+    impl CommandRunner for Recorder {
+        fn run_with_output<C, I, S>(&self, cmd: C, args: I) -> Result<Output>
+        where
+            C: AsRef<OsStr>,
+            I: IntoIterator<Item = S>,
+            S: AsRef<OsStr>,
+        {
+            let mut line = cmd.as_ref().to_string_lossy().into_owned();
+            for arg in args {
+                line.push(' ');
+                line.push_str(&arg.as_ref().to_string_lossy());
+            }
+            self.0.borrow_mut().push(line);
+            Ok(Output::new(Vec::new()))
+        }
+
+        fn resolves_tools(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_tool_resolved_by_runner() {
+        let runner = Recorder::default();
+        let tool = Tool::discover("VMCTRL_TEST_TOOL", &["vboxmanage", "VBoxManage"], &[]);
+        let _ = tool.run_with_output(&runner, ["list", "vms"]).unwrap();
+
+        assert_eq!(*runner.0.borrow(), vec!["vboxmanage list vms"]);
+    }
+
+    #[test]
+    fn test_write_file() {
+        let path = env::temp_dir().join(format!("vmctrl-write-{}.vmx", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        Local.write_file(path, "a = \"1\"\n").unwrap();
+        Local.write_file(path, "a = \"2\"\n").unwrap();
+        assert_eq!(Local.read_file(path).unwrap(), "a = \"2\"\n");
+        assert!(!Local.file_exists(&format!("{}.tmp", path)).unwrap());
+        Local.remove_file(path).unwrap();
+        assert!(!Local.file_exists(path).unwrap());
+    }
+
+    /// Local commands without local file access, like on remote host.
+    struct Shell;
+
+    impl CommandRunner for Shell {
+        fn run_with_output<C, I, S>(&self, cmd: C, args: I) -> Result<Output>
+        where
+            C: AsRef<OsStr>,
+            I: IntoIterator<Item = S>,
+            S: AsRef<OsStr>,
+        {
+            Local.run_with_output(cmd, args)
+        }
+
+        fn run_with_input<C, I, S>(&self, cmd: C, args: I, input: &str) -> Result<Output>
+        where
+            C: AsRef<OsStr>,
+            I: IntoIterator<Item = S>,
+            S: AsRef<OsStr>,
+        {
+            Local.run_with_input(cmd, args, input)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_file_with_shell() {
+        let path = env::temp_dir().join(format!("vmctrl-shell-{}.vmx", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        Shell.write_file(path, "a = \"$1\"\n\n").unwrap();
+        assert_eq!(Shell.read_file(path).unwrap(), "a = \"$1\"\n\n");
+        Shell.write_file(path, "").unwrap();
+        assert_eq!(Shell.read_file(path).unwrap(), "");
+        Shell.write_file(path, "no newline").unwrap();
+        assert_eq!(Shell.read_file(path).unwrap(), "no newline");
+        assert!(!Shell.file_exists(&format!("{}.tmp", path)).unwrap());
+        Shell.remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_find_files() {
+        let dir = env::temp_dir().join(format!("vmctrl-find-{}", std::process::id()));
+        let nested = dir.join("Ubuntu.vmwarevm").join("disks").join("old");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.join("Win10.VMX"), "").unwrap();
+        fs::write(dir.join("Ubuntu.vmwarevm").join("Ubuntu.vmx"), "").unwrap();
+        fs::write(dir.join("Ubuntu.vmwarevm").join("Ubuntu.vmxf"), "").unwrap();
+        fs::write(nested.join("deep.vmx"), "").unwrap();
+        let dir = dir.to_str().unwrap();
+
+        let mut found = Local.find_files(dir, 2, "vmx").unwrap();
+        found.sort();
+        let expected = [
+            Path::new(dir).join("Ubuntu.vmwarevm").join("Ubuntu.vmx"),
+            Path::new(dir).join("Win10.VMX"),
+        ];
+        assert_eq!(
+            found,
+            expected
+                .iter()
+                .map(|path| path.to_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        #[cfg(unix)]
+        {
+            let mut by_shell = Shell.find_files(dir, 2, "vmx").unwrap();
+            by_shell.sort();
+            assert_eq!(by_shell, found);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_od_parse() {
+        let output = Output::new(vec![" 89 50 4e 47".into(), " 0d 0a".into(), "".into()]);
+        assert_eq!(od_parse(output).unwrap(), b"\x89PNG\r\n");
+        assert!(od_parse(Output::new(vec!["zz".into()])).is_err());
+    }
+
     #[test]
     fn test_escape_shell_chars() {
         let a1: &OsStr = "ala".as_ref();
@@ -158,5 +848,4 @@ mod tests {
         assert_eq!(escape_shell_chars(a1), a1);
         assert_eq!(escape_shell_chars("ala ma kota".as_ref()), a2)
     }
-
 }
@@ -0,0 +1,176 @@
+//! Hardware settings applied with `Machine::configure`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Firmware {
+    Bios,
+    Efi,
+}
+
+impl Firmware {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Firmware::Bios => "bios",
+            Firmware::Efi => "efi",
+        }
+    }
+}
+
+impl FromStr for Firmware {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "bios" => Ok(Firmware::Bios),
+            "efi" => Ok(Firmware::Efi),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown firmware '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Firmware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootDevice {
+    Floppy,
+    Dvd,
+    Disk,
+    Net,
+}
+
+impl BootDevice {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BootDevice::Floppy => "floppy",
+            BootDevice::Dvd => "dvd",
+            BootDevice::Disk => "disk",
+            BootDevice::Net => "net",
+        }
+    }
+}
+
+impl FromStr for BootDevice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "floppy" => Ok(BootDevice::Floppy),
+            "dvd" | "cdrom" => Ok(BootDevice::Dvd),
+            "disk" | "hdd" => Ok(BootDevice::Disk),
+            "net" | "ethernet" => Ok(BootDevice::Net),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown boot device '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for BootDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Most boot devices hypervisors accept.
+pub const MAX_BOOT_DEVICES: usize = 4;
+
+/// Changes to machine hardware, unset fields are left untouched.
+///
+/// ```
+/// use vmctrl::config::{BootDevice, Firmware, MachineConfig};
+///
+/// let config = MachineConfig::new()
+///     .cpus(4)
+///     .memory_mb(8192)
+///     .firmware(Firmware::Efi)
+///     .boot_order(&[BootDevice::Disk, BootDevice::Net]);
+/// assert!(config.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MachineConfig {
+    pub cpus: Option<u32>,
+    pub memory_mb: Option<u64>,
+    pub firmware: Option<Firmware>,
+    pub boot_order: Option<Vec<BootDevice>>,
+}
+
+impl MachineConfig {
+    pub fn new() -> Self {
+        MachineConfig::default()
+    }
+
+    pub fn cpus(mut self, cpus: u32) -> Self {
+        self.cpus = Some(cpus);
+        self
+    }
+
+    pub fn memory_mb(mut self, memory_mb: u64) -> Self {
+        self.memory_mb = Some(memory_mb);
+        self
+    }
+
+    pub fn firmware(mut self, firmware: Firmware) -> Self {
+        self.firmware = Some(firmware);
+        self
+    }
+
+    /// Devices tried in order, empty list disables booting from any device.
+    pub fn boot_order(mut self, devices: &[BootDevice]) -> Self {
+        self.boot_order = Some(devices.to_vec());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == MachineConfig::default()
+    }
+
+    /// Checks limits common to all hypervisors.
+    pub fn validate(&self) -> Result<()> {
+        if self.cpus == Some(0) {
+            bail!(ErrorKind::InvalidConfig("cpus must be at least 1".into()))
+        }
+        if self.memory_mb == Some(0) {
+            bail!(ErrorKind::InvalidConfig(
+                "memory must be at least 1 MB".into()
+            ))
+        }
+        if let Some(ref devices) = self.boot_order {
+            if devices.len() > MAX_BOOT_DEVICES {
+                bail!(ErrorKind::InvalidConfig(format!(
+                    "at most {} boot devices allowed",
+                    MAX_BOOT_DEVICES
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(MachineConfig::new().is_empty());
+        assert!(MachineConfig::new().cpus(0).validate().is_err());
+        assert!(MachineConfig::new()
+            .boot_order(&[BootDevice::Disk; 5])
+            .validate()
+            .is_err());
+        assert_eq!("HDD".parse::<BootDevice>().unwrap(), BootDevice::Disk);
+        assert!("usb".parse::<BootDevice>().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! Virtual disk images and their attachment to machines.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskFormat {
+    Vdi,
+    Vmdk,
+    Vhd,
+}
+
+impl DiskFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiskFormat::Vdi => "VDI",
+            DiskFormat::Vmdk => "VMDK",
+            DiskFormat::Vhd => "VHD",
+        }
+    }
+
+    /// Format matching extension of `path`.
+    pub fn for_path(path: &str) -> Option<Self> {
+        let ext = path.rsplit('.').next()?;
+        ext.parse().ok()
+    }
+}
+
+impl FromStr for DiskFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "vdi" => Ok(DiskFormat::Vdi),
+            "vmdk" => Ok(DiskFormat::Vmdk),
+            "vhd" => Ok(DiskFormat::Vhd),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown disk format '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for DiskFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// New disk settings.
+///
+/// ```
+/// use vmctrl::disk::{DiskFormat, DiskOptions};
+///
+/// let options = DiskOptions::new(20 * 1024).format(DiskFormat::Vmdk).fixed();
+/// assert!(options.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskOptions {
+    pub size_mb: u64,
+    pub format: DiskFormat,
+    /// Allocate whole size up front instead of growing on demand.
+    pub fixed: bool,
+}
+
+impl DiskOptions {
+    /// Dynamically allocated VDI disk of `size_mb`.
+    pub fn new(size_mb: u64) -> Self {
+        DiskOptions {
+            size_mb,
+            format: DiskFormat::Vdi,
+            fixed: false,
+        }
+    }
+
+    pub fn format(mut self, format: DiskFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn fixed(mut self) -> Self {
+        self.fixed = true;
+        self
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.size_mb == 0 {
+            bail!(ErrorKind::InvalidConfig(
+                "disk size must be at least 1 MB".into()
+            ))
+        }
+        Ok(())
+    }
+}
+
+/// Controller location disk is attached to, as in `info().storage`.
+///
+/// VirtualBox uses storage controller name with port and device, VMware
+/// controller such as `scsi0` with unit as port and device `0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskSlot {
+    pub controller: String,
+    pub port: u32,
+    pub device: u32,
+}
+
+impl DiskSlot {
+    pub fn new<T: Into<String>>(controller: T, port: u32, device: u32) -> Self {
+        DiskSlot {
+            controller: controller.into(),
+            port,
+            device,
+        }
+    }
+}
+
+/// Parses `controller:port:device`, device defaults to `0` (`scsi0:1`).
+impl FromStr for DiskSlot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ErrorKind::InvalidConfig(format!("invalid disk slot '{}'", s));
+        let parts: Vec<&str> = s.rsplitn(3, ':').collect();
+        let (controller, port, device) = match parts.as_slice() {
+            [device, port, controller] => (*controller, *port, *device),
+            [port, controller] => (*controller, *port, "0"),
+            _ => bail!(invalid()),
+        };
+        if controller.is_empty() {
+            bail!(invalid())
+        }
+        Ok(DiskSlot::new(
+            controller,
+            port.parse().chain_err(invalid)?,
+            device.parse().chain_err(invalid)?,
+        ))
+    }
+}
+
+impl fmt::Display for DiskSlot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.controller, self.port, self.device)
+    }
+}
+
+/// Disk images on hypervisor host, paths are host paths.
+pub trait DiskStore {
+    fn create(&self, path: &str, options: &DiskOptions) -> Result<()>;
+
+    /// Grows disk to `size_mb`, shrinking is not supported by hypervisors.
+    fn resize(&self, path: &str, size_mb: u64) -> Result<()>;
+
+    /// Releases unused space of dynamically allocated disk.
+    fn compact(&self, path: &str) -> Result<()>;
+
+    /// Removes disk image, it must not be attached to any machine.
+    fn delete(&self, path: &str) -> Result<()>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(DiskFormat::for_path("/vms/a.VMDK"), Some(DiskFormat::Vmdk));
+        assert_eq!(DiskFormat::for_path("/vms/a"), None);
+        assert!(DiskOptions::new(0).validate().is_err());
+    }
+
+    #[test]
+    fn test_slot() {
+        assert_eq!(
+            "SATA Controller:1:0".parse::<DiskSlot>().unwrap(),
+            DiskSlot::new("SATA Controller", 1, 0)
+        );
+        assert_eq!(
+            "scsi0:2".parse::<DiskSlot>().unwrap(),
+            DiskSlot::new("scsi0", 2, 0)
+        );
+        assert!("scsi0".parse::<DiskSlot>().is_err());
+        assert!(":1:0".parse::<DiskSlot>().is_err());
+    }
+}
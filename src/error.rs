@@ -5,12 +5,61 @@ error_chain! {
     foreign_links {
         Io(io::Error) #[doc = "Error during IO"];
         UTF8(str::Utf8Error);
+        Toml(::toml::de::Error) #[cfg(feature = "inventory")];
     }
 
 
     errors {
         InvalidResponse(line : String)
         MissingSummary
+        UnknownScheme(scheme : String) {
+            description("unknown uri scheme")
+            display("unknown uri scheme: '{}'", scheme)
+        }
+        InvalidUri(uri : String) {
+            description("malformed uri")
+            display("malformed uri: '{}'", uri)
+        }
+        UnknownMachine(name : String) {
+            description("machine not found in inventory")
+            display("machine '{}' not found in inventory", name)
+        }
+        Http(status : u16, message : String) {
+            description("vmctrl-server request failed")
+            display("vmctrl-server responded {}: {}", status, message)
+        }
+        UnknownHost(name : String) {
+            description("host not found in inventory")
+            display("host '{}' not found in inventory", name)
+        }
+        NotSupported(operation : String) {
+            description("operation not supported by driver")
+            display("operation not supported: {}", operation)
+        }
+        MachineRunning(name : String) {
+            description("operation requires powered off machine")
+            display("machine '{}' is running, power it off first", name)
+        }
+        MachineSuspended(name : String) {
+            description("operation requires machine without saved state")
+            display("machine '{}' is suspended, resume and power it off first", name)
+        }
+        InvalidConfig(reason : String) {
+            description("invalid machine configuration")
+            display("invalid machine configuration: {}", reason)
+        }
+        HasLinkedClones(name : String, clones : Vec<String>) {
+            description("machine has linked clones")
+            display("machine '{}' has linked clones: {}", name, clones.join(", "))
+        }
+        Hypervisor(message : String) {
+            description("hypervisor reported error")
+            display("hypervisor error: {}", message)
+        }
+        PoolExhausted {
+            description("no free machine in pool")
+            display("no free machine in pool")
+        }
         Exec(code : i32, stderr : ProcessOutput, stdout : ProcessOutput) {
             description("shell command exec failed")
             display("Error code {}", code)
@@ -22,6 +71,12 @@ pub struct ProcessOutput {
     c: Vec<u8>,
 }
 
+impl ProcessOutput {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.c
+    }
+}
+
 impl From<Vec<u8>> for ProcessOutput {
     fn from(src: Vec<u8>) -> Self {
         ProcessOutput { c: src }
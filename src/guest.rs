@@ -0,0 +1,78 @@
+//! Operations inside running guest, require guest additions / VMware tools.
+
+use super::command::Output;
+use super::error::*;
+use super::uri::VmUri;
+use std::env;
+
+/// Environment variable with guest password for machines whose uri only names
+/// the user, passwords are never part of uris.
+pub const PASSWORD_VAR: &str = "VMCTRL_GUEST_PASSWORD";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new<U: Into<String>, P: Into<String>>(user: U, password: P) -> Self {
+        Credentials {
+            user: user.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Reads `gu` uri parameter, password is taken from `VMCTRL_GUEST_PASSWORD`
+    /// and defaults to empty.
+    pub fn from_uri(uri: &VmUri) -> Option<Self> {
+        let user = uri.query_param("gu")?;
+        Some(Credentials::new(
+            user,
+            env::var(PASSWORD_VAR).unwrap_or_default(),
+        ))
+    }
+
+    /// `configured` credentials (from inventory) unless `uri` names another user.
+    pub fn resolve(configured: Option<&Credentials>, uri: &VmUri) -> Option<Self> {
+        match (configured, Credentials::from_uri(uri)) {
+            (Some(configured), Some(from_uri)) if from_uri.user != configured.user => {
+                Some(from_uri)
+            }
+            (Some(configured), _) => Some(configured.clone()),
+            (None, from_uri) => from_uri,
+        }
+    }
+}
+
+/// Process running inside guest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestProcess {
+    pub pid: u32,
+    /// User account, for VirtualBox the user of guest session that started it.
+    pub owner: String,
+    pub command: String,
+}
+
+/// Paths on the host side are paths on hypervisor host, which for `ssh+` schemes
+/// is the remote machine.
+pub trait GuestSession {
+    /// Runs program and waits for it to finish.
+    ///
+    /// Output is empty for backends that do not capture guest stdout (VMware).
+    fn exec(&self, program: &str, args: &[&str]) -> Result<Output>;
+
+    fn copy_to_guest(&self, host_path: &str, guest_path: &str) -> Result<()>;
+
+    fn copy_from_guest(&self, guest_path: &str, host_path: &str) -> Result<()>;
+
+    /// Processes running in guest. VirtualBox only lists processes started
+    /// through guest control, e.g. by `exec`.
+    fn list_processes(&self) -> Result<Vec<GuestProcess>> {
+        bail!(ErrorKind::NotSupported("listing guest processes".into()))
+    }
+
+    fn kill(&self, _pid: u32) -> Result<()> {
+        bail!(ErrorKind::NotSupported("killing guest processes".into()))
+    }
+}
@@ -0,0 +1,405 @@
+//! Client side of `vmctrl-server` protocol.
+//!
+//! `http+vmware://server:7741/vms/x.vmx` runs `vmrun` on the host where
+//! `vmctrl-server` listens, through its `/v1/command` endpoint. Requests are
+//! plain HTTP/1.0 with JSON bodies, one connection per command, authenticated
+//! by bearer token taken from `VMCTRL_TOKEN`.
+//!
+//! The server only runs allowlisted subcommands and has no file access, so
+//! operations editing machine files on the host (`.vmx` changes, screenshots,
+//! serial output) are not supported over `http+`; use the REST routes instead.
+
+use super::command::{CommandRunner, Output};
+use super::error::*;
+use super::uri::VmUri;
+use serde_json;
+use std::env;
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 7741;
+pub const COMMAND_PATH: &str = "/v1/command";
+/// Environment variable holding token shared by `vmctrl-server` and its clients.
+pub const TOKEN_VAR: &str = "VMCTRL_TOKEN";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandRequest {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Written to stdin of the command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandResponse {
+    pub stdout: Vec<String>,
+}
+
+/// Body of every non 2xx response.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+}
+
+impl<'a> From<&'a Error> for ErrorResponse {
+    fn from(e: &'a Error) -> Self {
+        let mut response = ErrorResponse {
+            error: e.to_string(),
+            ..ErrorResponse::default()
+        };
+        match *e.kind() {
+            ErrorKind::Exec(code, ref stderr, ref stdout) => {
+                response.exit_code = Some(code);
+                response.stdout = String::from_utf8_lossy(stdout.as_bytes()).into_owned();
+                response.stderr = String::from_utf8_lossy(stderr.as_bytes()).into_owned();
+            }
+            ErrorKind::Http(_, ref message) => response.error = message.clone(),
+            _ => (),
+        }
+        response
+    }
+}
+
+impl ErrorResponse {
+    fn into_error(self, status: u16) -> Error {
+        match self.exit_code {
+            Some(code) => ErrorKind::Exec(
+                code,
+                self.stderr.into_bytes().into(),
+                self.stdout.into_bytes().into(),
+            )
+            .into(),
+            None => ErrorKind::Http(status, self.error).into(),
+        }
+    }
+}
+
+pub struct Http {
+    host: String,
+    port: u16,
+    token: Option<String>,
+    timeout: Duration,
+}
+
+impl Http {
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Bearer token sent with every request, instead of `VMCTRL_TOKEN`.
+    pub fn token<T: Into<String>>(mut self, token: T) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Longest wait for server to accept or answer request, 30 minutes by default.
+    /// Commands outliving it (large imports) fail, but keep running on the server.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let mut last_error = None;
+        for addr in (self.host.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT.min(self.timeout)) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, self.host.clone()))
+            .into())
+    }
+
+    /// Sends single request, returns status code and response body.
+    pub fn request(&self, method: &str, path: &str, body: &[u8]) -> Result<(u16, Vec<u8>)> {
+        let mut stream = self.connect()?;
+        let authorization = match self.token {
+            Some(ref token) => format!("Authorization: Bearer {}\r\n", token),
+            None => String::new(),
+        };
+        write!(
+            stream,
+            "{} {} HTTP/1.0\r\nHost: {}:{}\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            self.host,
+            self.port,
+            authorization,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+
+        let invalid =
+            || ErrorKind::InvalidResponse(String::from_utf8_lossy(&response).into_owned());
+        let header_end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(invalid)?;
+        let status = str::from_utf8(&response[..header_end])?
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(invalid)?;
+
+        Ok((status, response[header_end + 4..].to_vec()))
+    }
+}
+
+impl Http {
+    fn command(&self, request: &CommandRequest) -> Result<Output> {
+        let body = serde_json::to_vec(request).chain_err(|| "encoding command")?;
+        let (status, body) = self.request("POST", COMMAND_PATH, &body)?;
+
+        if status / 100 == 2 {
+            let response: CommandResponse = serde_json::from_slice(&body)
+                .chain_err(|| ErrorKind::InvalidResponse(String::from_utf8_lossy(&body).into()))?;
+            Ok(Output::new(response.stdout))
+        } else {
+            match serde_json::from_slice::<ErrorResponse>(&body) {
+                Ok(response) => Err(response.into_error(status)),
+                Err(_) => bail!(ErrorKind::Http(
+                    status,
+                    String::from_utf8_lossy(&body).into()
+                )),
+            }
+        }
+    }
+}
+
+fn command_request<C, I, S>(cmd: C, args: I, stdin: Option<&str>) -> CommandRequest
+where
+    C: AsRef<OsStr>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    CommandRequest {
+        command: cmd.as_ref().to_string_lossy().into_owned(),
+        args: args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+            .collect(),
+        stdin: stdin.map(|input| input.to_string()),
+    }
+}
+
+impl CommandRunner for Http {
+    fn run_with_output<C, I, S>(&self, cmd: C, args: I) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command(&command_request(cmd, args, None))
+    }
+
+    fn run_with_input<C, I, S>(&self, cmd: C, args: I, input: &str) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command(&command_request(cmd, args, Some(input)))
+    }
+
+    fn transport(&self) -> Option<VmUri> {
+        Some(VmUri::new("http", "").with_authority(
+            None::<String>,
+            self.host.as_str(),
+            Some(self.port),
+        ))
+    }
+
+    /// `vmctrl-server` runs the binary its allowlist configures for a name.
+    fn resolves_tools(&self) -> bool {
+        true
+    }
+
+    fn read_file(&self, path: &str) -> Result<String> {
+        bail!(ErrorKind::NotSupported(format!(
+            "reading {} over http",
+            path
+        )))
+    }
+
+    fn read_bytes(&self, path: &str) -> Result<Vec<u8>> {
+        bail!(ErrorKind::NotSupported(format!(
+            "reading {} over http",
+            path
+        )))
+    }
+
+    fn read_bytes_from(&self, path: &str, _offset: u64) -> Result<Vec<u8>> {
+        bail!(ErrorKind::NotSupported(format!(
+            "reading {} over http",
+            path
+        )))
+    }
+
+    fn write_file(&self, path: &str, _contents: &str) -> Result<()> {
+        bail!(ErrorKind::NotSupported(format!(
+            "writing {} over http",
+            path
+        )))
+    }
+
+    fn file_exists(&self, path: &str) -> Result<bool> {
+        bail!(ErrorKind::NotSupported(format!(
+            "checking {} over http",
+            path
+        )))
+    }
+
+    fn remove_file(&self, path: &str) -> Result<()> {
+        bail!(ErrorKind::NotSupported(format!(
+            "removing {} over http",
+            path
+        )))
+    }
+}
+
+pub fn http<T: Into<String>>(host: T) -> Http {
+    Http {
+        host: host.into(),
+        port: DEFAULT_PORT,
+        token: env::var(TOKEN_VAR).ok().filter(|token| !token.is_empty()),
+        timeout: DEFAULT_TIMEOUT,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answers one request, returns its `Authorization` header and body.
+    fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> (u16, thread::JoinHandle<(Option<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            let mut authorization = None;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(len) = line.strip_prefix("Content-Length: ") {
+                    content_length = len.trim().parse().unwrap();
+                }
+                if let Some(value) = line.strip_prefix("Authorization: ") {
+                    authorization = Some(value.trim().to_string());
+                }
+            }
+            let mut request = vec![0; content_length];
+            reader.read_exact(&mut request).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.0 {}\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            (authorization, String::from_utf8(request).unwrap())
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn test_run_with_output() {
+        let (port, handle) = serve_once("200 OK", r#"{"stdout":["Total running VMs: 0"]}"#);
+
+        let output: Vec<String> = http("127.0.0.1")
+            .port(port)
+            .run_with_output("vmrun", ["list"])
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        assert_eq!(output, vec!["Total running VMs: 0"]);
+        assert_eq!(
+            handle.join().unwrap().1,
+            r#"{"command":"vmrun","args":["list"]}"#
+        );
+    }
+
+    #[test]
+    fn test_token() {
+        let (port, handle) = serve_once("200 OK", r#"{"stdout":[]}"#);
+
+        let _ = http("127.0.0.1")
+            .port(port)
+            .token("s3cret")
+            .run_with_output("vmrun", ["list"])
+            .unwrap();
+
+        assert_eq!(handle.join().unwrap().0.as_deref(), Some("Bearer s3cret"));
+    }
+
+    #[test]
+    fn test_file_access_not_supported() {
+        match http("127.0.0.1").read_file("/vms/x.vmx") {
+            Err(Error(ErrorKind::NotSupported(_), _)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("file read over http"),
+        }
+    }
+
+    #[test]
+    fn test_exec_error() {
+        let (port, _) = serve_once(
+            "500 Internal Server Error",
+            r#"{"error":"Error code 255","exit_code":255,"stdout":"Error: bad","stderr":""}"#,
+        );
+
+        match http("127.0.0.1")
+            .port(port)
+            .run_with_output("vmrun", ["start"])
+        {
+            Err(Error(ErrorKind::Exec(255, _, ref stdout), _)) => {
+                assert_eq!(stdout.as_bytes(), b"Error: bad")
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("error response accepted"),
+        }
+    }
+
+    #[test]
+    fn test_transport() {
+        let uri = http("vmhost").transport().unwrap();
+
+        assert_eq!(uri.to_string(), "http://vmhost:7741");
+    }
+}
@@ -0,0 +1,118 @@
+//! Machine configuration as reported by hypervisor.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::*;
+use super::MachineState;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineInfo {
+    pub name: String,
+    pub uuid: Option<String>,
+    pub os_type: Option<String>,
+    pub cpus: Option<u32>,
+    pub memory_mb: Option<u64>,
+    pub state: MachineState,
+    pub nics: Vec<NicInfo>,
+    pub storage: Vec<StorageController>,
+    pub snapshot_folder: Option<String>,
+    pub current_snapshot: Option<String>,
+}
+
+impl MachineInfo {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        MachineInfo {
+            name: name.into(),
+            uuid: None,
+            os_type: None,
+            cpus: None,
+            memory_mb: None,
+            state: MachineState::Unknown,
+            nics: Vec::new(),
+            storage: Vec::new(),
+            snapshot_folder: None,
+            current_snapshot: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NicAttachment {
+    None,
+    Nat,
+    NatNetwork,
+    Bridged,
+    HostOnly,
+    Internal,
+    Generic,
+    Other(String),
+}
+
+impl NicAttachment {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            NicAttachment::None => "none",
+            NicAttachment::Nat => "nat",
+            NicAttachment::NatNetwork => "natnetwork",
+            NicAttachment::Bridged => "bridged",
+            NicAttachment::HostOnly => "hostonly",
+            NicAttachment::Internal => "internal",
+            NicAttachment::Generic => "generic",
+            NicAttachment::Other(ref other) => other,
+        }
+    }
+}
+
+impl FromStr for NicAttachment {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(NicAttachment::None),
+            "nat" => Ok(NicAttachment::Nat),
+            "natnetwork" => Ok(NicAttachment::NatNetwork),
+            "bridged" => Ok(NicAttachment::Bridged),
+            "hostonly" => Ok(NicAttachment::HostOnly),
+            "internal" | "intnet" => Ok(NicAttachment::Internal),
+            "generic" => Ok(NicAttachment::Generic),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown attachment '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for NicAttachment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NicInfo {
+    /// Adapter slot as numbered by hypervisor (`nic1`, `ethernet0`).
+    pub slot: u32,
+    pub attachment: NicAttachment,
+    /// Bridged interface, host-only adapter or internal network name.
+    pub network: Option<String>,
+    pub mac: Option<String>,
+    pub cable_connected: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageController {
+    pub name: String,
+    pub controller_type: Option<String>,
+    pub attachments: Vec<MediumAttachment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediumAttachment {
+    pub port: u32,
+    pub device: u32,
+    /// Image path, `emptydrive` for removable drive without medium.
+    pub medium: String,
+    pub uuid: Option<String>,
+}
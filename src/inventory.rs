@@ -0,0 +1,411 @@
+//! Named hosts and machines loaded from TOML file.
+//!
+//! ```toml
+//! [hosts.macx]
+//! transport = "ssh"
+//! address = "macx.local"
+//! user = "prekucki"
+//! ssh_options = ["-i", "/home/ci/.ssh/vmhost"]
+//! type = "vmware"
+//! command = "/Applications/VMware Fusion.app/Contents/Library/vmrun"
+//!
+//! [machines.build-win10]
+//! host = "macx"
+//! path = "/Users/prekucki/vm/win10.vmwarevm/win10.vmx"
+//! snapshot = "clean"
+//! guest_user = "build"
+//! guest_password = "secret"
+//! ```
+//!
+//! `DriverRepo::from_config` resolves `name:build-win10` to the machine above and
+//! `host://macx/path/to/vm.vmx` to any machine on a named host.
+
+use super::appliance::ImportOptions;
+#[cfg(any(feature = "vmware", feature = "virtualbox"))]
+use super::command::FromCommandRunner;
+use super::command::{self, CommandRunner};
+use super::disk::DiskStore;
+use super::error::*;
+use super::guest::Credentials;
+use super::uri::{DriverFactory, DriverRepo, Listing, VmUri};
+use super::version::Version;
+use super::Machine;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct Inventory {
+    #[serde(default)]
+    pub hosts: HashMap<String, HostEntry>,
+    #[serde(default)]
+    pub machines: HashMap<String, MachineEntry>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Local,
+    Ssh,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostType {
+    Vmware,
+    Virtualbox,
+}
+
+impl HostType {
+    pub fn scheme(self) -> &'static str {
+        match self {
+            HostType::Vmware => "vmware",
+            HostType::Virtualbox => "virtualbox",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HostEntry {
+    #[serde(default)]
+    pub transport: Transport,
+    /// Ssh destination, defaults to host name.
+    pub address: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub ssh_options: Vec<String>,
+    #[serde(rename = "type")]
+    pub host_type: HostType,
+    /// Path to `vmrun` or `VBoxManage` on the host, well-known locations are
+    /// searched when unset.
+    pub command: Option<String>,
+    /// `vmrun -T` host type (`ws`, `fusion`, `player`, ...) of VMware hosts,
+    /// detected when unset.
+    pub vmrun_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MachineEntry {
+    pub host: String,
+    pub path: String,
+    pub snapshot: Option<String>,
+    pub guest_user: Option<String>,
+    pub guest_password: Option<String>,
+}
+
+impl MachineEntry {
+    /// Uri query carrying machine defaults (`snapshot`, `gu`), the password
+    /// stays out of uris which end up in listings and logs.
+    fn query(&self) -> Vec<(String, String)> {
+        let params = [("snapshot", &self.snapshot), ("gu", &self.guest_user)];
+        params
+            .iter()
+            .filter_map(|(k, v)| v.as_ref().map(|v| (k.to_string(), v.clone())))
+            .collect()
+    }
+
+    fn credentials(&self) -> Option<Credentials> {
+        let user = self.guest_user.as_ref()?;
+        Some(Credentials::new(
+            user.as_str(),
+            self.guest_password.clone().unwrap_or_default(),
+        ))
+    }
+}
+
+impl Inventory {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn host(&self, name: &str) -> Result<&HostEntry> {
+        self.hosts
+            .get(name)
+            .ok_or_else(|| ErrorKind::UnknownHost(name.into()).into())
+    }
+
+    pub fn machine(&self, name: &str) -> Result<&MachineEntry> {
+        self.machines
+            .get(name)
+            .ok_or_else(|| ErrorKind::UnknownMachine(name.into()).into())
+    }
+
+    /// Driver running commands on named host.
+    fn driver_for(&self, host_name: &str) -> Result<Box<dyn DriverFactory>> {
+        self.driver_with(host_name, None)
+    }
+
+    /// Same as `driver_for`, machines get `credentials` as guest account.
+    fn driver_with(
+        &self,
+        host_name: &str,
+        credentials: Option<Credentials>,
+    ) -> Result<Box<dyn DriverFactory>> {
+        let host = self.host(host_name)?;
+
+        match host.transport {
+            Transport::Local => driver_on(command::local(), host, credentials),
+            Transport::Ssh => {
+                let mut cmd = command::ssh(host.address.as_ref().map_or(host_name, |a| a.as_str()));
+                if let Some(ref user) = host.user {
+                    cmd = cmd.user(user.as_str());
+                }
+                if let Some(port) = host.port {
+                    cmd = cmd.port(port);
+                }
+                for option in &host.ssh_options {
+                    cmd = cmd.option(option.as_str());
+                }
+                driver_on(cmd, host, credentials)
+            }
+        }
+    }
+
+    fn open(
+        &self,
+        host_name: &str,
+        path: &str,
+        query: Vec<(String, String)>,
+        credentials: Option<Credentials>,
+    ) -> Result<Box<dyn Machine>> {
+        let uri = VmUri::new(self.host(host_name)?.host_type.scheme(), path).with_query(query);
+
+        self.driver_with(host_name, credentials)?
+            .machine_for_uri(&uri)
+    }
+}
+
+impl FromStr for Inventory {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(::toml::from_str(s)?)
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "vmware", feature = "virtualbox")),
+    allow(unused_variables)
+)]
+fn driver_on<C: CommandRunner + 'static>(
+    cmd: C,
+    host: &HostEntry,
+    credentials: Option<Credentials>,
+) -> Result<Box<dyn DriverFactory>> {
+    match host.host_type {
+        #[cfg(feature = "vmware")]
+        HostType::Vmware => {
+            let mut factory = super::vmware::factory();
+            if let Some(ref command) = host.command {
+                factory = factory.with_command(command.as_str());
+            }
+            if let Some(ref host_type) = host.vmrun_type {
+                factory = factory.with_host_type(host_type.parse()?);
+            }
+            if let Some(credentials) = credentials {
+                factory = factory.with_guest_credentials(credentials);
+            }
+            Ok(Box::new(factory.from_cmd(cmd)))
+        }
+        #[cfg(feature = "virtualbox")]
+        HostType::Virtualbox => {
+            let mut factory = super::virtual_box::factory();
+            if let Some(ref command) = host.command {
+                factory = factory.with_command(command.as_str());
+            }
+            if let Some(credentials) = credentials {
+                factory = factory.with_guest_credentials(credentials);
+            }
+            Ok(Box::new(factory.from_cmd(cmd)))
+        }
+        #[allow(unreachable_patterns)]
+        host_type => bail!(ErrorKind::UnknownScheme(host_type.scheme().into())),
+    }
+}
+
+/// Resolves `name:<machine>` uris.
+struct NameFactory(Rc<Inventory>);
+
+impl DriverFactory for NameFactory {
+    fn machine_for_uri(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        let entry = self.0.machine(uri.path())?;
+        let mut query = entry.query();
+        // explicit parameters override inventory defaults.
+        for (k, v) in uri.query() {
+            query.retain(|(qk, _)| qk != k);
+            query.push((k.clone(), v.clone()));
+        }
+        self.0
+            .open(&entry.host, &entry.path, query, entry.credentials())
+    }
+
+    fn list_running(&self, uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        bail!(ErrorKind::NotSupported(format!("listing {}", uri)))
+    }
+}
+
+/// Resolves `host://<host>/<path>` uris.
+struct HostFactory(Rc<Inventory>);
+
+impl DriverFactory for HostFactory {
+    fn machine_for_uri(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        let host = match uri.host() {
+            Some(host) if !uri.path().is_empty() => host,
+            _ => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        };
+        let path = match self.0.host(host)?.host_type {
+            HostType::Virtualbox => uri.path().trim_start_matches('/'),
+            HostType::Vmware => uri.path(),
+        };
+        self.0.open(host, path, uri.query().to_vec(), None)
+    }
+
+    fn list_running(&self, uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        match uri.host() {
+            Some(host) => self.0.driver_for(host)?.list_running(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
+    }
+
+    fn list_all(&self, uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        match uri.host() {
+            Some(host) => self.0.driver_for(host)?.list_all(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
+    }
+
+    fn register(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        let host = match uri.host() {
+            Some(host) if !uri.path().is_empty() => host,
+            _ => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        };
+        let file_uri = VmUri::new(self.0.host(host)?.host_type.scheme(), uri.path());
+        self.0.driver_for(host)?.register(&file_uri)
+    }
+
+    fn import_appliance(&self, uri: &VmUri, options: &ImportOptions) -> Result<Box<dyn Machine>> {
+        let host = match uri.host() {
+            Some(host) if !uri.path().is_empty() => host,
+            _ => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        };
+        let file_uri = VmUri::new(self.0.host(host)?.host_type.scheme(), uri.path());
+        self.0
+            .driver_for(host)?
+            .import_appliance(&file_uri, options)
+    }
+
+    fn version(&self, uri: &VmUri) -> Result<Version> {
+        match uri.host() {
+            Some(host) => self.0.driver_for(host)?.version(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
+    }
+
+    fn disk_store(&self, uri: &VmUri) -> Result<Box<dyn DiskStore>> {
+        match uri.host() {
+            Some(host) => self.0.driver_for(host)?.disk_store(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
+    }
+}
+
+impl DriverRepo {
+    /// Repo with all built-in schemes plus `name:` and `host:` lookups in `inventory`.
+    pub fn from_config(inventory: Inventory) -> Self {
+        let inventory = Rc::new(inventory);
+        let repo = super::driver_repo();
+
+        repo.register("name", Box::new(NameFactory(inventory.clone())));
+        repo.register("host", Box::new(HostFactory(inventory)));
+        repo
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INVENTORY: &str = r#"
+[hosts.macx]
+transport = "ssh"
+address = "macx.local"
+user = "prekucki"
+port = 2222
+ssh_options = ["-i", "/home/ci/.ssh/vmhost"]
+type = "vmware"
+
+[hosts.local]
+type = "virtualbox"
+command = "/usr/local/bin/VBoxManage"
+
+[machines.build-win10]
+host = "macx"
+path = "/Users/prekucki/vm/win10.vmwarevm/win10.vmx"
+snapshot = "clean"
+guest_user = "build"
+guest_password = "s3cret"
+
+[machines.ubuntu]
+host = "local"
+path = "ubuntu-a"
+"#;
+
+    #[test]
+    fn test_parse() {
+        let inventory: Inventory = INVENTORY.parse().unwrap();
+
+        let macx = inventory.host("macx").unwrap();
+        assert_eq!(macx.transport, Transport::Ssh);
+        assert_eq!(macx.host_type, HostType::Vmware);
+        assert_eq!(macx.port, Some(2222));
+        assert_eq!(macx.ssh_options.len(), 2);
+
+        let local = inventory.host("local").unwrap();
+        assert_eq!(local.transport, Transport::Local);
+        assert_eq!(local.command.as_ref().unwrap(), "/usr/local/bin/VBoxManage");
+
+        let win = inventory.machine("build-win10").unwrap();
+        assert_eq!(win.snapshot.as_ref().unwrap(), "clean");
+        assert_eq!(win.guest_password.as_ref().unwrap(), "s3cret");
+    }
+
+    #[test]
+    #[cfg(all(feature = "vmware", feature = "virtualbox"))]
+    fn test_resolve() {
+        use Driver;
+
+        let repo = DriverRepo::from_config(INVENTORY.parse().unwrap());
+
+        let m = repo.from_path("name:build-win10?gu=admin").unwrap();
+        let uri = m.uri();
+        assert_eq!(uri.scheme(), "ssh+vmware");
+        assert_eq!(uri.host(), Some("macx.local"));
+        assert_eq!(uri.user(), Some("prekucki"));
+        assert_eq!(uri.port(), Some(2222));
+        assert_eq!(uri.path(), "/Users/prekucki/vm/win10.vmwarevm/win10.vmx");
+        assert_eq!(uri.query_param("snapshot"), Some("clean"));
+        assert_eq!(uri.query_param("gu"), Some("admin"));
+        // other user than in inventory gets no inventory password.
+        assert_ne!(m.guest_credentials().unwrap().password, "s3cret");
+
+        let m = repo.from_path("name:build-win10").unwrap();
+        assert!(!m.uri().to_string().contains("s3cret"));
+        assert_eq!(
+            m.guest_credentials(),
+            Some(Credentials::new("build", "s3cret"))
+        );
+
+        let m = repo.from_path("host://local/ubuntu-b").unwrap();
+        assert_eq!(m.uri().to_string(), "virtualbox:ubuntu-b");
+
+        match repo.from_path("name:missing") {
+            Err(Error(ErrorKind::UnknownMachine(ref name), _)) => assert_eq!(name, "missing"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("missing machine resolved"),
+        }
+    }
+}
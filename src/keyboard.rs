@@ -0,0 +1,303 @@
+//! Keyboard input sent to machine console, for boot prompts and login screens
+//! reached before guest tools start.
+
+use std::str::FromStr;
+
+use super::error::*;
+
+/// Set 1 scan codes are prefixed by this byte for keys missing on the original
+/// XT keyboard (arrows, navigation block, right modifiers).
+const EXTENDED: u8 = 0xe0;
+
+/// Released key has the top bit of its scan code set.
+const BREAK: u8 = 0x80;
+
+const SHIFT: u8 = 0x2a;
+
+/// Set 1 scan codes of US layout keys, in `(unshifted, shifted, code)` form.
+const US_LAYOUT: &[(char, char, u8)] = &[
+    ('1', '!', 0x02),
+    ('2', '@', 0x03),
+    ('3', '#', 0x04),
+    ('4', '$', 0x05),
+    ('5', '%', 0x06),
+    ('6', '^', 0x07),
+    ('7', '&', 0x08),
+    ('8', '*', 0x09),
+    ('9', '(', 0x0a),
+    ('0', ')', 0x0b),
+    ('-', '_', 0x0c),
+    ('=', '+', 0x0d),
+    ('q', 'Q', 0x10),
+    ('w', 'W', 0x11),
+    ('e', 'E', 0x12),
+    ('r', 'R', 0x13),
+    ('t', 'T', 0x14),
+    ('y', 'Y', 0x15),
+    ('u', 'U', 0x16),
+    ('i', 'I', 0x17),
+    ('o', 'O', 0x18),
+    ('p', 'P', 0x19),
+    ('[', '{', 0x1a),
+    (']', '}', 0x1b),
+    ('a', 'A', 0x1e),
+    ('s', 'S', 0x1f),
+    ('d', 'D', 0x20),
+    ('f', 'F', 0x21),
+    ('g', 'G', 0x22),
+    ('h', 'H', 0x23),
+    ('j', 'J', 0x24),
+    ('k', 'K', 0x25),
+    ('l', 'L', 0x26),
+    (';', ':', 0x27),
+    ('\'', '"', 0x28),
+    ('`', '~', 0x29),
+    ('\\', '|', 0x2b),
+    ('z', 'Z', 0x2c),
+    ('x', 'X', 0x2d),
+    ('c', 'C', 0x2e),
+    ('v', 'V', 0x2f),
+    ('b', 'B', 0x30),
+    ('n', 'N', 0x31),
+    ('m', 'M', 0x32),
+    (',', '<', 0x33),
+    ('.', '>', 0x34),
+    ('/', '?', 0x35),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// Printable character of US layout, upper case and symbols imply Shift.
+    Char(char),
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Space,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Function key `F1` to `F12`.
+    F(u8),
+    Ctrl,
+    Alt,
+    Shift,
+    Win,
+}
+
+impl Key {
+    /// Scan code sequences pressing and releasing the key.
+    fn scancodes(self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (code, extended, shifted) = match self {
+            Key::Char(ch) => {
+                let (code, shifted) = char_code(ch)?;
+                (code, false, shifted)
+            }
+            Key::Enter => (0x1c, false, false),
+            Key::Escape => (0x01, false, false),
+            Key::Tab => (0x0f, false, false),
+            Key::Backspace => (0x0e, false, false),
+            Key::Space => (0x39, false, false),
+            Key::Insert => (0x52, true, false),
+            Key::Delete => (0x53, true, false),
+            Key::Home => (0x47, true, false),
+            Key::End => (0x4f, true, false),
+            Key::PageUp => (0x49, true, false),
+            Key::PageDown => (0x51, true, false),
+            Key::Up => (0x48, true, false),
+            Key::Down => (0x50, true, false),
+            Key::Left => (0x4b, true, false),
+            Key::Right => (0x4d, true, false),
+            Key::F(n @ 1..=10) => (0x3a + n, false, false),
+            Key::F(11) => (0x57, false, false),
+            Key::F(12) => (0x58, false, false),
+            Key::F(n) => bail!(ErrorKind::InvalidConfig(format!("unknown key 'F{}'", n))),
+            Key::Ctrl => (0x1d, false, false),
+            Key::Alt => (0x38, false, false),
+            Key::Shift => (SHIFT, false, false),
+            Key::Win => (0x5b, true, false),
+        };
+
+        let (mut press, mut release) = (Vec::new(), Vec::new());
+        if shifted {
+            press.push(SHIFT);
+        }
+        if extended {
+            press.push(EXTENDED);
+            release.push(EXTENDED);
+        }
+        press.push(code);
+        release.push(code | BREAK);
+        if shifted {
+            release.push(SHIFT | BREAK);
+        }
+        Ok((press, release))
+    }
+}
+
+fn char_code(ch: char) -> Result<(u8, bool)> {
+    match ch {
+        '\n' => return Ok((0x1c, false)),
+        '\t' => return Ok((0x0f, false)),
+        ' ' => return Ok((0x39, false)),
+        _ => {}
+    }
+    US_LAYOUT
+        .iter()
+        .find_map(|&(plain, shifted, code)| {
+            if ch == plain {
+                Some((code, false))
+            } else if ch == shifted {
+                Some((code, true))
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| {
+            ErrorKind::InvalidConfig(format!("no US layout key types '{}'", ch.escape_default()))
+                .into()
+        })
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let lower = s.to_ascii_lowercase();
+        let key = match lower.as_str() {
+            "enter" | "return" => Key::Enter,
+            "esc" | "escape" => Key::Escape,
+            "tab" => Key::Tab,
+            "backspace" => Key::Backspace,
+            "space" => Key::Space,
+            "ins" | "insert" => Key::Insert,
+            "del" | "delete" => Key::Delete,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pgup" | "pageup" => Key::PageUp,
+            "pgdn" | "pagedown" => Key::PageDown,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "ctrl" | "control" => Key::Ctrl,
+            "alt" => Key::Alt,
+            "shift" => Key::Shift,
+            "win" | "super" | "meta" => Key::Win,
+            "plus" => Key::Char('+'),
+            _ => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Key::Char(ch),
+                    _ => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                        Some(n) => Key::F(n),
+                        None => bail!(ErrorKind::InvalidConfig(format!("unknown key '{}'", s))),
+                    },
+                }
+            }
+        };
+        let _ = key.scancodes()?;
+        Ok(key)
+    }
+}
+
+/// Keys held down together, e.g. `ctrl+alt+del`.
+///
+/// Keys are pressed in order and released in reverse order:
+///
+/// ```
+/// use vmctrl::keyboard::KeyCombo;
+///
+/// let combo: KeyCombo = "Ctrl+Alt+Del".parse().unwrap();
+/// assert_eq!(
+///     combo.scancodes().unwrap(),
+///     vec![0x1d, 0x38, 0xe0, 0x53, 0xe0, 0xd3, 0xb8, 0x9d]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub keys: Vec<Key>,
+}
+
+impl KeyCombo {
+    pub fn new(keys: Vec<Key>) -> Self {
+        KeyCombo { keys }
+    }
+
+    pub fn scancodes(&self) -> Result<Vec<u8>> {
+        let mut press = Vec::new();
+        let mut release = Vec::new();
+        for key in &self.keys {
+            let (down, up) = key.scancodes()?;
+            press.extend(down);
+            release.splice(0..0, up);
+        }
+        press.extend(release);
+        Ok(press)
+    }
+}
+
+/// Parses keys joined by `+`, `plus` stands for the `+` key itself.
+impl FromStr for KeyCombo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "+" {
+            return Ok(KeyCombo::new(vec![Key::Char('+')]));
+        }
+        let keys = s
+            .split('+')
+            .map(|key| key.trim().parse())
+            .collect::<Result<Vec<Key>>>()?;
+        Ok(KeyCombo::new(keys))
+    }
+}
+
+/// Scan codes typing `text` on US layout keyboard.
+pub fn text_scancodes(text: &str) -> Result<Vec<u8>> {
+    let mut codes = Vec::new();
+    for ch in text.chars() {
+        codes.extend(KeyCombo::new(vec![Key::Char(ch)]).scancodes()?);
+    }
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_combo() {
+        assert_eq!(
+            "alt+F4".parse::<KeyCombo>().unwrap(),
+            KeyCombo::new(vec![Key::Alt, Key::F(4)])
+        );
+        assert_eq!(
+            "shift+up".parse::<KeyCombo>().unwrap().scancodes().unwrap(),
+            vec![0x2a, 0xe0, 0x48, 0xe0, 0xc8, 0xaa]
+        );
+        assert_eq!(
+            "ctrl+plus".parse::<KeyCombo>().unwrap().keys,
+            vec![Key::Ctrl, Key::Char('+')]
+        );
+        assert!("ctrl+f13".parse::<KeyCombo>().is_err());
+        assert!("ctrl+hyper".parse::<KeyCombo>().is_err());
+        assert!("ctrl+é".parse::<KeyCombo>().is_err());
+    }
+
+    #[test]
+    fn test_text_scancodes() {
+        assert_eq!(
+            text_scancodes("aB\n").unwrap(),
+            vec![0x1e, 0x9e, 0x2a, 0x30, 0xb0, 0xaa, 0x1c, 0x9c]
+        );
+        assert!(text_scancodes("zażółć").is_err());
+    }
+}
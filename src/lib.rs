@@ -3,18 +3,84 @@ extern crate error_chain;
 #[macro_use]
 extern crate lazy_static;
 extern crate regex;
+#[cfg(any(feature = "inventory", feature = "http"))]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(any(feature = "inventory", feature = "http"))]
+extern crate serde;
+#[cfg(feature = "http")]
+extern crate serde_json;
+#[cfg(feature = "inventory")]
+extern crate toml;
 
 pub trait Driver {
     type Machine: Machine;
 
     fn list_running(&self) -> Result<Vec<Self::Machine>, error::Error>;
 
+    /// Lists every machine known to the hypervisor, including powered off ones.
+    fn list_all(&self) -> Result<Vec<Self::Machine>, error::Error>;
+
+    /// Adds existing machine (`.vmx` or `.vbox` file) to hypervisor inventory.
+    fn register(&self, path: &str) -> Result<Self::Machine, error::Error>;
+
+    /// Imports OVF/OVA appliance found at `path` on hypervisor host.
+    fn import_appliance(
+        &self,
+        path: &str,
+        options: &appliance::ImportOptions,
+    ) -> Result<Self::Machine, error::Error>;
+
+    #[allow(clippy::wrong_self_convention)]
     fn from_path(&self, path: &str) -> Result<Self::Machine, error::Error>;
+
+    /// Version of hypervisor tool (`vmrun`, `VBoxManage`) on target host.
+    fn version(&self) -> Result<version::Version, error::Error> {
+        bail!(error::ErrorKind::NotSupported("version detection".into()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineState {
+    Running,
+    PoweredOff,
+    Paused,
+    Suspended,
+    Aborted,
+    Unknown,
+}
+
+impl MachineState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MachineState::Running => "running",
+            MachineState::PoweredOff => "poweroff",
+            MachineState::Paused => "paused",
+            MachineState::Suspended => "suspended",
+            MachineState::Aborted => "aborted",
+            MachineState::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for MachineState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 pub trait Machine {
     fn name(&self) -> &str;
 
+    /// Uri that opens this machine again when passed to `driver().from_path`.
+    fn uri(&self) -> uri::VmUri;
+
+    fn state(&self) -> Result<MachineState, error::Error>;
+
+    fn info(&self) -> Result<info::MachineInfo, error::Error> {
+        bail!(error::ErrorKind::NotSupported("machine info".into()))
+    }
+
     fn list_snapshots(&self) -> Result<Vec<String>, error::Error>;
 
     fn stop(&mut self) -> Result<(), error::Error>;
@@ -24,23 +90,237 @@ pub trait Machine {
     fn revert_to(&mut self, snapshot_name: &str) -> Result<(), error::Error>;
 
     fn create_snapshot(&mut self, snapshot_name: &str) -> Result<(), error::Error>;
+
+    fn delete_snapshot(&mut self, snapshot_name: &str) -> Result<(), error::Error>;
+
+    /// Clones machine into `target` (`.vmx` path for VMware, machine name for
+    /// VirtualBox) and returns it opened through the same driver.
+    ///
+    /// Linked clones share disks with `from_snapshot` of this machine.
+    fn clone_to(
+        &self,
+        _target: &str,
+        _from_snapshot: Option<&str>,
+        _linked: bool,
+    ) -> Result<Box<dyn Machine>, error::Error> {
+        bail!(error::ErrorKind::NotSupported("cloning".into()))
+    }
+
+    /// Removes machine from hypervisor inventory, files stay on disk.
+    fn unregister(&mut self) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("unregistering".into()))
+    }
+
+    /// Removes machine configuration, and its disk images when `with_disks` is set.
+    ///
+    /// Fails with `ErrorKind::MachineRunning` for running machines and, when disks
+    /// are to be removed, with `ErrorKind::HasLinkedClones` if other machines
+    /// still depend on them.
+    fn delete(&mut self, _with_disks: bool) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("deleting".into()))
+    }
+
+    /// Applies hardware changes, machine must be powered off.
+    fn configure(&mut self, _config: &config::MachineConfig) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("configuring".into()))
+    }
+
+    /// Changes network adapter in `slot`, numbered as in `info().nics`.
+    ///
+    /// Attachment and cable state of running machines are changed where the
+    /// hypervisor allows it, other changes require the machine to be powered off.
+    fn configure_nic(
+        &mut self,
+        _slot: u32,
+        _config: &network::NicConfig,
+    ) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported(
+            "network adapter configuration".into()
+        ))
+    }
+
+    /// NAT port forwarding rules of adapter in `slot`.
+    fn port_forwards(&self, _slot: u32) -> Result<Vec<network::PortForward>, error::Error> {
+        bail!(error::ErrorKind::NotSupported("port forwarding".into()))
+    }
+
+    fn add_port_forward(
+        &mut self,
+        _slot: u32,
+        _rule: &network::PortForward,
+    ) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("port forwarding".into()))
+    }
+
+    fn remove_port_forward(&mut self, _slot: u32, _name: &str) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("port forwarding".into()))
+    }
+
+    /// Attaches disk image at `path` on hypervisor host to `slot`.
+    fn attach_disk(&mut self, _slot: &disk::DiskSlot, _path: &str) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("attaching disks".into()))
+    }
+
+    /// Detaches disk from `slot`, the image stays on disk.
+    fn detach_disk(&mut self, _slot: &disk::DiskSlot) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("detaching disks".into()))
+    }
+
+    fn shared_folders(&self) -> Result<Vec<shared_folder::SharedFolder>, error::Error> {
+        bail!(error::ErrorKind::NotSupported("shared folders".into()))
+    }
+
+    fn add_shared_folder(
+        &mut self,
+        _folder: &shared_folder::SharedFolder,
+    ) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("shared folders".into()))
+    }
+
+    fn remove_shared_folder(&mut self, _name: &str) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("shared folders".into()))
+    }
+
+    /// Turns sharing of all folders with the guest on or off.
+    fn set_shared_folders_enabled(&mut self, _enabled: bool) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("shared folders".into()))
+    }
+
+    /// Captures display of running machine as PNG to `path` on hypervisor host
+    /// and returns the image, the file is removed afterwards. Failing to remove
+    /// it does not fail the capture.
+    fn screenshot(&self, _path: &str) -> Result<Vec<u8>, error::Error> {
+        bail!(error::ErrorKind::NotSupported("screenshots".into()))
+    }
+
+    /// Types `text` on console of running machine using US keyboard layout.
+    ///
+    /// `vmrun` offers no console keyboard input, so VMware machines do not
+    /// support it.
+    fn send_text(&self, _text: &str) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("keyboard input".into()))
+    }
+
+    /// Presses and releases keys of `combo` on console of running machine.
+    fn send_keys(&self, _combo: &keyboard::KeyCombo) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("keyboard input".into()))
+    }
+
+    /// Connects serial `port`, numbered as by hypervisor (`uart1` is port 1 for
+    /// VirtualBox, `serial0` port 0 for VMware), machine must be powered off.
+    fn configure_serial(
+        &mut self,
+        _port: u32,
+        _mode: &serial::SerialMode,
+    ) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("serial ports".into()))
+    }
+
+    fn serial_mode(&self, _port: u32) -> Result<serial::SerialMode, error::Error> {
+        bail!(error::ErrorKind::NotSupported("serial ports".into()))
+    }
+
+    /// Output of serial `port` connected to file, from byte `offset` on.
+    fn read_serial(&self, _port: u32, _offset: u64) -> Result<Vec<u8>, error::Error> {
+        bail!(error::ErrorKind::NotSupported("serial ports".into()))
+    }
+
+    /// Value of guest property (VirtualBox) or variable (VMware, see
+    /// `property::VariableScope`), `None` when unset.
+    fn guest_property(&self, _name: &str) -> Result<Option<String>, error::Error> {
+        bail!(error::ErrorKind::NotSupported("guest properties".into()))
+    }
+
+    /// Properties matching `pattern` (see `property::matches`).
+    fn guest_properties(
+        &self,
+        _pattern: &str,
+    ) -> Result<Vec<property::GuestProperty>, error::Error> {
+        bail!(error::ErrorKind::NotSupported(
+            "enumerating guest properties".into()
+        ))
+    }
+
+    fn set_guest_property(&mut self, _name: &str, _value: &str) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("guest properties".into()))
+    }
+
+    fn delete_guest_property(&mut self, _name: &str) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("guest properties".into()))
+    }
+
+    /// Blocks until property matching `pattern` is set, for guest to host
+    /// signalling. Returns at once if such property already exists, `None`
+    /// after `timeout`.
+    fn wait_for_property(
+        &self,
+        _pattern: &str,
+        _timeout: std::time::Duration,
+    ) -> Result<Option<property::GuestProperty>, error::Error> {
+        bail!(error::ErrorKind::NotSupported("guest properties".into()))
+    }
+
+    /// Exports powered off machine as appliance to `path` on hypervisor host.
+    fn export(&self, _path: &str, _format: appliance::ExportFormat) -> Result<(), error::Error> {
+        bail!(error::ErrorKind::NotSupported("exporting".into()))
+    }
+
+    /// Guest account used when an operation needs one (VMware `guestEnv`
+    /// variables, screenshots): `gu` uri parameter, with password configured
+    /// for the machine in inventory or taken from `VMCTRL_GUEST_PASSWORD`.
+    fn guest_credentials(&self) -> Option<guest::Credentials> {
+        guest::Credentials::from_uri(&self.uri())
+    }
+
+    /// Opens session for running programs and copying files inside the guest.
+    fn guest_session<'a>(
+        &'a self,
+        credentials: &guest::Credentials,
+    ) -> Result<Box<dyn guest::GuestSession + 'a>, error::Error>;
 }
 
 pub use crate::command::{local, ssh, CommandRunner, FromCommandRunner};
 
+pub mod appliance;
 pub mod command;
+pub mod config;
+pub mod disk;
 pub mod error;
+pub mod guest;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod info;
+#[cfg(feature = "inventory")]
+pub mod inventory;
+pub mod keyboard;
+pub mod network;
+pub mod pool;
+pub mod property;
+pub mod serial;
+pub mod shared_folder;
 pub mod uri;
+pub mod version;
 
 #[cfg(feature = "virtualbox")]
 pub mod virtual_box;
 #[cfg(feature = "vmware")]
 pub mod vmware;
+#[cfg(feature = "vmware")]
+pub mod vmx;
 
 mod remote;
 
-pub fn driver() -> impl Driver<Machine = Box<Machine + 'static>> {
-    let mut uri = uri::DriverRepo::default();
+/// Driver resolving `scheme:path` uris against all compiled in backends.
+///
+/// Unknown schemes fail with `ErrorKind::UnknownScheme`, uris the backend cannot
+/// parse with `ErrorKind::InvalidUri`, everything else is reported by the backend.
+pub fn driver() -> impl Driver<Machine = Box<dyn Machine + 'static>> {
+    driver_repo()
+}
+
+/// Same as `driver()` but exposes `DriverRepo` for registering extra schemes.
+pub fn driver_repo() -> uri::DriverRepo {
+    let uri = uri::DriverRepo::default();
 
     #[cfg(feature = "vmware")]
     uri.register("vmware", vmware::local_driver());
@@ -54,5 +334,11 @@ pub fn driver() -> impl Driver<Machine = Box<Machine + 'static>> {
     #[cfg(feature = "virtualbox")]
     uri.register("ssh+virtualbox", virtual_box::remote_driver());
 
+    #[cfg(all(feature = "vmware", feature = "http"))]
+    uri.register("http+vmware", vmware::http_driver());
+
+    #[cfg(all(feature = "virtualbox", feature = "http"))]
+    uri.register("http+virtualbox", virtual_box::http_driver());
+
     uri
 }
@@ -0,0 +1,260 @@
+//! Network adapter settings and NAT port forwarding.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::*;
+use super::info::NicAttachment;
+
+/// Changes to network adapter, unset fields are left untouched.
+///
+/// ```
+/// use vmctrl::info::NicAttachment;
+/// use vmctrl::network::NicConfig;
+///
+/// let config = NicConfig::new()
+///     .attachment(NicAttachment::Bridged)
+///     .network("eth0")
+///     .mac("08:00:27:a1:b2:c3")
+///     .cable_connected(true);
+/// assert!(config.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NicConfig {
+    pub attachment: Option<NicAttachment>,
+    /// Bridged interface, host-only adapter or internal network name.
+    pub network: Option<String>,
+    pub mac: Option<String>,
+    pub cable_connected: Option<bool>,
+}
+
+impl NicConfig {
+    pub fn new() -> Self {
+        NicConfig::default()
+    }
+
+    pub fn attachment(mut self, attachment: NicAttachment) -> Self {
+        self.attachment = Some(attachment);
+        self
+    }
+
+    pub fn network<T: Into<String>>(mut self, network: T) -> Self {
+        self.network = Some(network.into());
+        self
+    }
+
+    /// Hex digits, optionally separated by `:` or `-`.
+    pub fn mac<T: Into<String>>(mut self, mac: T) -> Self {
+        self.mac = Some(mac.into());
+        self
+    }
+
+    pub fn cable_connected(mut self, cable_connected: bool) -> Self {
+        self.cable_connected = Some(cable_connected);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == NicConfig::default()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(ref mac) = self.mac {
+            let _ = mac_digits(mac)?;
+        }
+        Ok(())
+    }
+}
+
+/// Twelve upper case hex digits of `mac`, separators removed.
+pub(crate) fn mac_digits(mac: &str) -> Result<String> {
+    let digits: String = mac
+        .chars()
+        .filter(|ch| *ch != ':' && *ch != '-')
+        .collect::<String>()
+        .to_ascii_uppercase();
+    if digits.len() != 12 || !digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        bail!(ErrorKind::InvalidConfig(format!(
+            "invalid mac address '{}'",
+            mac
+        )))
+    }
+    Ok(digits)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(Protocol::Tcp),
+            "udp" => Ok(Protocol::Udp),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown protocol '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// NAT rule forwarding `host_port` on hypervisor host to `guest_port` in guest.
+///
+/// Written and parsed in VirtualBox rule syntax,
+/// `name,protocol,host_ip,host_port,guest_ip,guest_port` with empty addresses
+/// standing for any:
+///
+/// ```
+/// use vmctrl::network::PortForward;
+///
+/// let rule: PortForward = "ssh,tcp,,2222,,22".parse().unwrap();
+/// assert_eq!(rule, PortForward::tcp("ssh", 2222, 22));
+/// assert_eq!(rule.to_string(), "ssh,tcp,,2222,,22");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortForward {
+    pub name: String,
+    pub protocol: Protocol,
+    pub host_ip: Option<String>,
+    pub host_port: u16,
+    pub guest_ip: Option<String>,
+    pub guest_port: u16,
+}
+
+impl PortForward {
+    pub fn tcp<T: Into<String>>(name: T, host_port: u16, guest_port: u16) -> Self {
+        PortForward {
+            name: name.into(),
+            protocol: Protocol::Tcp,
+            host_ip: None,
+            host_port,
+            guest_ip: None,
+            guest_port,
+        }
+    }
+
+    pub fn udp<T: Into<String>>(name: T, host_port: u16, guest_port: u16) -> Self {
+        PortForward {
+            protocol: Protocol::Udp,
+            ..PortForward::tcp(name, host_port, guest_port)
+        }
+    }
+
+    pub fn host_ip<T: Into<String>>(mut self, host_ip: T) -> Self {
+        self.host_ip = Some(host_ip.into());
+        self
+    }
+
+    pub fn guest_ip<T: Into<String>>(mut self, guest_ip: T) -> Self {
+        self.guest_ip = Some(guest_ip.into());
+        self
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty()
+            || self
+                .name
+                .contains(|ch: char| ch == ',' || ch.is_whitespace())
+        {
+            bail!(ErrorKind::InvalidConfig(format!(
+                "invalid rule name '{}'",
+                self.name
+            )))
+        }
+        if self.host_port == 0 || self.guest_port == 0 {
+            bail!(ErrorKind::InvalidConfig("port must not be 0".into()))
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PortForward {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split(',').map(|field| field.trim()).collect();
+        if fields.len() != 6 {
+            bail!(ErrorKind::InvalidConfig(format!("invalid rule '{}'", s)))
+        }
+        let ip = |field: &str| Some(field.to_string()).filter(|ip| !ip.is_empty());
+        let port = |field: &str| -> Result<u16> {
+            field
+                .parse()
+                .chain_err(|| ErrorKind::InvalidConfig(format!("invalid port in rule '{}'", s)))
+        };
+
+        let rule = PortForward {
+            name: fields[0].into(),
+            protocol: fields[1].parse()?,
+            host_ip: ip(fields[2]),
+            host_port: port(fields[3])?,
+            guest_ip: ip(fields[4]),
+            guest_port: port(fields[5])?,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for PortForward {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{},{}",
+            self.name,
+            self.protocol,
+            self.host_ip.as_ref().map_or("", |ip| ip.as_str()),
+            self.host_port,
+            self.guest_ip.as_ref().map_or("", |ip| ip.as_str()),
+            self.guest_port
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_port_forward() {
+        let rule: PortForward = "web, udp, 127.0.0.1, 8080, 10.0.2.15, 80".parse().unwrap();
+        assert_eq!(
+            rule,
+            PortForward::udp("web", 8080, 80)
+                .host_ip("127.0.0.1")
+                .guest_ip("10.0.2.15")
+        );
+        assert_eq!(rule.to_string(), "web,udp,127.0.0.1,8080,10.0.2.15,80");
+        assert!("ssh,tcp,,2222,22".parse::<PortForward>().is_err());
+        assert!("ssh,sctp,,2222,,22".parse::<PortForward>().is_err());
+        assert!("ssh,tcp,,0,,22".parse::<PortForward>().is_err());
+    }
+
+    #[test]
+    fn test_mac_digits() {
+        assert_eq!(mac_digits("08:00:27:a1:b2:c3").unwrap(), "080027A1B2C3");
+        assert_eq!(mac_digits("080027A1B2C3").unwrap(), "080027A1B2C3");
+        assert!(mac_digits("08:00:27:a1:b2").is_err());
+        assert!(mac_digits("08:00:27:a1:b2:zz").is_err());
+    }
+}
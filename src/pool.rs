@@ -0,0 +1,355 @@
+//! Pool of machines handed out one job at a time.
+//!
+//! Every machine goes back to a clean snapshot when its lease ends. Machines that
+//! fail to revert are quarantined instead of being leased again.
+//!
+//! ```no_run
+//! use vmctrl::pool::Pool;
+//! use vmctrl::Driver;
+//!
+//! let driver = vmctrl::driver();
+//! let pool = Pool::new("clean");
+//! pool.add(driver.from_path("vmware:/vms/worker1.vmx").unwrap());
+//!
+//! let vm = pool.lease().unwrap();
+//! // ... run job on `vm` ...
+//! vm.release().unwrap();
+//! ```
+
+use super::error::*;
+use super::Machine;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+pub struct Pool<M: Machine> {
+    snapshot: String,
+    lease_timeout: Option<Duration>,
+    inner: RefCell<PoolImpl<M>>,
+}
+
+struct PoolImpl<M> {
+    free: VecDeque<M>,
+    leases: Vec<LeaseInfo>,
+    quarantine: Vec<(M, Error)>,
+    next_id: u64,
+}
+
+/// Book keeping of active lease.
+#[derive(Clone, Debug)]
+pub struct LeaseInfo {
+    id: u64,
+    name: String,
+    since: Instant,
+}
+
+impl LeaseInfo {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn since(&self) -> Instant {
+        self.since
+    }
+}
+
+impl<M: Machine> Pool<M> {
+    /// Creates empty pool reverting machines to `snapshot`.
+    ///
+    /// Machines opened with `?snapshot=` uri parameter revert to that snapshot instead.
+    pub fn new<S: Into<String>>(snapshot: S) -> Self {
+        Pool {
+            snapshot: snapshot.into(),
+            lease_timeout: None,
+            inner: RefCell::new(PoolImpl {
+                free: VecDeque::new(),
+                leases: Vec::new(),
+                quarantine: Vec::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    /// Leases held longer are reported by `expired_leases`, they are never
+    /// reclaimed: machine goes back to pool only when its `Lease` is released
+    /// or dropped.
+    pub fn with_lease_timeout(mut self, lease_timeout: Duration) -> Self {
+        self.lease_timeout = Some(lease_timeout);
+        self
+    }
+
+    pub fn add(&self, machine: M) {
+        self.inner.borrow_mut().free.push_back(machine)
+    }
+
+    /// Takes first free machine, fails with `ErrorKind::PoolExhausted` if there is none.
+    pub fn lease(&self) -> Result<Lease<'_, M>> {
+        let mut inner = self.inner.borrow_mut();
+        let machine = match inner.free.pop_front() {
+            Some(machine) => machine,
+            None => bail!(ErrorKind::PoolExhausted),
+        };
+        let info = LeaseInfo {
+            id: inner.next_id,
+            name: machine.name().into(),
+            since: Instant::now(),
+        };
+        inner.next_id += 1;
+        inner.leases.push(info.clone());
+
+        Ok(Lease {
+            pool: self,
+            machine: Some(machine),
+            info,
+        })
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.inner.borrow().free.len()
+    }
+
+    pub fn leases(&self) -> Vec<LeaseInfo> {
+        self.inner.borrow().leases.clone()
+    }
+
+    /// Leases held longer than lease timeout, still held by their holders.
+    pub fn expired_leases(&self) -> Vec<LeaseInfo> {
+        self.inner
+            .borrow()
+            .leases
+            .iter()
+            .filter(|info| self.is_expired(info))
+            .cloned()
+            .collect()
+    }
+
+    /// Names of machines that failed to revert.
+    pub fn quarantined(&self) -> Vec<String> {
+        self.inner
+            .borrow()
+            .quarantine
+            .iter()
+            .map(|(m, _)| m.name().to_string())
+            .collect()
+    }
+
+    /// Removes quarantined machines from pool together with messages of revert
+    /// errors, the errors themselves are returned by `Lease::release`.
+    pub fn take_quarantined(&self) -> Vec<(M, Error)> {
+        self.inner.borrow_mut().quarantine.drain(..).collect()
+    }
+
+    fn is_expired(&self, info: &LeaseInfo) -> bool {
+        match self.lease_timeout {
+            Some(timeout) => info.since.elapsed() > timeout,
+            None => false,
+        }
+    }
+
+    fn snapshot_for(&self, machine: &M) -> String {
+        match machine.uri().query_param("snapshot") {
+            Some(snapshot) => snapshot.into(),
+            None => self.snapshot.clone(),
+        }
+    }
+
+    fn give_back(&self, id: u64, mut machine: M) -> Result<()> {
+        let snapshot = self.snapshot_for(&machine);
+        // machine may be already stopped, revert decides if state is fine.
+        let _ = machine.stop();
+        let result = machine.revert_to(&snapshot);
+
+        let mut inner = self.inner.borrow_mut();
+        inner.leases.retain(|info| info.id != id);
+        match result {
+            Ok(()) => {
+                inner.free.push_back(machine);
+                Ok(())
+            }
+            Err(e) => {
+                inner.quarantine.push((machine, e.to_string().into()));
+                Err(e).chain_err(|| format!("revert to '{}' failed, machine quarantined", snapshot))
+            }
+        }
+    }
+}
+
+/// Machine taken from `Pool`, goes back to pool on `release` or drop.
+pub struct Lease<'a, M: Machine + 'a> {
+    pool: &'a Pool<M>,
+    machine: Option<M>,
+    info: LeaseInfo,
+}
+
+impl<'a, M: Machine> Lease<'a, M> {
+    /// Reverts machine and returns it to pool, reporting revert failure.
+    pub fn release(mut self) -> Result<()> {
+        let machine = self.machine.take().unwrap();
+        self.pool.give_back(self.info.id, machine)
+    }
+
+    pub fn info(&self) -> &LeaseInfo {
+        &self.info
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.pool.is_expired(&self.info)
+    }
+}
+
+impl<'a, M: Machine> Deref for Lease<'a, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        self.machine.as_ref().unwrap()
+    }
+}
+
+impl<'a, M: Machine> DerefMut for Lease<'a, M> {
+    fn deref_mut(&mut self) -> &mut M {
+        self.machine.as_mut().unwrap()
+    }
+}
+
+impl<'a, M: Machine> Drop for Lease<'a, M> {
+    fn drop(&mut self) {
+        if let Some(machine) = self.machine.take() {
+            let _ = self.pool.give_back(self.info.id, machine);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use guest::{Credentials, GuestSession};
+    use std::rc::Rc;
+    use uri::VmUri;
+    use MachineState;
+
+    struct FakeMachine {
+        name: String,
+        log: Rc<RefCell<Vec<String>>>,
+        broken: bool,
+    }
+
+    impl Machine for FakeMachine {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn uri(&self) -> VmUri {
+            match self.name.as_str() {
+                "b" => VmUri::new("fake", "b").with_query_param("snapshot", "base"),
+                _ => VmUri::new("fake", self.name.as_str()),
+            }
+        }
+
+        fn state(&self) -> Result<MachineState> {
+            Ok(MachineState::Unknown)
+        }
+
+        fn list_snapshots(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn revert_to(&mut self, snapshot_name: &str) -> Result<()> {
+            self.log
+                .borrow_mut()
+                .push(format!("{}@{}", self.name, snapshot_name));
+            if self.broken {
+                bail!("broken")
+            }
+            Ok(())
+        }
+
+        fn create_snapshot(&mut self, _snapshot_name: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn delete_snapshot(&mut self, _snapshot_name: &str) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn guest_session<'a>(
+            &'a self,
+            _credentials: &Credentials,
+        ) -> Result<Box<dyn GuestSession + 'a>> {
+            unimplemented!()
+        }
+    }
+
+    fn pool(log: &Rc<RefCell<Vec<String>>>) -> Pool<FakeMachine> {
+        let pool = Pool::new("clean");
+        for name in &["a", "b"] {
+            pool.add(FakeMachine {
+                name: name.to_string(),
+                log: log.clone(),
+                broken: false,
+            });
+        }
+        pool
+    }
+
+    #[test]
+    fn test_lease_and_revert() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let pool = pool(&log);
+
+        let a = pool.lease().unwrap();
+        let b = pool.lease().unwrap();
+        assert_eq!(a.name(), "a");
+        assert_eq!(pool.leases().len(), 2);
+        assert!(pool.lease().is_err());
+
+        a.release().unwrap();
+        drop(b);
+
+        assert_eq!(*log.borrow(), vec!["a@clean", "b@base"]);
+        assert_eq!(pool.free_count(), 2);
+        assert!(pool.leases().is_empty());
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let pool = pool(&log);
+
+        let mut a = pool.lease().unwrap();
+        a.broken = true;
+        let e = a.release().unwrap_err();
+        assert_eq!(
+            e.iter().nth(1).map(|cause| cause.to_string()),
+            Some("broken".into())
+        );
+
+        assert_eq!(pool.quarantined(), vec!["a"]);
+        assert_eq!(pool.free_count(), 1);
+        assert_eq!(pool.lease().unwrap().name(), "b");
+
+        let taken = pool.take_quarantined();
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].1.to_string(), "broken");
+        assert!(pool.quarantined().is_empty());
+    }
+
+    #[test]
+    fn test_lease_timeout() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let pool = pool(&log).with_lease_timeout(Duration::from_secs(0));
+
+        let a = pool.lease().unwrap();
+        ::std::thread::sleep(Duration::from_millis(1));
+        assert!(a.is_expired());
+        assert_eq!(pool.expired_leases()[0].name(), "a");
+    }
+}
@@ -0,0 +1,127 @@
+//! Key/value properties shared between host and running guest, VirtualBox guest
+//! properties and VMware guest variables.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestProperty {
+    pub name: String,
+    pub value: String,
+}
+
+impl GuestProperty {
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
+        GuestProperty {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// Namespace of VMware variable, chosen by `scope:` prefix of property name.
+///
+/// ```
+/// use vmctrl::property::VariableScope;
+///
+/// assert_eq!(
+///     VariableScope::split("guestEnv:PATH"),
+///     (VariableScope::GuestEnv, "PATH")
+/// );
+/// assert_eq!(VariableScope::split("job-id"), (VariableScope::GuestVar, "job-id"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableScope {
+    /// Set in guest by `vmware-rpctool "info-set guestinfo.<name> <value>"`,
+    /// lost on power off.
+    GuestVar,
+    /// Entry of running machine `.vmx` configuration.
+    RuntimeConfig,
+    /// Environment variable in guest, requires guest credentials (`gu` and
+    /// `gp` uri parameters).
+    GuestEnv,
+}
+
+impl VariableScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VariableScope::GuestVar => "guestVar",
+            VariableScope::RuntimeConfig => "runtimeConfig",
+            VariableScope::GuestEnv => "guestEnv",
+        }
+    }
+
+    /// Scope and variable name of property `name`, guest variable when it has
+    /// no known scope prefix.
+    pub fn split(name: &str) -> (VariableScope, &str) {
+        if let Some((scope, variable)) = name.split_once(':') {
+            if let Ok(scope) = scope.parse() {
+                return (scope, variable);
+            }
+        }
+        (VariableScope::GuestVar, name)
+    }
+}
+
+impl FromStr for VariableScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "guestVar" => Ok(VariableScope::GuestVar),
+            "runtimeConfig" => Ok(VariableScope::RuntimeConfig),
+            "guestEnv" => Ok(VariableScope::GuestEnv),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown variable scope '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for VariableScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether `name` matches VirtualBox style `pattern`: `*` and `?` wildcards,
+/// alternatives separated by `|`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    fn glob(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some((&'*', rest)) => (0..=name.len()).any(|skip| glob(rest, &name[skip..])),
+            Some((&'?', rest)) => !name.is_empty() && glob(rest, &name[1..]),
+            Some((ch, rest)) => name.first() == Some(ch) && glob(rest, &name[1..]),
+        }
+    }
+
+    let name: Vec<char> = name.chars().collect();
+    pattern
+        .split('|')
+        .any(|alternative| glob(&alternative.chars().collect::<Vec<_>>(), &name))
+}
+
+/// Whether `pattern` names single property.
+pub fn is_literal(pattern: &str) -> bool {
+    !pattern.contains(['*', '?', '|'])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("/job/*", "/job/id"));
+        assert!(matches("/job/*", "/job/"));
+        assert!(matches("/a|/job/?d", "/job/id"));
+        assert!(!matches("/job/?d", "/job/uid"));
+        assert!(!matches("/job", "/job/id"));
+        assert!(is_literal("/job/id"));
+        assert!(!is_literal("/job/*"));
+    }
+}
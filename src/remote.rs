@@ -1,55 +1,108 @@
-use super::command::Ssh;
-use super::uri::DriverFactory;
-use super::{ssh, Driver, FromCommandRunner, Machine};
+use super::appliance::ImportOptions;
+use super::command::{CommandRunner, Ssh};
+use super::disk::DiskStore;
+use super::error::*;
+use super::uri::{DriverFactory, Listing, VmUri};
+use super::version::Version;
+use super::{ssh, FromCommandRunner, Machine};
+
+/// Command runner reaching hypervisor host named by uri authority.
+pub trait RemoteCommand: CommandRunner + Sized {
+    fn for_uri(uri: &VmUri) -> Option<Self>;
+}
+
+impl RemoteCommand for Ssh {
+    fn for_uri(uri: &VmUri) -> Option<Self> {
+        let mut cmd = ssh(uri.host().filter(|host| !host.is_empty())?);
+        if let Some(user) = uri.user() {
+            cmd = cmd.user(user);
+        }
+        if let Some(port) = uri.port() {
+            cmd = cmd.port(port);
+        }
+        Some(cmd)
+    }
+}
+
+#[cfg(feature = "http")]
+impl RemoteCommand for ::http::Http {
+    fn for_uri(uri: &VmUri) -> Option<Self> {
+        let mut cmd = ::http::http(uri.host().filter(|host| !host.is_empty())?);
+        if let Some(port) = uri.port() {
+            cmd = cmd.port(port);
+        }
+        Some(cmd)
+    }
+}
 
 pub struct RemoteFactory<D: FromCommandRunner>(D);
 
-fn parse_ssh(path: &str) -> Option<(&str, &str)> {
-    if path.starts_with("//") {
-        return parse_ssh(&path[2..]);
+impl<R: FromCommandRunner<Output = D>, D: DriverFactory> DriverFactory for RemoteFactory<R>
+where
+    R::Command: RemoteCommand,
+{
+    fn machine_for_uri(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        match R::Command::for_uri(uri) {
+            Some(cmd) => self.0.from_cmd(cmd).machine_for_uri(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
+    }
+
+    fn list_running(&self, uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        match R::Command::for_uri(uri) {
+            Some(cmd) => self.0.from_cmd(cmd).list_running(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
     }
 
-    if let Some(p) = path.find(":") {
-        return Some((&path[0..p], &path[p + 1..]));
+    fn list_all(&self, uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        match R::Command::for_uri(uri) {
+            Some(cmd) => self.0.from_cmd(cmd).list_all(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
     }
 
-    if let Some(p) = path.find("/") {
-        return Some((&path[0..p], &path[p + 1..]));
+    fn register(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        match R::Command::for_uri(uri) {
+            Some(cmd) => self.0.from_cmd(cmd).register(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
     }
 
-    None
-}
+    fn import_appliance(&self, uri: &VmUri, options: &ImportOptions) -> Result<Box<dyn Machine>> {
+        match R::Command::for_uri(uri) {
+            Some(cmd) => self.0.from_cmd(cmd).import_appliance(uri, options),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
+    }
 
-impl<R: FromCommandRunner<Command = Ssh, Output = D>, D: Driver> DriverFactory for RemoteFactory<R>
-where
-    D::Machine: 'static,
-{
-    fn machine_for_uri(&self, uri: &str) -> Option<Box<Machine>> {
-        if let Some((host, path)) = parse_ssh(uri) {
-            let driver = self.0.from_cmd(ssh(host));
+    fn disk_store(&self, uri: &VmUri) -> Result<Box<dyn DiskStore>> {
+        match R::Command::for_uri(uri) {
+            Some(cmd) => self.0.from_cmd(cmd).disk_store(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
+        }
+    }
 
-            return match driver.from_path(path) {
-                Ok(m) => Some(Box::new(m)),
-                Err(_) => None,
-            };
+    fn version(&self, uri: &VmUri) -> Result<Version> {
+        match R::Command::for_uri(uri) {
+            Some(cmd) => self.0.from_cmd(cmd).version(uri),
+            None => bail!(ErrorKind::InvalidUri(uri.to_string())),
         }
-        None
     }
 }
 
-impl<R: FromCommandRunner<Command = Ssh, Output = D>, D: Driver> From<R> for RemoteFactory<R>
+impl<R: FromCommandRunner<Output = D>, D: DriverFactory> From<R> for RemoteFactory<R>
 where
-    D::Machine: 'static,
+    R::Command: RemoteCommand,
 {
     fn from(factory: R) -> Self {
         RemoteFactory(factory)
     }
 }
 
-impl<R: FromCommandRunner<Command = Ssh, Output = D> + 'static, D: Driver> From<R>
-    for Box<DriverFactory>
+impl<R: FromCommandRunner<Output = D> + 'static, D: DriverFactory> From<R> for Box<dyn DriverFactory>
 where
-    D::Machine: 'static,
+    R::Command: RemoteCommand,
 {
     fn from(factory: R) -> Self {
         let f: RemoteFactory<_> = factory.into();
@@ -0,0 +1,125 @@
+//! Guest serial ports connected to host files or pipes.
+
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use super::error::*;
+use super::Machine;
+
+/// Host end of guest serial port.
+///
+/// Written as `off`, `disconnected`, `file:<path>` or `pipe:<path>`:
+///
+/// ```
+/// use vmctrl::serial::SerialMode;
+///
+/// let mode: SerialMode = "file:/var/log/vms/ttyS0.log".parse().unwrap();
+/// assert_eq!(mode, SerialMode::File("/var/log/vms/ttyS0.log".into()));
+/// assert_eq!(mode.to_string(), "file:/var/log/vms/ttyS0.log");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerialMode {
+    /// No serial port in guest.
+    Off,
+    /// Port present in guest, but connected to nothing on host.
+    Disconnected,
+    /// Guest output is written to file on hypervisor host.
+    File(String),
+    /// Named pipe (Unix socket on Linux hosts) created by hypervisor, for
+    /// interactive consoles through e.g. `socat`.
+    Pipe(String),
+    /// Connection this crate does not configure (host device, TCP socket),
+    /// as reported by hypervisor.
+    Other(String),
+}
+
+impl SerialMode {
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            SerialMode::File(ref path) | SerialMode::Pipe(ref path) if path.is_empty() => bail!(
+                ErrorKind::InvalidConfig("serial port path must not be empty".into())
+            ),
+            SerialMode::Other(ref other) => bail!(ErrorKind::InvalidConfig(format!(
+                "unsupported serial port mode '{}'",
+                other
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for SerialMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mode = match s.split_once(':') {
+            Some(("file", path)) => SerialMode::File(path.into()),
+            Some(("pipe", path)) => SerialMode::Pipe(path.into()),
+            None if s == "off" => SerialMode::Off,
+            None if s == "disconnected" => SerialMode::Disconnected,
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown serial port mode '{}'",
+                s
+            ))),
+        };
+        mode.validate()?;
+        Ok(mode)
+    }
+}
+
+impl fmt::Display for SerialMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerialMode::Off => f.write_str("off"),
+            SerialMode::Disconnected => f.write_str("disconnected"),
+            SerialMode::File(ref path) => write!(f, "file:{}", path),
+            SerialMode::Pipe(ref path) => write!(f, "pipe:{}", path),
+            SerialMode::Other(ref other) => f.write_str(other),
+        }
+    }
+}
+
+/// Streams output of serial `port` connected to file, starting at byte
+/// `offset`, until `on_output` returns `false`.
+///
+/// The file is polled every `interval` through `Machine::read_serial`, so this
+/// works for remote hosts too. `on_output` is called after every poll, with
+/// empty chunk when guest wrote nothing. Returns offset following the last chunk.
+pub fn follow<M: Machine + ?Sized>(
+    machine: &M,
+    port: u32,
+    mut offset: u64,
+    interval: Duration,
+    on_output: &mut dyn FnMut(&[u8]) -> bool,
+) -> Result<u64> {
+    loop {
+        let chunk = machine.read_serial(port, offset)?;
+        offset += chunk.len() as u64;
+        if !on_output(&chunk) {
+            return Ok(offset);
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mode() {
+        assert!(SerialMode::File("/tmp/ttyS0.log".into()).validate().is_ok());
+        assert!(SerialMode::Pipe(String::new()).validate().is_err());
+        assert_eq!(
+            "file:C:/vms/ttyS0.log".parse::<SerialMode>().unwrap(),
+            SerialMode::File("C:/vms/ttyS0.log".into())
+        );
+        assert!("pipe:".parse::<SerialMode>().is_err());
+        assert!("tcp:2000".parse::<SerialMode>().is_err());
+        assert!(SerialMode::Other("tcpserver,2000".into())
+            .validate()
+            .is_err());
+    }
+}
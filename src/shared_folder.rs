@@ -0,0 +1,58 @@
+//! Host directories shared with guests.
+
+/// Host directory visible in guest under `name`.
+///
+/// ```
+/// use vmctrl::shared_folder::SharedFolder;
+///
+/// let folder = SharedFolder::new("src", "/home/ci/src")
+///     .read_only()
+///     .auto_mount();
+/// assert!(!folder.writable);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedFolder {
+    pub name: String,
+    pub host_path: String,
+    pub writable: bool,
+    /// Mounted by guest additions on boot, VMware Tools mount every folder.
+    pub auto_mount: bool,
+    /// Guest directory auto-mounted folder appears in, VirtualBox only.
+    pub mount_point: Option<String>,
+    /// Lasts until machine powers off, can only be added to running machine.
+    pub transient: bool,
+}
+
+impl SharedFolder {
+    pub fn new<N: Into<String>, P: Into<String>>(name: N, host_path: P) -> Self {
+        SharedFolder {
+            name: name.into(),
+            host_path: host_path.into(),
+            writable: true,
+            auto_mount: false,
+            mount_point: None,
+            transient: false,
+        }
+    }
+
+    pub fn read_only(mut self) -> Self {
+        self.writable = false;
+        self
+    }
+
+    pub fn auto_mount(mut self) -> Self {
+        self.auto_mount = true;
+        self
+    }
+
+    pub fn mount_point<T: Into<String>>(mut self, mount_point: T) -> Self {
+        self.auto_mount = true;
+        self.mount_point = Some(mount_point.into());
+        self
+    }
+
+    pub fn transient(mut self) -> Self {
+        self.transient = true;
+        self
+    }
+}
@@ -1,36 +1,386 @@
+use super::appliance::{ExportFormat, ImportOptions};
+use super::config::MachineConfig;
+use super::disk::{DiskSlot, DiskStore};
+use super::guest::{Credentials, GuestSession};
+use super::info::MachineInfo;
+use super::keyboard::KeyCombo;
+use super::network::{NicConfig, PortForward};
+use super::property::GuestProperty;
+use super::serial::SerialMode;
+use super::shared_folder::SharedFolder;
+use super::version::Version;
 use super::Driver;
-use super::Machine;
+use super::{Machine, MachineState};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
+use std::str::{self, FromStr};
+use std::time::Duration;
 
 use super::error::*;
 
-struct VmUri<'a> {
-    schema: &'a str,
-    path: &'a str,
+/// Machine location in form of RFC 3986 uri.
+///
+/// ```text
+/// ssh+vmware://user@host:22/vms/ubuntu.vmx?snapshot=clean&gu=user
+/// vmware:C:\vms\ubuntu.vmx
+/// vmware:/vms/ubuntu.vmx?T=player
+/// virtualbox:ubuntu-a
+/// ```
+///
+/// Parser is lenient about characters that should be percent-encoded
+/// (spaces, backslashes, stray `%`) and accepts legacy `ssh+vmware:host:/path`
+/// form, `Display` always emits the encoded form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmUri {
+    scheme: String,
+    user: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    path: String,
+    query: Vec<(String, String)>,
 }
 
-impl<'a> From<&'a str> for VmUri<'a> {
-    fn from(uri: &'a str) -> Self {
-        if let Some(pos) = uri.find(':') {
-            VmUri {
-                schema: &uri[0..pos],
-                path: &uri[pos + 1..],
+impl VmUri {
+    pub fn new<S: Into<String>, P: Into<String>>(scheme: S, path: P) -> Self {
+        VmUri {
+            scheme: scheme.into(),
+            user: None,
+            host: None,
+            port: None,
+            path: path.into(),
+            query: Vec::new(),
+        }
+    }
+
+    pub fn parse(uri: &str) -> Result<Self> {
+        let invalid = || Error::from(ErrorKind::InvalidUri(uri.into()));
+
+        let (scheme, rest) = match uri.find(':') {
+            // single letter is a windows drive, not a scheme.
+            Some(pos) if pos > 1 && is_scheme(&uri[..pos]) => (&uri[..pos], &uri[pos + 1..]),
+            _ => ("file", uri),
+        };
+
+        let (hier, query) = match rest.find('?') {
+            Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+            None => (rest, None),
+        };
+
+        let mut result = VmUri::new(scheme, "");
+
+        let path = if let Some(hier) = hier.strip_prefix("//") {
+            let end = hier.find('/').unwrap_or(hier.len());
+            result.parse_authority(&hier[..end]).ok_or_else(invalid)?;
+            &hier[end..]
+        } else {
+            match legacy_authority(scheme, hier) {
+                Some((authority, path)) => {
+                    result.parse_authority(authority).ok_or_else(invalid)?;
+                    path
+                }
+                None => hier,
             }
+        };
+
+        result.path = percent_decode(path).ok_or_else(invalid)?;
+        if result.host.is_some() && has_drive_prefix(&result.path[1.min(result.path.len())..]) {
+            result.path.remove(0);
+        }
+
+        if let Some(query) = query {
+            for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+                let (k, v) = match pair.find('=') {
+                    Some(pos) => (&pair[..pos], &pair[pos + 1..]),
+                    None => (pair, ""),
+                };
+                result.query.push((
+                    percent_decode(k).ok_or_else(invalid)?,
+                    percent_decode(v).ok_or_else(invalid)?,
+                ));
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn parse_authority(&mut self, authority: &str) -> Option<()> {
+        let (user, hostport) = match authority.rfind('@') {
+            Some(pos) => (Some(&authority[..pos]), &authority[pos + 1..]),
+            None => (None, authority),
+        };
+
+        let (host, port) = if hostport.starts_with('[') {
+            let end = hostport.find(']')?;
+            match &hostport[end + 1..] {
+                "" => (&hostport[..=end], None),
+                port => (&hostport[..=end], Some(port.strip_prefix(':')?)),
+            }
+        } else {
+            match hostport.rfind(':') {
+                Some(pos) => (&hostport[..pos], Some(&hostport[pos + 1..])),
+                None => (hostport, None),
+            }
+        };
+
+        self.user = match user {
+            Some(user) => Some(percent_decode(user)?),
+            None => None,
+        };
+        self.host = Some(percent_decode(host)?);
+        self.port = match port {
+            Some("") | None => None,
+            Some(port) => Some(port.parse().ok()?),
+        };
+        Some(())
+    }
+
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// Percent decoded path, windows drive paths come without leading `/`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> &[(String, String)] {
+        &self.query
+    }
+
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn with_authority<U, H>(mut self, user: Option<U>, host: H, port: Option<u16>) -> Self
+    where
+        U: Into<String>,
+        H: Into<String>,
+    {
+        self.user = user.map(Into::into);
+        self.host = Some(host.into());
+        self.port = port;
+        self
+    }
+
+    pub fn with_query(mut self, query: Vec<(String, String)>) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn with_query_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// Tunnels this uri through `transport`, e.g. `vmware:/vm.vmx` over `ssh://host`
+    /// becomes `ssh+vmware://host/vm.vmx`.
+    pub fn over(mut self, transport: &VmUri) -> Self {
+        self.scheme = format!("{}+{}", transport.scheme, self.scheme);
+        self.user = transport.user.clone();
+        self.host = transport.host.clone();
+        self.port = transport.port;
+        self
+    }
+}
+
+fn is_scheme(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(ch) if ch.is_ascii_alphabetic() => (),
+        _ => return false,
+    }
+    chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '+' || ch == '-' || ch == '.')
+}
+
+fn has_drive_prefix(path: &str) -> bool {
+    let b = path.as_bytes();
+    b.len() >= 2 && b[0].is_ascii_alphabetic() && b[1] == b':'
+}
+
+/// Host and path of `ssh+vmware:host:/path` and `ssh+vmware:host/path` forms
+/// used before uris had authority, recognized for remote schemes only.
+fn legacy_authority<'a>(scheme: &str, hier: &'a str) -> Option<(&'a str, &'a str)> {
+    if !scheme.contains('+') || hier.starts_with('/') {
+        return None;
+    }
+    let end = hier.find([':', '/'])?;
+    if end == 0 {
+        return None;
+    }
+    // `:` only separates host, `/` already belongs to the path.
+    let path = &hier[end..];
+    Some((&hier[..end], path.strip_prefix(':').unwrap_or(path)))
+}
+
+/// Decodes `%XX` sequences, `%` not followed by two hex digits stands for
+/// itself (`/vms/50%done.vmx`).
+fn percent_decode(s: &str) -> Option<String> {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let byte = b
+            .get(i + 1..i + 3)
+            .and_then(|hex| str::from_utf8(hex).ok())
+            .filter(|hex| hex.bytes().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let (b'%', Some(byte)) = (b[i], byte) {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(b[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn percent_encode(f: &mut fmt::Formatter, s: &str, extra: &[u8]) -> fmt::Result {
+    for &b in s.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~!$'()*,;".contains(&b) || extra.contains(&b) {
+            write!(f, "{}", b as char)?;
         } else {
-            VmUri {
-                schema: "file",
-                path: uri,
+            write!(f, "%{:02X}", b)?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for VmUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.scheme)?;
+        if let Some(ref host) = self.host {
+            f.write_str("//")?;
+            if let Some(ref user) = self.user {
+                percent_encode(f, user, b":&=+")?;
+                f.write_str("@")?;
+            }
+            if host.starts_with('[') {
+                f.write_str(host)?;
+            } else {
+                percent_encode(f, host, b"&=+")?;
+            }
+            if let Some(port) = self.port {
+                write!(f, ":{}", port)?;
             }
+            if !self.path.is_empty() && !self.path.starts_with('/') {
+                f.write_str("/")?;
+            }
+        }
+        percent_encode(f, &self.path, b":@/&=+")?;
+        for (i, (k, v)) in self.query.iter().enumerate() {
+            f.write_str(if i == 0 { "?" } else { "&" })?;
+            percent_encode(f, k, b":@/")?;
+            f.write_str("=")?;
+            percent_encode(f, v, b":@/")?;
         }
+        Ok(())
     }
 }
 
-type MachinePtr = Box<Machine>;
+impl FromStr for VmUri {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        VmUri::parse(s)
+    }
+}
+
+type MachinePtr = Box<dyn Machine>;
+
+/// Machines listed on a host, with errors for lines of hypervisor output that
+/// could not be parsed and were skipped, so that one odd machine does not hide
+/// the others.
+pub struct Listing<M> {
+    pub machines: Vec<M>,
+    pub skipped: Vec<Error>,
+}
+
+impl<M> Listing<M> {
+    /// Listing without skipped lines.
+    pub fn new(machines: Vec<M>) -> Self {
+        Listing {
+            machines,
+            skipped: Vec::new(),
+        }
+    }
+
+    pub fn map<N, F: FnMut(M) -> N>(self, f: F) -> Listing<N> {
+        Listing {
+            machines: self.machines.into_iter().map(f).collect(),
+            skipped: self.skipped,
+        }
+    }
+
+    /// Machines of listing, which fails only when every line was skipped.
+    pub fn into_machines(self) -> Result<Vec<M>> {
+        match self.skipped.into_iter().next() {
+            Some(e) if self.machines.is_empty() => Err(e),
+            _ => Ok(self.machines),
+        }
+    }
+}
 
 pub trait DriverFactory {
-    fn machine_for_uri(&self, uri: &str) -> Option<MachinePtr>;
+    /// Opens machine for parsed uri.
+    ///
+    /// Fails with `ErrorKind::InvalidUri` when `uri` is malformed for this factory,
+    /// any other error comes from the backend itself.
+    fn machine_for_uri(&self, uri: &VmUri) -> Result<MachinePtr>;
+
+    /// Lists running machines on host pointed by `uri`, path part is ignored.
+    fn list_running(&self, uri: &VmUri) -> Result<Listing<MachinePtr>>;
+
+    /// Lists all registered machines on host pointed by `uri`, path part is ignored.
+    fn list_all(&self, uri: &VmUri) -> Result<Listing<MachinePtr>> {
+        bail!(ErrorKind::NotSupported(format!(
+            "listing all machines at {}",
+            uri
+        )))
+    }
+
+    /// Registers machine whose configuration file is `uri` path.
+    fn register(&self, uri: &VmUri) -> Result<MachinePtr> {
+        bail!(ErrorKind::NotSupported(format!("registering {}", uri)))
+    }
+
+    /// Imports appliance whose file is `uri` path.
+    fn import_appliance(&self, uri: &VmUri, _options: &ImportOptions) -> Result<MachinePtr> {
+        bail!(ErrorKind::NotSupported(format!("importing {}", uri)))
+    }
+
+    /// Version of hypervisor tool on host pointed by `uri`, path part is ignored.
+    fn version(&self, uri: &VmUri) -> Result<Version> {
+        bail!(ErrorKind::NotSupported(format!(
+            "detecting version at {}",
+            uri
+        )))
+    }
+
+    /// Disk images on host `uri` points to.
+    fn disk_store(&self, uri: &VmUri) -> Result<Box<dyn DiskStore>> {
+        bail!(ErrorKind::NotSupported(format!(
+            "managing disks at {}",
+            uri
+        )))
+    }
 }
 
 #[derive(Clone, Default)]
@@ -40,24 +390,27 @@ pub struct DriverRepo {
 
 #[derive(Default)]
 struct DriverRepoImpl {
-    scheme: HashMap<&'static str, Box<DriverFactory>>,
+    scheme: HashMap<&'static str, Box<dyn DriverFactory>>,
 }
 
 impl DriverRepo {
-    pub fn register(&mut self, scheme: &'static str, factory: Box<DriverFactory>) {
+    /// Resolves uris of `scheme` with `factory`, replacing previous one.
+    ///
+    /// Shadows `Driver::register`, call that one as `Driver::register(&repo, path)`.
+    pub fn register(&self, scheme: &'static str, factory: Box<dyn DriverFactory>) {
         let s = &mut self.inner.borrow_mut().scheme;
 
         s.insert(scheme, factory);
     }
 
-    pub fn apply<Fn, T>(&self, scheme: &str, f: Fn) -> Option<T>
+    pub fn apply<Fn, T>(&self, scheme: &str, f: Fn) -> Result<T>
     where
-        Fn: FnOnce(&DriverFactory) -> Option<T>,
+        Fn: FnOnce(&dyn DriverFactory) -> Result<T>,
     {
         if let Some(driver_factory) = self.inner.borrow().scheme.get(scheme) {
             f(driver_factory.as_ref())
         } else {
-            None
+            bail!(ErrorKind::UnknownScheme(scheme.into()))
         }
     }
 }
@@ -65,26 +418,102 @@ impl DriverRepo {
 impl Driver for DriverRepo {
     type Machine = MachinePtr;
 
+    /// Listing needs host uri, see `list_running_at`.
     fn list_running(&self) -> Result<Vec<<Self as Driver>::Machine>> {
-        unimplemented!()
+        bail!(ErrorKind::NotSupported(
+            "listing machines without host uri, use list_running_at".into()
+        ))
+    }
+
+    /// Listing needs host uri, see `list_all_at`.
+    fn list_all(&self) -> Result<Vec<<Self as Driver>::Machine>> {
+        bail!(ErrorKind::NotSupported(
+            "listing machines without host uri, use list_all_at".into()
+        ))
+    }
+
+    fn register(&self, path: &str) -> Result<<Self as Driver>::Machine> {
+        self.register_at(path)
+    }
+
+    fn import_appliance(
+        &self,
+        path: &str,
+        options: &ImportOptions,
+    ) -> Result<<Self as Driver>::Machine> {
+        self.import_appliance_at(path, options)
     }
 
     fn from_path(&self, path: &str) -> Result<<Self as Driver>::Machine> {
-        let uri: VmUri = path.into();
+        let uri = VmUri::parse(path)?;
 
-        if let Some(machine) = self.apply(uri.schema, |driver| driver.machine_for_uri(uri.path)) {
-            Ok(machine)
-        } else {
-            bail!("schema not found")
-        }
+        self.apply(uri.scheme(), |driver| driver.machine_for_uri(&uri))
+    }
+}
+
+impl DriverRepo {
+    /// Lists running machines for host uri, e.g. `vmware:` or `ssh+virtualbox://host`.
+    pub fn list_running_at(&self, uri: &str) -> Result<Listing<MachinePtr>> {
+        let uri = VmUri::parse(uri)?;
+
+        self.apply(uri.scheme(), |driver| driver.list_running(&uri))
+    }
+
+    /// Lists all registered machines for host uri, running or not.
+    pub fn list_all_at(&self, uri: &str) -> Result<Listing<MachinePtr>> {
+        let uri = VmUri::parse(uri)?;
+
+        self.apply(uri.scheme(), |driver| driver.list_all(&uri))
+    }
+
+    /// Registers machine file, e.g. `ssh+virtualbox://host/vms/a/a.vbox`.
+    pub fn register_at(&self, uri: &str) -> Result<MachinePtr> {
+        let uri = VmUri::parse(uri)?;
+
+        self.apply(uri.scheme(), |driver| driver.register(&uri))
+    }
+
+    /// Imports appliance file, e.g. `virtualbox:/images/golden.ova`.
+    pub fn import_appliance_at(&self, uri: &str, options: &ImportOptions) -> Result<MachinePtr> {
+        let uri = VmUri::parse(uri)?;
+
+        self.apply(uri.scheme(), |driver| {
+            driver.import_appliance(&uri, options)
+        })
+    }
+
+    /// Disk images on host, e.g. `virtualbox:` or `ssh+vmware://host`.
+    pub fn disk_store_at(&self, uri: &str) -> Result<Box<dyn DiskStore>> {
+        let uri = VmUri::parse(uri)?;
+
+        self.apply(uri.scheme(), |driver| driver.disk_store(&uri))
+    }
+
+    /// Hypervisor tool version on host, e.g. `vmware:` or `ssh+virtualbox://host`.
+    pub fn version_at(&self, uri: &str) -> Result<Version> {
+        let uri = VmUri::parse(uri)?;
+
+        self.apply(uri.scheme(), |driver| driver.version(&uri))
     }
 }
 
-impl Machine for Box<Machine> {
+impl Machine for Box<dyn Machine> {
     fn name(&self) -> &str {
         (**self).name()
     }
 
+    fn uri(&self) -> VmUri {
+        (**self).uri()
+    }
+
+    fn state(&self) -> Result<MachineState> {
+        (**self).state()
+    }
+
+    fn info(&self) -> Result<MachineInfo> {
+        (**self).info()
+    }
+
     fn list_snapshots(&self) -> Result<Vec<String>> {
         (**self).list_snapshots()
     }
@@ -104,6 +533,130 @@ impl Machine for Box<Machine> {
     fn create_snapshot(&mut self, snapshot_name: &str) -> Result<()> {
         (**self).create_snapshot(snapshot_name)
     }
+
+    fn delete_snapshot(&mut self, snapshot_name: &str) -> Result<()> {
+        (**self).delete_snapshot(snapshot_name)
+    }
+
+    fn clone_to(
+        &self,
+        target: &str,
+        from_snapshot: Option<&str>,
+        linked: bool,
+    ) -> Result<Box<dyn Machine>> {
+        (**self).clone_to(target, from_snapshot, linked)
+    }
+
+    fn unregister(&mut self) -> Result<()> {
+        (**self).unregister()
+    }
+
+    fn delete(&mut self, with_disks: bool) -> Result<()> {
+        (**self).delete(with_disks)
+    }
+
+    fn configure(&mut self, config: &MachineConfig) -> Result<()> {
+        (**self).configure(config)
+    }
+
+    fn configure_nic(&mut self, slot: u32, config: &NicConfig) -> Result<()> {
+        (**self).configure_nic(slot, config)
+    }
+
+    fn port_forwards(&self, slot: u32) -> Result<Vec<PortForward>> {
+        (**self).port_forwards(slot)
+    }
+
+    fn add_port_forward(&mut self, slot: u32, rule: &PortForward) -> Result<()> {
+        (**self).add_port_forward(slot, rule)
+    }
+
+    fn remove_port_forward(&mut self, slot: u32, name: &str) -> Result<()> {
+        (**self).remove_port_forward(slot, name)
+    }
+
+    fn attach_disk(&mut self, slot: &DiskSlot, path: &str) -> Result<()> {
+        (**self).attach_disk(slot, path)
+    }
+
+    fn detach_disk(&mut self, slot: &DiskSlot) -> Result<()> {
+        (**self).detach_disk(slot)
+    }
+
+    fn shared_folders(&self) -> Result<Vec<SharedFolder>> {
+        (**self).shared_folders()
+    }
+
+    fn add_shared_folder(&mut self, folder: &SharedFolder) -> Result<()> {
+        (**self).add_shared_folder(folder)
+    }
+
+    fn remove_shared_folder(&mut self, name: &str) -> Result<()> {
+        (**self).remove_shared_folder(name)
+    }
+
+    fn set_shared_folders_enabled(&mut self, enabled: bool) -> Result<()> {
+        (**self).set_shared_folders_enabled(enabled)
+    }
+
+    fn screenshot(&self, path: &str) -> Result<Vec<u8>> {
+        (**self).screenshot(path)
+    }
+
+    fn send_text(&self, text: &str) -> Result<()> {
+        (**self).send_text(text)
+    }
+
+    fn send_keys(&self, combo: &KeyCombo) -> Result<()> {
+        (**self).send_keys(combo)
+    }
+
+    fn configure_serial(&mut self, port: u32, mode: &SerialMode) -> Result<()> {
+        (**self).configure_serial(port, mode)
+    }
+
+    fn serial_mode(&self, port: u32) -> Result<SerialMode> {
+        (**self).serial_mode(port)
+    }
+
+    fn read_serial(&self, port: u32, offset: u64) -> Result<Vec<u8>> {
+        (**self).read_serial(port, offset)
+    }
+
+    fn guest_property(&self, name: &str) -> Result<Option<String>> {
+        (**self).guest_property(name)
+    }
+
+    fn guest_properties(&self, pattern: &str) -> Result<Vec<GuestProperty>> {
+        (**self).guest_properties(pattern)
+    }
+
+    fn set_guest_property(&mut self, name: &str, value: &str) -> Result<()> {
+        (**self).set_guest_property(name, value)
+    }
+
+    fn delete_guest_property(&mut self, name: &str) -> Result<()> {
+        (**self).delete_guest_property(name)
+    }
+
+    fn wait_for_property(&self, pattern: &str, timeout: Duration) -> Result<Option<GuestProperty>> {
+        (**self).wait_for_property(pattern, timeout)
+    }
+
+    fn export(&self, path: &str, format: ExportFormat) -> Result<()> {
+        (**self).export(path, format)
+    }
+
+    fn guest_credentials(&self) -> Option<Credentials> {
+        (**self).guest_credentials()
+    }
+
+    fn guest_session<'a>(
+        &'a self,
+        credentials: &Credentials,
+    ) -> Result<Box<dyn GuestSession + 'a>> {
+        (**self).guest_session(credentials)
+    }
 }
 
 #[cfg(test)]
@@ -116,9 +669,16 @@ mod test {
     struct NopMachine(String);
 
     impl DriverFactory for Nop {
-        fn machine_for_uri(&self, path: &str) -> Option<MachinePtr> {
-            println!("me new {}", path);
-            Some(Box::new(NopMachine(path.into())))
+        fn machine_for_uri(&self, uri: &VmUri) -> Result<MachinePtr> {
+            if uri.path().is_empty() {
+                bail!(ErrorKind::InvalidUri(uri.to_string()))
+            }
+            println!("me new {}", uri);
+            Ok(Box::new(NopMachine(uri.path().into())))
+        }
+
+        fn list_running(&self, _uri: &VmUri) -> Result<Listing<MachinePtr>> {
+            Ok(Listing::new(vec![Box::new(NopMachine("smok1".into()))]))
         }
     }
 
@@ -127,6 +687,14 @@ mod test {
             self.0.as_ref()
         }
 
+        fn uri(&self) -> VmUri {
+            VmUri::new("nop", self.0.as_str())
+        }
+
+        fn state(&self) -> Result<MachineState> {
+            Ok(MachineState::Unknown)
+        }
+
         fn list_snapshots(&self) -> Result<Vec<String>> {
             Ok(Vec::new())
         }
@@ -146,16 +714,171 @@ mod test {
         fn create_snapshot(&mut self, _snapshot_name: &str) -> Result<()> {
             unimplemented!()
         }
+
+        fn delete_snapshot(&mut self, _snapshot_name: &str) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn guest_session<'a>(
+            &'a self,
+            _credentials: &Credentials,
+        ) -> Result<Box<dyn GuestSession + 'a>> {
+            unimplemented!()
+        }
     }
 
     #[test]
     fn test_repo() {
-        let mut repo = Box::new(DriverRepo::default());
+        let repo = Box::new(DriverRepo::default());
 
         repo.register("nop", Box::new(Nop));
 
         let m = repo.from_path("nop:smok1").unwrap();
 
-        println!("m={}", m.name())
+        println!("m={}", m.name());
+        assert_eq!(m.uri().to_string(), "nop:smok1");
+
+        let running = repo.list_running_at("nop:").unwrap();
+        assert_eq!(running.machines[0].name(), "smok1");
+        assert!(running.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_repo_errors() {
+        let repo = DriverRepo::default();
+
+        repo.register("nop", Box::new(Nop));
+
+        match repo.from_path("nope:smok1") {
+            Err(Error(ErrorKind::UnknownScheme(ref scheme), _)) => assert_eq!(scheme, "nope"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unknown scheme resolved"),
+        }
+
+        match repo.from_path("nop:") {
+            Err(Error(ErrorKind::InvalidUri(_), _)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("malformed uri resolved"),
+        }
+
+        match Driver::register(&repo, "nope:smok1.vmx") {
+            Err(Error(ErrorKind::UnknownScheme(ref scheme), _)) => assert_eq!(scheme, "nope"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unknown scheme registered"),
+        }
+
+        match Driver::list_running(&repo) {
+            Err(Error(ErrorKind::NotSupported(_), _)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("listed without host uri"),
+        }
+
+        match Driver::list_all(&repo) {
+            Err(Error(ErrorKind::NotSupported(_), _)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("listed without host uri"),
+        }
+    }
+
+    fn parse(uri: &str) -> VmUri {
+        VmUri::parse(uri).unwrap()
+    }
+
+    #[test]
+    fn test_parse_full() {
+        let uri = parse(
+            "ssh+vmware://prekucki@macx:2222/Users/vm/Ubuntu%2064-bit.vmx?snapshot=clean&gu=user",
+        );
+
+        assert_eq!(uri.scheme(), "ssh+vmware");
+        assert_eq!(uri.user(), Some("prekucki"));
+        assert_eq!(uri.host(), Some("macx"));
+        assert_eq!(uri.port(), Some(2222));
+        assert_eq!(uri.path(), "/Users/vm/Ubuntu 64-bit.vmx");
+        assert_eq!(uri.query_param("snapshot"), Some("clean"));
+        assert_eq!(uri.query_param("gu"), Some("user"));
+        assert_eq!(uri.query_param("gp"), None);
+    }
+
+    #[test]
+    fn test_parse_paths() {
+        let uri = parse("vmware:C:\\vms\\x.vmx");
+        assert_eq!(uri.scheme(), "vmware");
+        assert_eq!(uri.host(), None);
+        assert_eq!(uri.path(), "C:\\vms\\x.vmx");
+
+        let uri = parse("ssh+vmware://winhost/C:/vms/x.vmx");
+        assert_eq!(uri.host(), Some("winhost"));
+        assert_eq!(uri.path(), "C:/vms/x.vmx");
+
+        let uri = parse("vmware:/vms/a:b/x.vmx");
+        assert_eq!(uri.path(), "/vms/a:b/x.vmx");
+
+        let uri = parse("ssh+virtualbox://[::1]:22/ubuntu-a");
+        assert_eq!(uri.host(), Some("[::1]"));
+        assert_eq!(uri.port(), Some(22));
+        assert_eq!(uri.path(), "/ubuntu-a");
+
+        let uri = parse("/vms/x.vmx");
+        assert_eq!(uri.scheme(), "file");
+        assert_eq!(uri.path(), "/vms/x.vmx");
+
+        let uri = parse("vmware:/vms/50%done.vmx");
+        assert_eq!(uri.path(), "/vms/50%done.vmx");
+        assert_eq!(uri.to_string(), "vmware:/vms/50%25done.vmx");
+
+        // legacy form without `//`
+        let uri = parse("ssh+vmware:user@macx:/Users/vm/x.vmx");
+        assert_eq!(uri.user(), Some("user"));
+        assert_eq!(uri.host(), Some("macx"));
+        assert_eq!(uri.path(), "/Users/vm/x.vmx");
+        assert_eq!(uri.to_string(), "ssh+vmware://user@macx/Users/vm/x.vmx");
+
+        let uri = parse("ssh+vmware:host/vms/x.vmx");
+        assert_eq!(uri.host(), Some("host"));
+        assert_eq!(uri.path(), "/vms/x.vmx");
+        assert_eq!(uri.to_string(), "ssh+vmware://host/vms/x.vmx");
+
+        let uri = parse("ssh+vmware:winhost:C:\\vms\\x.vmx");
+        assert_eq!(uri.host(), Some("winhost"));
+        assert_eq!(uri.path(), "C:\\vms\\x.vmx");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for uri in &[
+            "vmware:/vms/%ff.vmx",
+            "ssh+vmware://host:port/x.vmx",
+            "ssh+vmware://host:99999/x.vmx",
+            "ssh+vmware://[::1/x.vmx",
+        ] {
+            match VmUri::parse(uri) {
+                Err(Error(ErrorKind::InvalidUri(_), _)) => (),
+                r => panic!("{} parsed as {:?}", uri, r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for uri in &[
+            "vmware:/Users/vm/Ubuntu 64-bit.vmx",
+            "vmware:C:\\vms\\x.vmx",
+            "ssh+vmware://user@winhost:2222/C:/vms/x.vmx?snapshot=clean&gu=a%26b",
+            "ssh+virtualbox://host/ubuntu-a?snapshot=clean",
+            "virtualbox:{c777e3e8-b82e-40a4-bf3d-550f0f0da9e9}",
+        ] {
+            let parsed = parse(uri);
+            let reparsed = parse(&parsed.to_string());
+            assert_eq!(parsed, reparsed);
+        }
+
+        let uri = VmUri::new("vmware", "/vms/x y.vmx")
+            .with_query_param("snapshot", "clean")
+            .over(&VmUri::new("ssh", "").with_authority(Some("u"), "h", None));
+        assert_eq!(
+            uri.to_string(),
+            "ssh+vmware://u@h/vms/x%20y.vmx?snapshot=clean"
+        );
     }
 }
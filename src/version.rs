@@ -0,0 +1,76 @@
+//! Versions reported by hypervisor tools, for adapting commands to them.
+
+use std::fmt;
+
+use regex::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Build or revision number, e.g. `161095` of VirtualBox `7.0.14r161095`.
+    pub build: Option<u64>,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+            build: None,
+        }
+    }
+
+    /// First version found in `text`, in `7.0.14r161095`, `6.1.38_Ubuntur153438`
+    /// or `1.17.0 build-21581411` form.
+    ///
+    /// ```
+    /// use vmctrl::version::Version;
+    ///
+    /// let version = Version::find("vmrun version 1.17.0 build-21581411").unwrap();
+    /// assert_eq!(version, Version { build: Some(21581411), ..Version::new(1, 17, 0) });
+    /// assert!(Version::find("7.0.14r161095").unwrap() >= Version::new(7, 0, 0));
+    /// ```
+    pub fn find(text: &str) -> Option<Self> {
+        lazy_static! {
+            static ref VERSION: Regex =
+                Regex::new(r"(\d+)\.(\d+)(?:\.(\d+))?(?:(?:_\w*?)?(?:r| build-)(\d+))?").unwrap();
+        }
+
+        let caps = VERSION.captures(text)?;
+        let number = |i| caps.get(i).and_then(|m| m.as_str().parse().ok());
+        Some(Version {
+            major: number(1)?,
+            minor: number(2)?,
+            patch: number(3).unwrap_or(0),
+            build: caps.get(4).and_then(|m| m.as_str().parse().ok()),
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        match self.build {
+            Some(build) => write!(f, " build {}", build),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let ubuntu = Version::find("6.1.38_Ubuntur153438").unwrap();
+        assert_eq!(ubuntu.build, Some(153438));
+        assert_eq!(ubuntu.to_string(), "6.1.38 build 153438");
+        assert_eq!(Version::find("VBoxManage 5.2"), Some(Version::new(5, 2, 0)));
+        assert!(Version::new(6, 1, 38) < Version::new(7, 0, 0));
+        assert_eq!(Version::find("Usage: vmrun"), None);
+    }
+}
@@ -1,14 +1,27 @@
-use super::command::{self, CommandRunner, Output};
-use super::uri::DriverFactory;
-use super::Machine;
-use std::borrow::Cow;
-use std::ffi::OsStr;
+use super::appliance::{ExportFormat, ImportOptions, ImportPlan};
+use super::command::{self, CommandRunner, Output, Tool};
+use super::config::{MachineConfig, MAX_BOOT_DEVICES};
+use super::disk::{DiskOptions, DiskSlot, DiskStore};
+use super::guest::{Credentials, GuestProcess, GuestSession};
+use super::info::{MachineInfo, MediumAttachment, NicAttachment, NicInfo, StorageController};
+use super::keyboard::KeyCombo;
+use super::network::{self, NicConfig, PortForward};
+use super::property::{self, GuestProperty};
+use super::serial::SerialMode;
+use super::shared_folder::SharedFolder;
+use super::uri::{DriverFactory, Listing, VmUri};
+use super::version::Version;
+use super::{Machine, MachineState};
+use std::ffi::{OsStr, OsString};
 use std::marker::PhantomData;
 use std::rc::Rc;
 
 use super::error::*;
 use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::str;
+use std::time::{Duration, Instant};
 
 pub struct Driver<Cmd: CommandRunner> {
     inner: Rc<DriverImpl<Cmd>>,
@@ -16,38 +29,163 @@ pub struct Driver<Cmd: CommandRunner> {
 
 struct DriverImpl<Cmd: CommandRunner> {
     command_runner: Cmd,
-    manage_command: Cow<'static, OsStr>,
+    manage_command: Tool,
+    guest_credentials: Option<Credentials>,
+    /// Detected from version on first use.
+    quoting: RefCell<Option<Quoting>>,
 }
 
 pub struct Factory<C: CommandRunner> {
+    manage_command: Tool,
+    guest_credentials: Option<Credentials>,
     marker: PhantomData<C>,
 }
 
+/// Locations of `VBoxManage` tried in order, the name is case-sensitive on
+/// Linux and some distributions ship only one spelling. The first one is the
+/// name `vmctrl-server` allows.
+const MANAGE_COMMANDS: &[&str] = &[
+    "vboxmanage",
+    "VBoxManage",
+    "/usr/local/bin/VBoxManage",
+    "/Applications/VirtualBox.app/Contents/MacOS/VBoxManage",
+    "C:\\Program Files\\Oracle\\VirtualBox\\VBoxManage.exe",
+];
+
 #[inline]
 pub fn factory<C: CommandRunner>() -> Factory<C> {
     Factory {
+        manage_command: Tool::discover("VMCTRL_VBOXMANAGE", MANAGE_COMMANDS, &["--version"]),
+        guest_credentials: None,
         marker: PhantomData,
     }
 }
 
+impl<C: CommandRunner> Factory<C> {
+    /// Overrides path to `VBoxManage` binary on target host, by default it is
+    /// taken from `VMCTRL_VBOXMANAGE` for local host or searched for.
+    pub fn with_command<P: Into<OsString>>(mut self, manage_command: P) -> Self {
+        self.manage_command = Tool::explicit(manage_command);
+        self
+    }
+
+    /// Guest account of machines opened by this driver, kept out of their uris.
+    pub fn with_guest_credentials(mut self, credentials: Credentials) -> Self {
+        self.guest_credentials = Some(credentials);
+        self
+    }
+}
+
 impl<C: CommandRunner> command::FromCommandRunner for Factory<C> {
     type Command = C;
     type Output = Driver<C>;
 
     fn from_cmd(&self, cmd: Self::Command) -> Self::Output {
-        Driver::<C>::from_cmd(cmd)
+        Driver {
+            inner: Rc::new(DriverImpl {
+                command_runner: cmd,
+                manage_command: self.manage_command.clone(),
+                guest_credentials: self.guest_credentials.clone(),
+                quoting: RefCell::new(None),
+            }),
+        }
     }
 }
 
 impl<C: CommandRunner> Driver<C> {
     pub fn from_cmd(cmd: C) -> Self {
-        Driver {
-            inner: Rc::new(DriverImpl {
-                command_runner: cmd,
-                manage_command: Cow::Borrowed("vboxmanage".as_ref()),
-            }),
+        command::FromCommandRunner::from_cmd(&factory(), cmd)
+    }
+
+    /// Registered (`all`) or running machines, lines of `VBoxManage list` that
+    /// cannot be parsed are skipped and reported in `Listing::skipped`.
+    pub fn list_vms(&self, all: bool) -> Result<Listing<MachineRef<C>>> {
+        let what = if all { "vms" } else { "runningvms" };
+        let (machines, skipped) = vmslist_parse_all(self.inner.run(["list", what])?);
+        Ok(Listing {
+            machines: machines
+                .into_iter()
+                .map(|(name, uuid)| self.machine(name, Some(uuid), Vec::new()))
+                .collect(),
+            skipped,
+        })
+    }
+
+    /// Shows what `import_appliance` would create, without importing.
+    pub fn plan_import(&self, path: &str, options: &ImportOptions) -> Result<ImportPlan> {
+        let mut args = import_args(path, options);
+        args.push("--dry-run".into());
+
+        import_plan_parse(self.inner.run(args)?)
+    }
+}
+
+fn import_args(path: &str, options: &ImportOptions) -> Vec<String> {
+    let mut vsys: Vec<String> = Vec::new();
+    if let Some(ref name) = options.name {
+        vsys.extend(vec!["--vmname".into(), name.clone()]);
+    }
+    if let Some(ref base_folder) = options.base_folder {
+        vsys.extend(vec!["--basefolder".into(), base_folder.clone()]);
+    }
+    if let Some(cpus) = options.cpus {
+        vsys.extend(vec!["--cpus".into(), cpus.to_string()]);
+    }
+    if let Some(memory_mb) = options.memory_mb {
+        vsys.extend(vec!["--memory".into(), memory_mb.to_string()]);
+    }
+    if options.accept_eulas {
+        vsys.extend(vec!["--eula".into(), "accept".into()]);
+    }
+
+    let mut args = vec!["import".to_string(), path.to_string()];
+    if !vsys.is_empty() {
+        args.extend(vec!["--vsys".into(), "0".into()]);
+        args.extend(vsys);
+    }
+    args
+}
+
+/// Parses `vboxmanage import --dry-run` listing of first virtual system.
+fn import_plan_parse<I: IntoIterator<Item = String>>(lines: I) -> Result<ImportPlan> {
+    lazy_static! {
+        static ref NAME: Regex = Regex::new("VM name[^\"]*\"([^\"]*)\"").unwrap();
+        static ref OS_TYPE: Regex = Regex::new("OS type[^\"]*\"([^\"]*)\"").unwrap();
+        static ref CPUS: Regex = Regex::new("Number of CPUs.*?(\\d+)\\s*$").unwrap();
+        static ref MEMORY: Regex = Regex::new("Guest memory.*?(\\d+) MB").unwrap();
+        static ref SETTINGS: Regex = Regex::new("settings file name[^\"]*\"([^\"]*)\"").unwrap();
+        static ref DISK: Regex =
+            Regex::new("Hard disk image:.*target path=(.*?)(?:, controller=.*)?$").unwrap();
+    }
+
+    let mut plan = ImportPlan::default();
+    let mut name = None;
+    for line in lines {
+        if line.starts_with("Virtual system 1:") {
+            break;
+        }
+        if let Some(caps) = NAME.captures(&line) {
+            name = Some(caps[1].to_string());
+        } else if let Some(caps) = OS_TYPE.captures(&line) {
+            plan.os_type = Some(caps[1].into());
+        } else if let Some(caps) = CPUS.captures(&line) {
+            plan.cpus = caps[1].parse().ok();
+        } else if let Some(caps) = MEMORY.captures(&line) {
+            plan.memory_mb = caps[1].parse().ok();
+        } else if let Some(caps) = SETTINGS.captures(&line) {
+            plan.settings_file = Some(caps[1].into());
+        } else if let Some(caps) = DISK.captures(&line) {
+            plan.disks.push(caps[1].into());
         }
     }
+
+    match name {
+        Some(name) => {
+            plan.name = name;
+            Ok(plan)
+        }
+        None => bail!(ErrorKind::MissingSummary),
+    }
 }
 
 impl<C: CommandRunner> DriverImpl<C> {
@@ -56,9 +194,51 @@ impl<C: CommandRunner> DriverImpl<C> {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        Ok(self
-            .command_runner
-            .run_with_output(&self.manage_command, args)?)
+        self.manage_command
+            .run_with_output(&self.command_runner, args)
+    }
+
+    fn run_with_input<I, S>(&self, args: I, input: &str) -> Result<Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.manage_command
+            .run_with_input(&self.command_runner, args, input)
+    }
+
+    /// Parses `VBoxManage --version`, whose last line holds the version after
+    /// any kernel module warnings.
+    fn version(&self) -> Result<Version> {
+        let line = self
+            .run(["--version"])?
+            .into_iter()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .chain_err(|| ErrorKind::MissingSummary)?;
+        Version::find(&line).chain_err(|| ErrorKind::InvalidResponse(line.clone()))
+    }
+
+    /// Quoting of `--machinereadable` output, escaped as by current releases
+    /// when version cannot be detected.
+    fn quoting(&self) -> Quoting {
+        if let Some(quoting) = *self.quoting.borrow() {
+            return quoting;
+        }
+        let quoting = self
+            .version()
+            .map(|version| Quoting::for_version(&version))
+            .unwrap_or(Quoting::Escaped);
+        *self.quoting.borrow_mut() = Some(quoting);
+        quoting
+    }
+
+    fn machine_uri(&self, path: &str, query: &[(String, String)]) -> VmUri {
+        let uri = VmUri::new("virtualbox", path).with_query(query.to_vec());
+        match self.command_runner.transport() {
+            Some(transport) => uri.over(&transport),
+            None => uri,
+        }
     }
 }
 
@@ -66,6 +246,7 @@ pub struct MachineRef<Cmd: CommandRunner> {
     driver_ref: Rc<DriverImpl<Cmd>>,
     path: String,
     uuid: Option<String>,
+    query: Vec<(String, String)>,
 }
 
 impl<Cmd: CommandRunner> Driver<Cmd> {
@@ -73,45 +254,567 @@ impl<Cmd: CommandRunner> Driver<Cmd> {
         &self,
         path: IntoStr,
         uuid: Option<String>,
+        query: Vec<(String, String)>,
     ) -> MachineRef<Cmd> {
         MachineRef {
             driver_ref: self.inner.clone(),
             path: path.into(),
-            uuid: uuid.map(|v| v.into()),
+            uuid,
+            query,
         }
     }
 }
 
-impl<Cmd: CommandRunner> super::Driver for Driver<Cmd> {
+impl<Cmd: CommandRunner + 'static> super::Driver for Driver<Cmd> {
     type Machine = MachineRef<Cmd>;
 
     fn list_running(&self) -> Result<Vec<MachineRef<Cmd>>> {
-        self.inner
-            .run(&["list", "runningvms"])?
-            .into_iter()
-            .map(|line| {
-                vmslist_parse(line.as_ref())
-                    .map(|(name, uuid)| self.machine(name, Some(uuid.into())))
-            }).collect()
+        self.list_vms(false)?.into_machines()
+    }
+
+    fn list_all(&self) -> Result<Vec<MachineRef<Cmd>>> {
+        self.list_vms(true)?.into_machines()
     }
 
     fn from_path(&self, path: &str) -> Result<MachineRef<Cmd>> {
-        Ok(self.machine(path, None))
+        Ok(self.machine(path, None, Vec::new()))
+    }
+
+    fn import_appliance(&self, path: &str, options: &ImportOptions) -> Result<MachineRef<Cmd>> {
+        let plan = self.plan_import(path, options)?;
+        let _ = self.inner.manage_command.run_with_progress(
+            &self.inner.command_runner,
+            import_args(path, options),
+            &mut |chunk| options.report(chunk),
+        )?;
+        Ok(self.machine(plan.name, None, Vec::new()))
+    }
+
+    fn register(&self, path: &str) -> Result<MachineRef<Cmd>> {
+        let _ = self.inner.run(["registervm", path])?;
+        let settings = self.inner.command_runner.read_file(path)?;
+        let (name, uuid) = vbox_machine_parse(&settings)?;
+        Ok(self.machine(name, Some(uuid.into()), Vec::new()))
+    }
+
+    fn version(&self) -> Result<Version> {
+        self.inner.version()
     }
 }
 
+/// Name and uuid from `<Machine>` element of `.vbox` settings file.
+fn vbox_machine_parse(settings: &str) -> Result<(&str, &str)> {
+    lazy_static! {
+        static ref NAME: Regex = Regex::new("<Machine\\s[^>]*\\bname=\"([^\"]*)\"").unwrap();
+        static ref UUID: Regex = Regex::new("<Machine\\s[^>]*\\buuid=\"([^\"]*)\"").unwrap();
+    }
+
+    match (NAME.captures(settings), UUID.captures(settings)) {
+        (Some(name), Some(uuid)) => {
+            Ok((name.get(1).unwrap().as_str(), uuid.get(1).unwrap().as_str()))
+        }
+        _ => bail!(ErrorKind::InvalidResponse(
+            "missing <Machine> element".into()
+        )),
+    }
+}
+
+/// Hard disk from `vboxmanage list hdds`.
+#[derive(Debug, Default)]
+struct Medium {
+    uuid: String,
+    parent: Option<String>,
+    /// Names and uuids of machines using the medium, in any snapshot.
+    vms: Vec<(String, String)>,
+}
+
+fn hdds_parse<I: IntoIterator<Item = String>>(lines: I) -> Vec<Medium> {
+    lazy_static! {
+        static ref SNAPSHOTS: Regex = Regex::new(r"\[[^\]]*\]").unwrap();
+        static ref VM: Regex = Regex::new(r"\s*(.+?) \(UUID: ([0-9a-fA-F-]+)\)").unwrap();
+    }
+
+    let mut media = Vec::new();
+    let mut current: Option<Medium> = None;
+    let mut in_use = String::new();
+
+    let mut finish = |medium: Option<Medium>, in_use: &mut String| {
+        if let Some(mut medium) = medium {
+            let in_use_vms = SNAPSHOTS.replace_all(in_use, "");
+            medium.vms = VM
+                .captures_iter(&in_use_vms)
+                .map(|caps| (caps[1].trim().to_string(), caps[2].to_string()))
+                .collect();
+            media.push(medium);
+        }
+        in_use.clear();
+    };
+
+    for line in lines {
+        if line.trim().is_empty() {
+            finish(current.take(), &mut in_use);
+            continue;
+        }
+        // continuation of "In use by VMs:" list.
+        if line.starts_with(char::is_whitespace) {
+            in_use.push('\n');
+            in_use.push_str(&line);
+            continue;
+        }
+        let (key, value) = match line.find(':') {
+            Some(pos) => (&line[..pos], line[pos + 1..].trim()),
+            None => continue,
+        };
+        match key {
+            "UUID" => {
+                finish(current.take(), &mut in_use);
+                current = Some(Medium {
+                    uuid: value.into(),
+                    ..Medium::default()
+                });
+            }
+            "Parent UUID" if value != "base" => {
+                if let Some(ref mut medium) = current {
+                    medium.parent = Some(value.into());
+                }
+            }
+            "In use by VMs" => in_use.push_str(value),
+            _ => (),
+        }
+    }
+    finish(current.take(), &mut in_use);
+
+    media
+}
+
+/// Parses `"name" {uuid}` line of `list vms`.
+///
+/// Names are printed unescaped, so everything between the first and the last
+/// quote belongs to the name, quotes included.
 fn vmslist_parse(line: &str) -> Result<(&str, &str)> {
+    let invalid = || Error::from(ErrorKind::InvalidResponse(line.into()));
+    let (name, uuid) = line.rsplit_once(' ').ok_or_else(invalid)?;
+    let uuid_valid = uuid.len() > 2
+        && uuid.starts_with('{')
+        && uuid.ends_with('}')
+        && uuid[1..uuid.len() - 1]
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() || c == '-');
+    let name = name
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'));
+    match name {
+        Some(name) if uuid_valid => Ok((name, uuid)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Names and uuids of `list vms` output, joining names that span several lines.
+///
+/// Lines that cannot be parsed are skipped and returned as errors, so that one
+/// odd machine does not hide the others.
+fn vmslist_parse_all<I: IntoIterator<Item = String>>(
+    lines: I,
+) -> (Vec<(String, String)>, Vec<Error>) {
+    let mut machines = Vec::new();
+    let mut skipped = Vec::new();
+    let mut pending: Option<String> = None;
+
+    for line in lines {
+        let line = match pending.take() {
+            Some(mut head) => {
+                head.push('\n');
+                head.push_str(&line);
+                head
+            }
+            None => line,
+        };
+        match vmslist_parse(&line) {
+            Ok((name, uuid)) => machines.push((name.to_string(), uuid.to_string())),
+            Err(_) if line.starts_with('"') && !line.ends_with('}') => pending = Some(line),
+            Err(e) => skipped.push(e),
+        }
+    }
+    if let Some(line) = pending {
+        skipped.push(ErrorKind::InvalidResponse(line).into());
+    }
+    (machines, skipped)
+}
+
+/// How `--machinereadable` output quotes strings: VirtualBox 6.1 and newer
+/// escape `"`, `\` and newline with backslash, older versions print them raw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    Escaped,
+    Raw,
+}
+
+impl Quoting {
+    fn for_version(version: &Version) -> Self {
+        if *version >= Version::new(6, 1, 0) {
+            Quoting::Escaped
+        } else {
+            Quoting::Raw
+        }
+    }
+}
+
+/// Reads quoted string at start of `s`, returns it unescaped with the text
+/// following its closing quote.
+///
+/// Escaped backslash sequences other than `\"`, `\\` and `\n` are kept as
+/// they are; raw strings end at the next quote.
+fn quoted_parse(s: &str, quoting: Quoting) -> Option<(String, &str)> {
+    if quoting == Quoting::Raw {
+        let (value, rest) = s.strip_prefix('"')?.split_once('"')?;
+        return Some((value.to_string(), rest));
+    }
+    let mut value = String::new();
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return Some((value, &s[i + 2..])),
+            '\\' => match chars.clone().next() {
+                Some((_, escaped @ '"')) | Some((_, escaped @ '\\')) => {
+                    value.push(escaped);
+                    let _ = chars.next();
+                }
+                Some((_, 'n')) => {
+                    value.push('\n');
+                    let _ = chars.next();
+                }
+                _ => value.push(ch),
+            },
+            ch => value.push(ch),
+        }
+    }
+    None
+}
+
+/// Quoted value filling the rest of line, raw value ends at the last quote
+/// and keeps quotes inside.
+fn quoted_value_parse(s: &str, quoting: Quoting) -> Option<String> {
+    match quoting {
+        Quoting::Escaped => match quoted_parse(s, quoting)? {
+            (value, "") => Some(value),
+            _ => None,
+        },
+        Quoting::Raw => s.strip_prefix('"')?.strip_suffix('"').map(String::from),
+    }
+}
+
+/// Parses `key="value"` line of `--machinereadable` output.
+///
+/// Keys may be quoted (`"SATA-0-0"="..."`), values may be unquoted (`memory=1024`).
+/// Quoted strings are unescaped as by `quoted_parse`.
+fn prop_parse(line: &str, quoting: Quoting) -> Result<(String, String)> {
+    let invalid = || Error::from(ErrorKind::InvalidResponse(line.into()));
+    let (key, rest) = if line.starts_with('"') {
+        quoted_parse(line, quoting).ok_or_else(invalid)?
+    } else {
+        let (key, _) = line.split_once('=').ok_or_else(invalid)?;
+        if key.is_empty() || key.contains(|c: char| c.is_whitespace() || c == '"') {
+            return Err(invalid());
+        }
+        (key.to_string(), &line[key.len()..])
+    };
+    let value = rest.strip_prefix('=').ok_or_else(invalid)?;
+    let value = if value.starts_with('"') {
+        quoted_value_parse(value, quoting).ok_or_else(invalid)?
+    } else if value.contains('"') {
+        return Err(invalid());
+    } else {
+        value.to_string()
+    };
+    Ok((key, value))
+}
+
+/// Whether quoted value of `line` is not closed and continues on next line,
+/// as raw multi-line values (e.g. descriptions) of old VirtualBox do.
+fn value_continues(line: &str, quoting: Quoting) -> bool {
+    match line.split_once("=\"") {
+        Some((_, value)) => quoting == Quoting::Raw && !value.ends_with('"'),
+        None => false,
+    }
+}
+
+/// Parses `showvminfo --machinereadable` output, whose lines that cannot be
+/// parsed (e.g. of newer VirtualBox) are skipped, see `props_parse_all`.
+fn props_parse<I: IntoIterator<Item = String>>(
+    lines: I,
+    quoting: Quoting,
+) -> Vec<(String, String)> {
+    props_parse_all(lines, quoting).0
+}
+
+/// Parses whole `--machinereadable` output, joining quoted values that span
+/// several lines.
+///
+/// Lines that cannot be parsed are skipped and returned as errors.
+fn props_parse_all<I: IntoIterator<Item = String>>(
+    lines: I,
+    quoting: Quoting,
+) -> (Vec<(String, String)>, Vec<Error>) {
+    let mut props = Vec::new();
+    let mut skipped = Vec::new();
+    let mut pending: Option<String> = None;
+
+    for line in lines {
+        let line = match pending.take() {
+            Some(mut head) => {
+                head.push('\n');
+                head.push_str(&line);
+                head
+            }
+            None => line,
+        };
+        match prop_parse(&line, quoting) {
+            Ok(prop) => props.push(prop),
+            Err(_) if value_continues(&line, quoting) => pending = Some(line),
+            Err(e) => skipped.push(e),
+        }
+    }
+    if let Some(line) = pending {
+        skipped.push(ErrorKind::InvalidResponse(line).into());
+    }
+    (props, skipped)
+}
+
+fn nic_attachment_parse(attachment: &str) -> NicAttachment {
+    match attachment {
+        "none" | "null" => NicAttachment::None,
+        "nat" => NicAttachment::Nat,
+        "natnetwork" => NicAttachment::NatNetwork,
+        "bridged" => NicAttachment::Bridged,
+        "hostonly" => NicAttachment::HostOnly,
+        "intnet" => NicAttachment::Internal,
+        "generic" => NicAttachment::Generic,
+        other => NicAttachment::Other(other.into()),
+    }
+}
+
+/// Attachment as spelled by `modifyvm --nicN`.
+fn nic_attachment_arg(attachment: &NicAttachment) -> &str {
+    match *attachment {
+        NicAttachment::Internal => "intnet",
+        ref other => other.as_str(),
+    }
+}
+
+/// `modifyvm` option naming network of `attachment`, without slot number.
+fn nic_network_option(attachment: &NicAttachment) -> Result<&'static str> {
+    match *attachment {
+        NicAttachment::Bridged => Ok("--bridgeadapter"),
+        NicAttachment::HostOnly => Ok("--hostonlyadapter"),
+        NicAttachment::Internal => Ok("--intnet"),
+        NicAttachment::NatNetwork => Ok("--nat-network"),
+        ref other => bail!(ErrorKind::InvalidConfig(format!(
+            "{} adapter has no network",
+            other
+        ))),
+    }
+}
+
+/// NAT rules of adapter in `slot`, listed as `Forwarding(N)` after its `nicN` entry.
+fn forwardings_parse(props: &[(String, String)], slot: u32) -> Result<Vec<PortForward>> {
+    let nic_key = format!("nic{}", slot);
+    let mut in_slot = false;
+    let mut rules = Vec::new();
+
+    for (key, value) in props {
+        if key
+            .strip_prefix("nic")
+            .is_some_and(|n| n.parse::<u32>().is_ok())
+        {
+            in_slot = *key == nic_key;
+        } else if in_slot && key.starts_with("Forwarding(") {
+            rules.push(
+                value
+                    .parse()
+                    .chain_err(|| ErrorKind::InvalidResponse(value.clone()))?,
+            );
+        }
+    }
+    Ok(rules)
+}
+
+/// Longest single `guestproperty wait`, a property set between waits is noticed
+/// this late at most.
+const PROPERTY_WAIT_SLICE: Duration = Duration::from_secs(5);
+
+/// Property from `guestproperty enumerate` or `wait` output, in the
+/// `Name: x, value: y, ...` format or `x = 'y' @ time [flags]` of VirtualBox 7.
+fn property_parse(line: &str) -> Option<GuestProperty> {
+    lazy_static! {
+        static ref OLD: Regex =
+            Regex::new(r"^Name: (.+?), value: (.*?)(?:, timestamp: \d+)?, flags: ?.*$").unwrap();
+        static ref NEW: Regex = Regex::new(r"^(\S+) = '(.*)'(?: @ \S+)?(?: \[[^\]]*\])?$").unwrap();
+    }
+
+    let captures = OLD.captures(line).or_else(|| NEW.captures(line))?;
+    Some(GuestProperty::new(&captures[1], &captures[2]))
+}
+
+/// Default I/O base and IRQ of `uart1` to `uart4`, as in the VirtualBox GUI.
+const UART_DEFAULTS: [(&str, &str); 4] = [
+    ("0x3f8", "4"),
+    ("0x2f8", "3"),
+    ("0x3e8", "4"),
+    ("0x2e8", "3"),
+];
+
+/// Host end of `uartN`, listed as `uartmodeN="file,/path"` when port is on.
+fn serial_mode_parse(props: &[(String, String)], port: u32) -> SerialMode {
+    let get = |key: String| {
+        props
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v.as_str())
+    };
+    match get(format!("uart{}", port)) {
+        None | Some("off") => return SerialMode::Off,
+        _ => {}
+    }
+    let mode = get(format!("uartmode{}", port)).unwrap_or("disconnected");
+    match mode.split_once(',') {
+        Some(("file", path)) => SerialMode::File(path.into()),
+        Some(("server", path)) => SerialMode::Pipe(path.into()),
+        _ if mode == "disconnected" => SerialMode::Disconnected,
+        _ => SerialMode::Other(mode.into()),
+    }
+}
+
+/// Attaches hard disk `medium` to `slot`, `none` empties it.
+fn storageattach_args(vmid: &str, slot: &DiskSlot, medium: &str) -> Vec<String> {
+    vec![
+        "storageattach".into(),
+        vmid.into(),
+        "--storagectl".into(),
+        slot.controller.clone(),
+        "--port".into(),
+        slot.port.to_string(),
+        "--device".into(),
+        slot.device.to_string(),
+        "--type".into(),
+        "hdd".into(),
+        "--medium".into(),
+        medium.into(),
+    ]
+}
+
+/// Parses `Name: 'x', Host path: '/y' (machine mapping), writable, auto-mount`
+/// lines of human readable `showvminfo`, machine readable output lacks flags.
+fn shared_folders_parse<I: IntoIterator<Item = String>>(lines: I) -> Vec<SharedFolder> {
     lazy_static! {
-        static ref RE: Regex = Regex::new("\"([^\"]*)\"\\s+(\\{[a-zA-Z0-9-]*\\})").unwrap();
+        static ref RE: Regex = Regex::new(
+            r"^Name: '(.*?)', Host path: '(.*)' \((machine|transient|global) mapping\)(.*)$"
+        )
+        .unwrap();
     }
 
-    if let Some(caps) = RE.captures(line) {
-        if let (Some(name), Some(uuid)) = (caps.get(1), caps.get(2)) {
-            return Ok((name.as_str(), uuid.as_str()));
+    let mut folders = Vec::new();
+    for line in lines {
+        let caps = match RE.captures(line.trim()) {
+            Some(caps) => caps,
+            None => continue,
+        };
+        let mut folder = SharedFolder::new(&caps[1], &caps[2]);
+        folder.transient = &caps[3] == "transient";
+        for flag in caps[4].split(", ").map(|flag| flag.trim()) {
+            match flag {
+                "readonly" => folder.writable = false,
+                "auto-mount" => folder.auto_mount = true,
+                _ => {
+                    if let Some(mount_point) = flag.strip_prefix("mount-point: ") {
+                        folder.mount_point = Some(mount_point.trim_matches('\'').into());
+                    }
+                }
+            }
         }
+        folders.push(folder);
+    }
+    folders
+}
+
+fn info_parse(props: Vec<(String, String)>) -> MachineInfo {
+    let props: HashMap<String, String> = props.into_iter().collect();
+    let get = |key: &str| props.get(key).cloned();
+
+    let mut info = MachineInfo::new(get("name").unwrap_or_default());
+    info.uuid = get("UUID");
+    info.os_type = get("ostype");
+    info.cpus = get("cpus").and_then(|v| v.parse().ok());
+    info.memory_mb = get("memory").and_then(|v| v.parse().ok());
+    info.state = get("VMState").map_or(MachineState::Unknown, |v| vmstate_parse(&v));
+    info.snapshot_folder = get("SnapFldr").filter(|v| v != "none");
+    info.current_snapshot = get("CurrentSnapshotName");
+
+    let mut slot = 1;
+    while let Some(attachment) = get(&format!("nic{}", slot)) {
+        let network = [
+            "bridgeadapter",
+            "hostonlyadapter",
+            "intnet",
+            "nat-network",
+            "generic",
+        ]
+        .iter()
+        .filter_map(|prefix| get(&format!("{}{}", prefix, slot)))
+        .next();
+        info.nics.push(NicInfo {
+            slot,
+            attachment: nic_attachment_parse(&attachment),
+            network,
+            mac: get(&format!("macaddress{}", slot)),
+            cable_connected: get(&format!("cableconnected{}", slot)).map(|v| v == "on"),
+        });
+        slot += 1;
     }
 
-    bail!("invalid")
+    let mut idx = 0;
+    while let Some(name) = get(&format!("storagecontrollername{}", idx)) {
+        let prefix = format!("{}-", name);
+        let mut attachments: Vec<MediumAttachment> = props
+            .iter()
+            .filter(|(_, medium)| medium.as_str() != "none")
+            .filter_map(|(key, medium)| {
+                let mut it = key.strip_prefix(&prefix)?.split('-');
+                let port = it.next()?.parse().ok()?;
+                let device = it.next()?.parse().ok()?;
+                if it.next().is_some() {
+                    return None;
+                }
+                Some(MediumAttachment {
+                    port,
+                    device,
+                    medium: medium.clone(),
+                    uuid: get(&format!("{}-ImageUUID-{}-{}", name, port, device)),
+                })
+            })
+            .collect();
+        attachments.sort_by_key(|a| (a.port, a.device));
+
+        info.storage.push(StorageController {
+            controller_type: get(&format!("storagecontrollertype{}", idx)),
+            name,
+            attachments,
+        });
+        idx += 1;
+    }
+
+    info
+}
+
+fn vmstate_parse(state: &str) -> MachineState {
+    match state {
+        "running" | "starting" | "stopping" | "saving" | "restoring" | "teleporting"
+        | "livesnapshotting" => MachineState::Running,
+        "poweroff" => MachineState::PoweredOff,
+        "paused" => MachineState::Paused,
+        "saved" => MachineState::Suspended,
+        "aborted" => MachineState::Aborted,
+        _ => MachineState::Unknown,
+    }
 }
 
 pub fn init() {}
@@ -122,6 +825,170 @@ fn test_vmslist_parse() {
 
     assert_eq!(a, "ubuntu-a");
     assert_eq!(b, "{c777e3e8-b82e-40a4-bf3d-550f0f0da9e9}");
+
+    let output = "\"say \"hi\" ż\" {00000000-0000-4000-8000-000000000001}\n\
+                  garbage\n\
+                  \"two\n\
+                  lines\" {00000000-0000-4000-8000-000000000002}\n\
+                  \"<inaccessible>\" {00000000-0000-4000-8000-00000000000x}";
+    let (machines, skipped) = vmslist_parse_all(output.lines().map(|s| s.to_string()));
+    let names: Vec<&str> = machines.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["say \"hi\" ż", "two\nlines"]);
+    assert_eq!(skipped.len(), 2);
+    assert!(Listing::<()> {
+        machines: Vec::new(),
+        skipped,
+    }
+    .into_machines()
+    .is_err());
+}
+
+#[test]
+fn test_prop_parse() {
+    let prop = |k: &str, v: &str| (k.to_string(), v.to_string());
+    let escaped = |line: &str| prop_parse(line, Quoting::Escaped);
+    let raw = |line: &str| prop_parse(line, Quoting::Raw);
+    let (k, v) = escaped("VMState=\"poweroff\"").unwrap();
+
+    assert_eq!(k, "VMState");
+    assert_eq!(vmstate_parse(&v), MachineState::PoweredOff);
+    assert_eq!(escaped("memory=1024").unwrap(), prop("memory", "1024"));
+    assert_eq!(
+        escaped("\"SATA-0-0\"=\"/vms/a.vdi\"").unwrap(),
+        prop("SATA-0-0", "/vms/a.vdi")
+    );
+    assert_eq!(
+        raw("\"SATA-0-0\"=\"/vms/a.vdi\"").unwrap(),
+        prop("SATA-0-0", "/vms/a.vdi")
+    );
+    assert_eq!(
+        escaped("Forwarding(0)=\"ssh,tcp,,2222,,22\"").unwrap(),
+        prop("Forwarding(0)", "ssh,tcp,,2222,,22")
+    );
+    assert!(escaped("garbage").is_err());
+
+    // escaped by VirtualBox 6.1+, raw before.
+    assert_eq!(
+        escaped(r#"SnapshotName-1="say \"hi\"\nand \\ bye""#).unwrap(),
+        prop("SnapshotName-1", "say \"hi\"\nand \\ bye")
+    );
+    assert_eq!(
+        raw(r#"SnapshotName-1="say \"hi\"\nand \\ bye""#).unwrap(),
+        prop("SnapshotName-1", r#"say \"hi\"\nand \\ bye"#)
+    );
+    assert_eq!(
+        raw(r#"SnapshotName="say "hi"""#).unwrap(),
+        prop("SnapshotName", "say \"hi\"")
+    );
+    assert!(escaped(r#"SnapshotName="say "hi"""#).is_err());
+    assert_eq!(
+        escaped(r#"CfgFile="C:\vms\a.vbox""#).unwrap(),
+        prop("CfgFile", r"C:\vms\a.vbox")
+    );
+    assert_eq!(
+        raw(r#"CfgFile="C:\vms\new\""#).unwrap(),
+        prop("CfgFile", r"C:\vms\new\")
+    );
+    assert!(escaped(r#"name="open \""#).is_err());
+
+    let output = "name=\"a\"\nnot a property\ndescription=\"one\ntwo\"\nVMState=\"running\"";
+    let (props, skipped) = props_parse_all(output.lines().map(|s| s.to_string()), Quoting::Raw);
+    assert_eq!(
+        props,
+        vec![
+            prop("name", "a"),
+            prop("description", "one\ntwo"),
+            prop("VMState", "running"),
+        ]
+    );
+    assert_eq!(skipped.len(), 1);
+    let (_, skipped) = props_parse_all(output.lines().map(|s| s.to_string()), Quoting::Escaped);
+    assert_eq!(skipped.len(), 3);
+    let (_, skipped) = props_parse_all(vec!["SnapshotName=\"open".to_string()], Quoting::Raw);
+    assert_eq!(skipped.len(), 1);
+
+    assert_eq!(Quoting::for_version(&Version::new(6, 0, 24)), Quoting::Raw);
+    assert_eq!(
+        Quoting::for_version(&Version::new(6, 1, 0)),
+        Quoting::Escaped
+    );
+}
+
+#[cfg(test)]
+const SHOWVMINFO: &str = r#"name="ubuntu-a"
+groups="/"
+ostype="Ubuntu (64-bit)"
+UUID="c777e3e8-b82e-40a4-bf3d-550f0f0da9e9"
+CfgFile="/home/golem/VirtualBox VMs/ubuntu-a/ubuntu-a.vbox"
+SnapFldr="/home/golem/VirtualBox VMs/ubuntu-a/Snapshots"
+description="build worker
+second line"
+memory=2048
+cpus=2
+VMState="running"
+storagecontrollername0="SATA"
+storagecontrollertype0="IntelAhci"
+storagecontrollerinstance0="0"
+storagecontrollername1="IDE"
+storagecontrollertype1="PIIX4"
+"SATA-0-0"="/home/golem/VirtualBox VMs/ubuntu-a/ubuntu-a.vdi"
+"SATA-ImageUUID-0-0"="7a1b2c3d-0000-4000-8000-000000000001"
+"SATA-1-0"="none"
+"IDE-1-0"="emptydrive"
+nic1="nat"
+nictype1="82540EM"
+macaddress1="080027A1B2C3"
+cableconnected1="on"
+Forwarding(0)="ssh,tcp,,2222,,22"
+nic2="bridged"
+bridgeadapter2="en0: Wi-Fi (AirPort)"
+macaddress2="080027D4E5F6"
+cableconnected2="off"
+nic3="none"
+CurrentSnapshotName="clean"
+CurrentSnapshotUUID="11111111-2222-3333-4444-555555555555""#;
+
+#[test]
+fn test_info_parse() {
+    let props = props_parse(SHOWVMINFO.lines().map(|l| l.to_string()), Quoting::Raw);
+    assert!(props.contains(&("description".into(), "build worker\nsecond line".into())));
+
+    let info = info_parse(props);
+    assert_eq!(info.name, "ubuntu-a");
+    assert_eq!(
+        info.uuid.as_ref().unwrap(),
+        "c777e3e8-b82e-40a4-bf3d-550f0f0da9e9"
+    );
+    assert_eq!(info.os_type.as_ref().unwrap(), "Ubuntu (64-bit)");
+    assert_eq!(info.cpus, Some(2));
+    assert_eq!(info.memory_mb, Some(2048));
+    assert_eq!(info.state, MachineState::Running);
+    assert_eq!(info.current_snapshot.as_ref().unwrap(), "clean");
+    assert_eq!(
+        info.snapshot_folder.as_ref().unwrap(),
+        "/home/golem/VirtualBox VMs/ubuntu-a/Snapshots"
+    );
+
+    assert_eq!(info.nics.len(), 3);
+    assert_eq!(info.nics[0].attachment, NicAttachment::Nat);
+    assert_eq!(info.nics[0].mac.as_ref().unwrap(), "080027A1B2C3");
+    assert_eq!(info.nics[1].attachment, NicAttachment::Bridged);
+    assert_eq!(
+        info.nics[1].network.as_ref().unwrap(),
+        "en0: Wi-Fi (AirPort)"
+    );
+    assert_eq!(info.nics[1].cable_connected, Some(false));
+    assert_eq!(info.nics[2].attachment, NicAttachment::None);
+
+    assert_eq!(info.storage.len(), 2);
+    assert_eq!(info.storage[0].name, "SATA");
+    assert_eq!(info.storage[0].attachments.len(), 1);
+    assert_eq!(
+        info.storage[0].attachments[0].uuid.as_ref().unwrap(),
+        "7a1b2c3d-0000-4000-8000-000000000001"
+    );
+    assert_eq!(info.storage[1].attachments[0].medium, "emptydrive");
+    assert_eq!(info.storage[1].attachments[0].port, 1);
 }
 
 impl<T: CommandRunner> MachineRef<T> {
@@ -130,71 +997,1255 @@ impl<T: CommandRunner> MachineRef<T> {
     }
 }
 
+impl<T: CommandRunner + 'static> MachineRef<T> {
+    /// Running and paused machines only accept `controlvm` changes.
+    fn is_running(&self) -> Result<bool> {
+        match super::Machine::state(self)? {
+            MachineState::Running | MachineState::Paused => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn ensure_stopped(&self) -> Result<()> {
+        if self.is_running()? {
+            bail!(ErrorKind::MachineRunning(self.path.clone()))
+        }
+        Ok(())
+    }
+
+    /// Command editing NAT rules, `controlvm` while running, `modifyvm` otherwise.
+    fn natpf_args(&self, slot: u32) -> Result<Vec<String>> {
+        if self.is_running()? {
+            Ok(vec![
+                "controlvm".into(),
+                self.vmid().into(),
+                format!("natpf{}", slot),
+            ])
+        } else {
+            Ok(vec![
+                "modifyvm".into(),
+                self.vmid().into(),
+                format!("--natpf{}", slot),
+            ])
+        }
+    }
+
+    fn nic(&self, slot: u32) -> Result<NicInfo> {
+        super::Machine::info(self)?
+            .nics
+            .into_iter()
+            .find(|nic| nic.slot == slot)
+            .chain_err(|| ErrorKind::InvalidConfig(format!("no network adapter in slot {}", slot)))
+    }
+
+    /// Machines whose disks are differencing images of this machine disks.
+    fn linked_clones(&self) -> Result<Vec<String>> {
+        let info = super::Machine::info(self)?;
+        let uuid = info.uuid.clone().unwrap_or_default();
+        let media = hdds_parse(self.driver_ref.run(["list", "hdds"])?);
+
+        let owned: Vec<&str> = media
+            .iter()
+            .filter(|m| m.vms.iter().any(|vm| vm.0 == info.name || vm.1 == uuid))
+            .map(|m| m.uuid.as_str())
+            .collect();
+        let mut clones: Vec<String> = media
+            .iter()
+            .filter(|m| {
+                m.parent
+                    .as_ref()
+                    .is_some_and(|p| owned.contains(&p.as_str()))
+            })
+            .flat_map(|m| m.vms.iter())
+            .filter(|vm| vm.0 != info.name && vm.1 != uuid)
+            .map(|vm| vm.0.clone())
+            .collect();
+        clones.sort();
+        clones.dedup();
+        Ok(clones)
+    }
+}
+
 impl<Cmd: CommandRunner + 'static> DriverFactory for Driver<Cmd> {
-    fn machine_for_uri(&self, uri: &str) -> Option<Box<Machine>> {
-        Some(Box::new(self.machine(uri, None)))
+    fn machine_for_uri(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        // vm names come after authority as `//host/name`.
+        let name = match uri.host() {
+            Some(_) => uri.path().trim_start_matches('/'),
+            None => uri.path(),
+        };
+        if name.is_empty() {
+            bail!(ErrorKind::InvalidUri(uri.to_string()))
+        }
+        Ok(Box::new(self.machine(name, None, uri.query().to_vec())))
+    }
+
+    /// Skipped lines are reported, unlike by `Driver::list_running`.
+    fn list_running(&self, _uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        Ok(self
+            .list_vms(false)?
+            .map(|m| Box::new(m) as Box<dyn Machine>))
+    }
+
+    fn list_all(&self, _uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        Ok(self
+            .list_vms(true)?
+            .map(|m| Box::new(m) as Box<dyn Machine>))
+    }
+
+    fn register(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        if uri.path().is_empty() {
+            bail!(ErrorKind::InvalidUri(uri.to_string()))
+        }
+        let mut machine = super::Driver::register(self, uri.path())?;
+        machine.query = uri.query().to_vec();
+        Ok(Box::new(machine))
+    }
+
+    fn import_appliance(&self, uri: &VmUri, options: &ImportOptions) -> Result<Box<dyn Machine>> {
+        if uri.path().is_empty() {
+            bail!(ErrorKind::InvalidUri(uri.to_string()))
+        }
+        let mut machine = super::Driver::import_appliance(self, uri.path(), options)?;
+        machine.query = uri.query().to_vec();
+        Ok(Box::new(machine))
+    }
+
+    fn version(&self, _uri: &VmUri) -> Result<Version> {
+        super::Driver::version(self)
+    }
+
+    fn disk_store(&self, _uri: &VmUri) -> Result<Box<dyn DiskStore>> {
+        Ok(Box::new(Driver {
+            inner: self.inner.clone(),
+        }))
+    }
+}
+
+impl<Cmd: CommandRunner> DiskStore for Driver<Cmd> {
+    fn create(&self, path: &str, options: &DiskOptions) -> Result<()> {
+        options.validate()?;
+        let size = options.size_mb.to_string();
+        let variant = if options.fixed { "Fixed" } else { "Standard" };
+        let _ = self.inner.run([
+            "createmedium",
+            "disk",
+            "--filename",
+            path,
+            "--size",
+            &size,
+            "--format",
+            options.format.as_str(),
+            "--variant",
+            variant,
+        ])?;
+        Ok(())
+    }
+
+    fn resize(&self, path: &str, size_mb: u64) -> Result<()> {
+        let size = size_mb.to_string();
+        let _ = self
+            .inner
+            .run(["modifymedium", "disk", path, "--resize", &size])?;
+        Ok(())
+    }
+
+    fn compact(&self, path: &str) -> Result<()> {
+        let _ = self
+            .inner
+            .run(["modifymedium", "disk", path, "--compact"])?;
+        Ok(())
+    }
+
+    fn delete(&self, path: &str) -> Result<()> {
+        let _ = self.inner.run(["closemedium", "disk", path, "--delete"])?;
+        Ok(())
     }
 }
 
-pub fn local_driver() -> Box<DriverFactory> {
+pub fn local_driver() -> Box<dyn DriverFactory> {
     Box::new(Driver::from_cmd(command::local()))
 }
 
-impl<Cmd: CommandRunner> super::Machine for MachineRef<Cmd> {
+impl<Cmd: CommandRunner + 'static> super::Machine for MachineRef<Cmd> {
     fn name(&self) -> &str {
         self.path.as_ref()
     }
 
+    fn uri(&self) -> VmUri {
+        self.driver_ref.machine_uri(&self.path, &self.query)
+    }
+
+    fn state(&self) -> Result<MachineState> {
+        let output = self
+            .driver_ref
+            .run(["showvminfo", self.vmid(), "--machinereadable"])?;
+
+        let (props, skipped) = props_parse_all(output, self.driver_ref.quoting());
+        for (k, v) in props {
+            if k == "VMState" {
+                return Ok(vmstate_parse(&v));
+            }
+        }
+        match skipped.into_iter().next() {
+            Some(e) => Err(e),
+            None => bail!(ErrorKind::MissingSummary),
+        }
+    }
+
+    fn info(&self) -> Result<MachineInfo> {
+        let output = self
+            .driver_ref
+            .run(["showvminfo", self.vmid(), "--machinereadable"])?;
+
+        Ok(info_parse(props_parse(output, self.driver_ref.quoting())))
+    }
+
+    /// Fails on line that cannot be parsed rather than leave out a snapshot.
     fn list_snapshots(&self) -> Result<Vec<String>> {
         let output = self
             .driver_ref
-            .run(&["snapshot", self.vmid(), "list", "--machinereadable"]);
-        let prop_re = Regex::new("^([a-zA-Z0-9\\-]+)=\"([^\"]*)\"$").unwrap();
+            .run(["snapshot", self.vmid(), "list", "--machinereadable"]);
         let mut res = Vec::new();
 
-        for line in output? {
-            let (k, v) = match prop_re.captures(&line) {
-                Some(cap) => match (cap.get(1), cap.get(2)) {
-                    (Some(k), Some(v)) => (k.as_str(), v.as_str()),
-                    _ => bail!(ErrorKind::InvalidResponse(line.clone())),
-                },
-                _ => bail!(ErrorKind::InvalidResponse(line.clone())),
-            };
+        let (props, skipped) = props_parse_all(output?, self.driver_ref.quoting());
+        if let Some(e) = skipped.into_iter().next() {
+            return Err(e);
+        }
+        for (k, v) in props {
             if k.starts_with("SnapshotName") {
-                res.push(v.into())
+                res.push(v)
             }
         }
         Ok(res)
     }
 
     fn stop(&mut self) -> Result<()> {
-        let _ = self.driver_ref.run(&["controlvm", self.vmid(), "poweroff"]);
+        let _ = self.driver_ref.run(["controlvm", self.vmid(), "poweroff"]);
         Ok(())
     }
 
     fn start(&mut self) -> Result<()> {
         let _ = self
             .driver_ref
-            .run(&["startvm", self.vmid(), "--type", "headless"])?;
+            .run(["startvm", self.vmid(), "--type", "headless"])?;
         Ok(())
     }
 
     fn revert_to(&mut self, snapshot_name: &str) -> Result<()> {
         let _ = self
             .driver_ref
-            .run(&["snapshot", self.vmid(), "restore", snapshot_name])?;
+            .run(["snapshot", self.vmid(), "restore", snapshot_name])?;
         Ok(())
     }
 
     fn create_snapshot(&mut self, snapshot_name: &str) -> Result<()> {
         let _ = self
             .driver_ref
-            .run(&["snapshot", self.vmid(), "take", snapshot_name])?;
+            .run(["snapshot", self.vmid(), "take", snapshot_name])?;
         Ok(())
     }
-}
 
-pub fn remote_driver() -> Box<DriverFactory> {
+    fn delete_snapshot(&mut self, snapshot_name: &str) -> Result<()> {
+        let _ = self
+            .driver_ref
+            .run(["snapshot", self.vmid(), "delete", snapshot_name])?;
+        Ok(())
+    }
 
-    factory().into()
+    fn clone_to(
+        &self,
+        target: &str,
+        from_snapshot: Option<&str>,
+        linked: bool,
+    ) -> Result<Box<dyn Machine>> {
+        let mut args = vec!["clonevm", self.vmid(), "--name", target, "--register"];
+        if let Some(snapshot) = from_snapshot {
+            args.extend_from_slice(&["--snapshot", snapshot]);
+        }
+        if linked {
+            if from_snapshot.is_none() {
+                bail!(ErrorKind::InvalidConfig(format!(
+                    "linked clone of {} requires snapshot",
+                    self.path
+                )))
+            }
+            args.extend_from_slice(&["--options", "link"]);
+        }
+        let _ = self.driver_ref.run(args)?;
+
+        Ok(Box::new(MachineRef {
+            driver_ref: self.driver_ref.clone(),
+            path: target.into(),
+            uuid: None,
+            query: self.query.clone(),
+        }))
+    }
+
+    fn unregister(&mut self) -> Result<()> {
+        self.ensure_stopped()?;
+        let _ = self.driver_ref.run(["unregistervm", self.vmid()])?;
+        Ok(())
+    }
+
+    fn configure(&mut self, config: &MachineConfig) -> Result<()> {
+        config.validate()?;
+        if config.is_empty() {
+            return Ok(());
+        }
+        self.ensure_stopped()?;
+
+        let mut args = vec!["modifyvm".to_string(), self.vmid().to_string()];
+        if let Some(cpus) = config.cpus {
+            args.extend(vec!["--cpus".into(), cpus.to_string()]);
+        }
+        if let Some(memory_mb) = config.memory_mb {
+            args.extend(vec!["--memory".into(), memory_mb.to_string()]);
+        }
+        if let Some(firmware) = config.firmware {
+            args.extend(vec!["--firmware".into(), firmware.to_string()]);
+        }
+        if let Some(ref devices) = config.boot_order {
+            for slot in 0..MAX_BOOT_DEVICES {
+                let device = devices.get(slot).map_or("none", |d| d.as_str());
+                args.extend(vec![format!("--boot{}", slot + 1), device.into()]);
+            }
+        }
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    /// Running machines accept attachment and cable changes, not MAC address.
+    fn configure_nic(&mut self, slot: u32, config: &NicConfig) -> Result<()> {
+        config.validate()?;
+        if config.is_empty() {
+            return Ok(());
+        }
+        let attachment = match (config.attachment.as_ref(), config.network.as_ref()) {
+            (Some(attachment), _) => Some(attachment.clone()),
+            (None, Some(_)) => Some(self.nic(slot)?.attachment),
+            (None, None) => None,
+        };
+        let network = match (attachment.as_ref(), config.network.as_ref()) {
+            (Some(attachment), Some(network)) => Some((nic_network_option(attachment)?, network)),
+            _ => None,
+        };
+
+        if self.is_running()? {
+            if config.mac.is_some() {
+                bail!(ErrorKind::MachineRunning(self.path.clone()))
+            }
+            if let Some(ref attachment) = attachment {
+                let kind = match *attachment {
+                    NicAttachment::None => "null",
+                    ref other => nic_attachment_arg(other),
+                };
+                let mut args = vec![
+                    "controlvm".to_string(),
+                    self.vmid().to_string(),
+                    format!("nic{}", slot),
+                    kind.to_string(),
+                ];
+                if let Some((_, network)) = network {
+                    args.push(network.clone());
+                }
+                let _ = self.driver_ref.run(args)?;
+            }
+            if let Some(connected) = config.cable_connected {
+                let _ = self.driver_ref.run([
+                    "controlvm",
+                    self.vmid(),
+                    &format!("setlinkstate{}", slot),
+                    if connected { "on" } else { "off" },
+                ])?;
+            }
+            return Ok(());
+        }
+
+        let mut args = vec!["modifyvm".to_string(), self.vmid().to_string()];
+        if let Some(ref attachment) = attachment {
+            args.extend(vec![
+                format!("--nic{}", slot),
+                nic_attachment_arg(attachment).into(),
+            ]);
+        }
+        if let Some((option, network)) = network {
+            args.extend(vec![format!("{}{}", option, slot), network.clone()]);
+        }
+        if let Some(ref mac) = config.mac {
+            args.extend(vec![
+                format!("--macaddress{}", slot),
+                network::mac_digits(mac)?,
+            ]);
+        }
+        if let Some(connected) = config.cable_connected {
+            args.extend(vec![
+                format!("--cableconnected{}", slot),
+                if connected { "on" } else { "off" }.into(),
+            ]);
+        }
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    fn port_forwards(&self, slot: u32) -> Result<Vec<PortForward>> {
+        let output = self
+            .driver_ref
+            .run(["showvminfo", self.vmid(), "--machinereadable"])?;
+        forwardings_parse(&props_parse(output, self.driver_ref.quoting()), slot)
+    }
+
+    fn add_port_forward(&mut self, slot: u32, rule: &PortForward) -> Result<()> {
+        rule.validate()?;
+        let mut args = self.natpf_args(slot)?;
+        args.push(rule.to_string());
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    fn remove_port_forward(&mut self, slot: u32, name: &str) -> Result<()> {
+        let mut args = self.natpf_args(slot)?;
+        args.extend(vec!["delete".to_string(), name.to_string()]);
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    fn attach_disk(&mut self, slot: &DiskSlot, path: &str) -> Result<()> {
+        let _ = self
+            .driver_ref
+            .run(storageattach_args(self.vmid(), slot, path))?;
+        Ok(())
+    }
+
+    fn detach_disk(&mut self, slot: &DiskSlot) -> Result<()> {
+        let _ = self
+            .driver_ref
+            .run(storageattach_args(self.vmid(), slot, "none"))?;
+        Ok(())
+    }
+
+    fn shared_folders(&self) -> Result<Vec<SharedFolder>> {
+        let output = self.driver_ref.run(["showvminfo", self.vmid()])?;
+        Ok(shared_folders_parse(output))
+    }
+
+    fn add_shared_folder(&mut self, folder: &SharedFolder) -> Result<()> {
+        let mut args = vec![
+            "sharedfolder".to_string(),
+            "add".into(),
+            self.vmid().into(),
+            "--name".into(),
+            folder.name.clone(),
+            "--hostpath".into(),
+            folder.host_path.clone(),
+        ];
+        if !folder.writable {
+            args.push("--readonly".into());
+        }
+        if folder.auto_mount {
+            args.push("--automount".into());
+        }
+        if let Some(ref mount_point) = folder.mount_point {
+            args.extend(vec!["--auto-mount-point".into(), mount_point.clone()]);
+        }
+        if folder.transient {
+            args.push("--transient".into());
+        }
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    fn remove_shared_folder(&mut self, name: &str) -> Result<()> {
+        let transient = self
+            .shared_folders()?
+            .iter()
+            .any(|folder| folder.name == name && folder.transient);
+        let mut args = vec!["sharedfolder", "remove", self.vmid(), "--name", name];
+        if transient {
+            args.push("--transient");
+        }
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    /// VirtualBox always shares configured folders, they can only be removed.
+    fn set_shared_folders_enabled(&mut self, enabled: bool) -> Result<()> {
+        if !enabled {
+            bail!(ErrorKind::NotSupported("disabling shared folders".into()))
+        }
+        Ok(())
+    }
+
+    fn screenshot(&self, path: &str) -> Result<Vec<u8>> {
+        let _ = self
+            .driver_ref
+            .run(["controlvm", self.vmid(), "screenshotpng", path])?;
+        let runner = &self.driver_ref.command_runner;
+        let image = runner.read_bytes(path);
+        let _ = runner.remove_file(path);
+        image
+    }
+
+    fn send_text(&self, text: &str) -> Result<()> {
+        let _ = self
+            .driver_ref
+            .run(["controlvm", self.vmid(), "keyboardputstring", text])?;
+        Ok(())
+    }
+
+    fn send_keys(&self, combo: &KeyCombo) -> Result<()> {
+        let codes: Vec<String> = combo
+            .scancodes()?
+            .iter()
+            .map(|code| format!("{:02x}", code))
+            .collect();
+        let mut args = vec!["controlvm", self.vmid(), "keyboardputscancode"];
+        args.extend(codes.iter().map(|code| code.as_str()));
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    fn configure_serial(&mut self, port: u32, mode: &SerialMode) -> Result<()> {
+        mode.validate()?;
+        let (io_base, irq) = match port {
+            1..=4 => UART_DEFAULTS[port as usize - 1],
+            _ => bail!(ErrorKind::InvalidConfig(
+                "VirtualBox serial ports are numbered 1 to 4".into()
+            )),
+        };
+        self.ensure_stopped()?;
+
+        let mut args = vec![
+            "modifyvm".to_string(),
+            self.vmid().into(),
+            format!("--uart{}", port),
+        ];
+        if *mode == SerialMode::Off {
+            args.push("off".into());
+            let _ = self.driver_ref.run(args)?;
+            return Ok(());
+        }
+        // keep I/O base and IRQ of port that is already on
+        if self.serial_mode(port)? == SerialMode::Off {
+            args.extend(vec![io_base.into(), irq.into()]);
+        } else {
+            let _ = args.pop();
+        }
+        args.push(format!("--uartmode{}", port));
+        match *mode {
+            SerialMode::File(ref path) => args.extend(vec!["file".into(), path.clone()]),
+            SerialMode::Pipe(ref path) => args.extend(vec!["server".into(), path.clone()]),
+            _ => args.push("disconnected".into()),
+        }
+        let _ = self.driver_ref.run(args)?;
+        Ok(())
+    }
+
+    fn serial_mode(&self, port: u32) -> Result<SerialMode> {
+        let output = self
+            .driver_ref
+            .run(["showvminfo", self.vmid(), "--machinereadable"])?;
+        Ok(serial_mode_parse(
+            &props_parse(output, self.driver_ref.quoting()),
+            port,
+        ))
+    }
+
+    fn read_serial(&self, port: u32, offset: u64) -> Result<Vec<u8>> {
+        match self.serial_mode(port)? {
+            SerialMode::File(path) => self
+                .driver_ref
+                .command_runner
+                .read_bytes_from(&path, offset),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "serial port {} is not connected to file",
+                port
+            ))),
+        }
+    }
+
+    fn guest_property(&self, name: &str) -> Result<Option<String>> {
+        let output = self
+            .driver_ref
+            .run(["guestproperty", "get", self.vmid(), name])?;
+        Ok(output
+            .into_iter()
+            .find_map(|line| line.strip_prefix("Value: ").map(|value| value.to_string())))
+    }
+
+    fn guest_properties(&self, pattern: &str) -> Result<Vec<GuestProperty>> {
+        let output = self
+            .driver_ref
+            .run(["guestproperty", "enumerate", self.vmid()])?;
+        Ok(output
+            .into_iter()
+            .filter_map(|line| property_parse(&line))
+            .filter(|prop| property::matches(pattern, &prop.name))
+            .collect())
+    }
+
+    fn set_guest_property(&mut self, name: &str, value: &str) -> Result<()> {
+        let _ = self
+            .driver_ref
+            .run(["guestproperty", "set", self.vmid(), name, value])?;
+        Ok(())
+    }
+
+    fn delete_guest_property(&mut self, name: &str) -> Result<()> {
+        let _ = self
+            .driver_ref
+            .run(["guestproperty", "unset", self.vmid(), name])?;
+        Ok(())
+    }
+
+    /// `guestproperty wait` reports only changes made while it runs, so it is
+    /// run in short slices with existing properties checked in between.
+    fn wait_for_property(&self, pattern: &str, timeout: Duration) -> Result<Option<GuestProperty>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(prop) = self.guest_properties(pattern)?.into_iter().next() {
+                return Ok(Some(prop));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Ok(None);
+            }
+            let slice = remaining.min(PROPERTY_WAIT_SLICE).as_millis().to_string();
+            let output = self.driver_ref.run([
+                "guestproperty",
+                "wait",
+                self.vmid(),
+                pattern,
+                "--timeout",
+                &slice,
+            ])?;
+            if let Some(prop) = output.into_iter().find_map(|line| property_parse(&line)) {
+                return Ok(Some(prop));
+            }
+        }
+    }
+
+    fn export(&self, path: &str, format: ExportFormat) -> Result<()> {
+        if !format.matches(path) {
+            bail!(ErrorKind::InvalidConfig(format!(
+                "{} does not end with .{}",
+                path,
+                format.extension()
+            )))
+        }
+        self.ensure_stopped()?;
+        let _ = self
+            .driver_ref
+            .run(["export", self.vmid(), "--output", path, "--ovf20"])?;
+        Ok(())
+    }
+
+    /// Without disks the machine is unregistered and its `.vbox` files removed.
+    fn delete(&mut self, with_disks: bool) -> Result<()> {
+        self.ensure_stopped()?;
+        if with_disks {
+            let clones = self.linked_clones()?;
+            if !clones.is_empty() {
+                bail!(ErrorKind::HasLinkedClones(self.path.clone(), clones))
+            }
+            let _ = self
+                .driver_ref
+                .run(["unregistervm", self.vmid(), "--delete"])?;
+            return Ok(());
+        }
+
+        let output = self
+            .driver_ref
+            .run(["showvminfo", self.vmid(), "--machinereadable"])?;
+        let settings = props_parse(output, self.driver_ref.quoting())
+            .into_iter()
+            .find(|(k, _)| k == "CfgFile")
+            .map(|(_, v)| v);
+        let _ = self.driver_ref.run(["unregistervm", self.vmid()])?;
+        if let Some(settings) = settings {
+            let runner = &self.driver_ref.command_runner;
+            runner.remove_file(&settings)?;
+            runner.remove_file(&format!("{}-prev", settings))?;
+        }
+        Ok(())
+    }
+
+    fn guest_credentials(&self) -> Option<Credentials> {
+        Credentials::resolve(self.driver_ref.guest_credentials.as_ref(), &self.uri())
+    }
+
+    fn guest_session<'a>(
+        &'a self,
+        credentials: &Credentials,
+    ) -> Result<Box<dyn GuestSession + 'a>> {
+        Ok(Box::new(GuestRef {
+            machine: self,
+            credentials: credentials.clone(),
+        }))
+    }
+}
+
+struct GuestRef<'a, Cmd: CommandRunner + 'a> {
+    machine: &'a MachineRef<Cmd>,
+    credentials: Credentials,
+}
+
+impl<'a, Cmd: CommandRunner> GuestRef<'a, Cmd> {
+    /// Password is read by `VBoxManage` from stdin (`--passwordfile -`), so it
+    /// does not show in process listings.
+    fn run(&self, command: &str, args: &[&str]) -> Result<Output> {
+        let mut cmd_args = vec![
+            "guestcontrol",
+            self.machine.vmid(),
+            command,
+            "--username",
+            &self.credentials.user,
+            "--passwordfile",
+            "-",
+        ];
+        cmd_args.extend_from_slice(args);
+        self.machine
+            .driver_ref
+            .run_with_input(cmd_args, &self.credentials.password)
+    }
+}
+
+impl<'a, Cmd: CommandRunner> GuestSession for GuestRef<'a, Cmd> {
+    fn exec(&self, program: &str, args: &[&str]) -> Result<Output> {
+        let mut cmd_args = vec!["--exe", program, "--wait-stdout", "--", program];
+        cmd_args.extend_from_slice(args);
+        self.run("run", &cmd_args)
+    }
+
+    fn copy_to_guest(&self, host_path: &str, guest_path: &str) -> Result<()> {
+        let _ = self.run("copyto", &[host_path, guest_path])?;
+        Ok(())
+    }
+
+    fn copy_from_guest(&self, guest_path: &str, host_path: &str) -> Result<()> {
+        let _ = self.run("copyfrom", &[guest_path, host_path])?;
+        Ok(())
+    }
+
+    fn list_processes(&self) -> Result<Vec<GuestProcess>> {
+        Ok(processes_parse(self.run("list", &["processes"])?)
+            .into_iter()
+            .map(|(_, process)| process)
+            .collect())
+    }
+
+    /// Kills process started through guest control, see `list_processes`.
+    fn kill(&self, pid: u32) -> Result<()> {
+        let session = processes_parse(self.run("list", &["processes"])?)
+            .into_iter()
+            .find(|(_, process)| process.pid == pid)
+            .map(|(session, _)| session.to_string())
+            .chain_err(|| format!("no guest control process with pid {}", pid))?;
+        let _ = self.run(
+            "process",
+            &["kill", "--session-id", &session, &pid.to_string()],
+        )?;
+        Ok(())
+    }
+}
+
+/// Running processes with id of guest session they belong to, from
+/// `guestcontrol list processes` table:
+///
+/// ```text
+///     Session #0   | ID=1   | User=ci   | Status=[Started] | Name=[VBoxManage]
+///         Process #0   | PID=1234   | Status=[Started] | Name=/bin/sleep
+/// ```
+fn processes_parse<I: IntoIterator<Item = String>>(lines: I) -> Vec<(u32, GuestProcess)> {
+    lazy_static! {
+        static ref SESSION: Regex =
+            Regex::new(r"Session #\d+\s*\|\s*ID=(\d+)\s*\|\s*User=(.*?)\s*\|").unwrap();
+        static ref PROCESS: Regex = Regex::new(
+            r"Process #\d+\s*\|\s*PID=(\d+)\s*\|\s*Status=\[(.*?)\]\s*\|\s*Name=(.*?)\s*$"
+        )
+        .unwrap();
+    }
+
+    let mut session: Option<(u32, String)> = None;
+    let mut processes = Vec::new();
+    for line in lines {
+        if let Some(captures) = SESSION.captures(&line) {
+            session = captures[1]
+                .parse()
+                .ok()
+                .map(|id| (id, captures[2].to_string()));
+        } else if let (Some(captures), Some((id, user))) = (PROCESS.captures(&line), &session) {
+            let pid = match captures[1].parse() {
+                Ok(pid) if captures[2].starts_with("Start") => pid,
+                _ => continue,
+            };
+            processes.push((
+                *id,
+                GuestProcess {
+                    pid,
+                    owner: user.clone(),
+                    command: captures[3].into(),
+                },
+            ));
+        }
+    }
+    processes
+}
+
+pub fn remote_driver() -> Box<dyn DriverFactory> {
+    factory::<command::Ssh>().into()
+}
+
+#[cfg(feature = "http")]
+pub fn http_driver() -> Box<dyn DriverFactory> {
+    factory::<::http::Http>().into()
+}
+
+#[test]
+fn test_hdds_parse() {
+    let output = r"UUID:           7a1b2c3d-0000-4000-8000-000000000001
+Parent UUID:    base
+State:          created
+Type:           normal (base)
+Location:       /vms/template/template.vdi
+In use by VMs:  template (UUID: c777e3e8-b82e-40a4-bf3d-550f0f0da9e9) [clean (UUID: 11111111-2222-3333-4444-555555555555)]
+
+UUID:           8b000000-0000-4000-8000-000000000002
+Parent UUID:    7a1b2c3d-0000-4000-8000-000000000001
+State:          created
+Type:           normal (differencing)
+Location:       /vms/worker-1/Snapshots/{8b000000-0000-4000-8000-000000000002}.vdi
+In use by VMs:  worker-1 (UUID: d8880000-0000-4000-8000-000000000003)
+                worker-2 (UUID: d8880000-0000-4000-8000-000000000004)
+";
+    let media = hdds_parse(output.lines().map(|l| l.to_string()));
+
+    assert_eq!(media.len(), 2);
+    assert_eq!(media[0].parent, None);
+    assert_eq!(media[0].vms[0].0, "template");
+    assert_eq!(
+        media[1].parent.as_ref().unwrap(),
+        "7a1b2c3d-0000-4000-8000-000000000001"
+    );
+    assert_eq!(media[1].vms.len(), 2);
+    assert_eq!(media[1].vms[1].0, "worker-2");
+
+    let settings = r#"<VirtualBox xmlns="http://www.virtualbox.org/">
+  <Machine uuid="{d8880000-0000-4000-8000-000000000003}" name="worker-1" OSType="Ubuntu_64">"#;
+    assert_eq!(
+        vbox_machine_parse(settings).unwrap(),
+        ("worker-1", "{d8880000-0000-4000-8000-000000000003}")
+    );
+}
+
+#[test]
+fn test_import_plan_parse() {
+    let output = r#"0%...10%...20%...30%...40%...50%...60%...70%...80%...90%...100%
+Interpreting /images/golden.ova...
+OK.
+Disks:
+  vmdisk1	20480	-1	http://www.vmware.com/interfaces/specifications/vmdk.html#streamOptimized	golden-disk001.vmdk	-1	-1
+
+Virtual system 0:
+ 0: Suggested OS type: "Ubuntu_64"
+    (change with "--vsys 0 --ostype <type>"; use "list ostypes" to list all possible values)
+ 1: VM name specified with --vmname: "worker-1"
+ 2: Suggested VM group "/"
+    (change with "--vsys 0 --group <group>")
+ 3: Suggested VM settings file name "/vms/worker-1/worker-1.vbox"
+    (change with "--vsys 0 --settingsfile <filename>")
+ 4: Suggested VM base folder "/vms"
+    (change with "--vsys 0 --basefolder <path>")
+ 5: Number of CPUs specified with --cpus: 4
+ 6: Guest memory: 2048 MB
+    (change with "--vsys 0 --memory <MB>")
+ 7: Hard disk image: source image=golden-disk001.vmdk, target path=/vms/worker-1/golden-disk001.vdi, controller=9;channel=0
+    (change target path with "--vsys 0 --unit 7 --disk path";
+    disable with "--vsys 0 --unit 7 --ignore")
+"#;
+    let plan = import_plan_parse(output.lines().map(|l| l.to_string())).unwrap();
+
+    assert_eq!(plan.name, "worker-1");
+    assert_eq!(plan.os_type.as_ref().unwrap(), "Ubuntu_64");
+    assert_eq!(plan.cpus, Some(4));
+    assert_eq!(plan.memory_mb, Some(2048));
+    assert_eq!(
+        plan.settings_file.as_ref().unwrap(),
+        "/vms/worker-1/worker-1.vbox"
+    );
+    assert_eq!(plan.disks, vec!["/vms/worker-1/golden-disk001.vdi"]);
+    assert_eq!(
+        import_args("/images/golden.ova", &ImportOptions::new().name("worker-1")),
+        vec![
+            "import",
+            "/images/golden.ova",
+            "--vsys",
+            "0",
+            "--vmname",
+            "worker-1"
+        ]
+    );
+}
+
+/// Records `vboxmanage` invocations, reports every machine as powered off.
+#[cfg(test)]
+#[derive(Default)]
+struct Recorder(::std::cell::RefCell<Vec<String>>);
+
+#[cfg(test)]
+impl CommandRunner for Rc<Recorder> {
+    fn run_with_output<C, I, S>(&self, _cmd: C, args: I) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let args: Vec<String> = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+            .collect();
+        // tool discovery probe
+        if args == ["--version"] {
+            return Ok(Output::new(vec!["7.0.14r161095".into()]));
+        }
+        self.0.borrow_mut().push(args.join(" "));
+        match args[0].as_str() {
+            "showvminfo" => Ok(Output::new(vec!["VMState=\"poweroff\"".into()])),
+            // od dump of staged file
+            "-An" => Ok(Output::new(vec![" 89 50 4e 47".into()])),
+            _ => Ok(Output::new(Vec::new())),
+        }
+    }
+
+    fn run_with_input<C, I, S>(&self, cmd: C, args: I, input: &str) -> Result<Output>
+    where
+        C: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self.run_with_output(cmd, args)?;
+        self.0.borrow_mut().push(format!("<<< {}", input));
+        Ok(output)
+    }
+}
+
+/// `worker-1` machine whose commands go to returned recorder.
+#[cfg(test)]
+fn recorded_machine() -> (Rc<Recorder>, MachineRef<Rc<Recorder>>) {
+    let recorder = Rc::new(Recorder::default());
+    let driver = Driver::from_cmd(recorder.clone());
+    let machine = super::Driver::from_path(&driver, "worker-1").unwrap();
+    (recorder, machine)
+}
+
+#[cfg(test)]
+impl super::remote::RemoteCommand for Rc<Recorder> {
+    fn for_uri(uri: &VmUri) -> Option<Self> {
+        uri.host().map(|_| Rc::new(Recorder::default()))
+    }
+}
+
+#[test]
+fn test_remote_version() {
+    use super::remote::RemoteFactory;
+
+    let remote: RemoteFactory<_> = factory::<Rc<Recorder>>().into();
+    let version = remote
+        .version(&VmUri::parse("ssh+virtualbox://host").unwrap())
+        .unwrap();
+    assert_eq!(version, Version::find("7.0.14r161095").unwrap());
+
+    let err = remote
+        .version(&VmUri::parse("ssh+virtualbox:").unwrap())
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::InvalidUri(_)));
+}
+
+#[test]
+fn test_guest_password_on_stdin() {
+    let (recorder, machine) = recorded_machine();
+
+    let credentials = Credentials::new("admin", "s3cret");
+    let session = machine.guest_session(&credentials).unwrap();
+    session.exec("/bin/true", &[]).unwrap();
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "guestcontrol worker-1 run --username admin --passwordfile - \
+             --exe /bin/true --wait-stdout -- /bin/true",
+            "<<< s3cret",
+        ]
+    );
+}
+
+#[test]
+fn test_configure() {
+    use super::config::BootDevice;
+
+    let (recorder, mut machine) = recorded_machine();
+
+    let config = MachineConfig::new()
+        .cpus(2)
+        .boot_order(&[BootDevice::Disk, BootDevice::Net]);
+    machine.configure(&config).unwrap();
+    assert!(machine.configure(&MachineConfig::new().cpus(0)).is_err());
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "showvminfo worker-1 --machinereadable",
+            "modifyvm worker-1 --cpus 2 --boot1 disk --boot2 net --boot3 none --boot4 none",
+        ]
+    );
+}
+
+#[test]
+fn test_shared_folders_parse() {
+    let output = "Shared folders:\n\n\
+                  Name: 'src', Host path: '/home/ci/src' (machine mapping), writable, auto-mount, mount-point: '/mnt/src'\n\
+                  Name: 'it's', Host path: '/tmp' (transient mapping), readonly\n\
+                  \n\
+                  VRDE Connection:    not active";
+    let folders = shared_folders_parse(output.lines().map(|s| s.to_string()));
+
+    assert_eq!(
+        folders,
+        vec![
+            SharedFolder::new("src", "/home/ci/src").mount_point("/mnt/src"),
+            SharedFolder::new("it's", "/tmp").read_only().transient(),
+        ]
+    );
+}
+
+#[test]
+fn test_forwardings_parse() {
+    let output = r#"natnet1="nat"
+macaddress1="0800279F4F68"
+nic1="nat"
+nictype1="82540EM"
+Forwarding(0)="ssh,tcp,,2222,,22"
+Forwarding(1)="dns,udp,127.0.0.1,5353,,53"
+natnet2="nat"
+nic2="nat"
+Forwarding(0)="web,tcp,,8080,,80""#;
+    let props = props_parse(output.lines().map(|s| s.to_string()), Quoting::Escaped);
+
+    assert_eq!(
+        forwardings_parse(&props, 1).unwrap(),
+        vec![
+            PortForward::tcp("ssh", 2222, 22),
+            PortForward::udp("dns", 5353, 53).host_ip("127.0.0.1"),
+        ]
+    );
+    assert_eq!(
+        forwardings_parse(&props, 2).unwrap(),
+        vec![PortForward::tcp("web", 8080, 80)]
+    );
+    assert!(forwardings_parse(&props, 3).unwrap().is_empty());
+}
+
+#[test]
+fn test_configure_nic() {
+    let (recorder, mut machine) = recorded_machine();
+
+    let config = NicConfig::new()
+        .attachment(NicAttachment::Bridged)
+        .network("eth0")
+        .mac("08:00:27:a1:b2:c3")
+        .cable_connected(false);
+    machine.configure_nic(1, &config).unwrap();
+    machine
+        .add_port_forward(1, &PortForward::tcp("ssh", 2222, 22))
+        .unwrap();
+    machine.remove_port_forward(1, "ssh").unwrap();
+    assert!(machine
+        .configure_nic(
+            1,
+            &NicConfig::new().attachment(NicAttachment::Nat).network("x")
+        )
+        .is_err());
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "showvminfo worker-1 --machinereadable",
+            "modifyvm worker-1 --nic1 bridged --bridgeadapter1 eth0 \
+             --macaddress1 080027A1B2C3 --cableconnected1 off",
+            "showvminfo worker-1 --machinereadable",
+            "modifyvm worker-1 --natpf1 ssh,tcp,,2222,,22",
+            "showvminfo worker-1 --machinereadable",
+            "modifyvm worker-1 --natpf1 delete ssh",
+        ]
+    );
+}
+
+#[test]
+fn test_disks() {
+    let (recorder, mut machine) = recorded_machine();
+    let driver = Driver::from_cmd(recorder.clone());
+    let slot = DiskSlot::new("SATA", 1, 0);
+
+    driver
+        .create("/scratch/job.vdi", &DiskOptions::new(10240))
+        .unwrap();
+    machine.attach_disk(&slot, "/scratch/job.vdi").unwrap();
+    machine.detach_disk(&slot).unwrap();
+    driver.delete("/scratch/job.vdi").unwrap();
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "createmedium disk --filename /scratch/job.vdi --size 10240 --format VDI \
+             --variant Standard",
+            "storageattach worker-1 --storagectl SATA --port 1 --device 0 --type hdd \
+             --medium /scratch/job.vdi",
+            "storageattach worker-1 --storagectl SATA --port 1 --device 0 --type hdd \
+             --medium none",
+            "closemedium disk /scratch/job.vdi --delete",
+        ]
+    );
+}
+
+#[test]
+fn test_screenshot() {
+    let (recorder, machine) = recorded_machine();
+
+    assert_eq!(machine.screenshot("/tmp/worker-1.png").unwrap(), b"\x89PNG");
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "controlvm worker-1 screenshotpng /tmp/worker-1.png",
+            "-An -v -tx1 /tmp/worker-1.png",
+            "-f /tmp/worker-1.png",
+        ]
+    );
+}
+
+#[test]
+fn test_keyboard() {
+    let (recorder, machine) = recorded_machine();
+
+    machine.send_text("root\n").unwrap();
+    machine.send_keys(&"ctrl+alt+del".parse().unwrap()).unwrap();
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "controlvm worker-1 keyboardputstring root\n",
+            "controlvm worker-1 keyboardputscancode 1d 38 e0 53 e0 d3 b8 9d",
+        ]
+    );
+}
+
+#[test]
+fn test_serial() {
+    let output = r#"uart1="0x03f8,4"
+uartmode1="file,/var/log/vms/worker-1,ttyS0.log"
+uart2="0x02f8,3"
+uartmode2="tcpserver,2000"
+uart3="off""#;
+    let props = props_parse(output.lines().map(|s| s.to_string()), Quoting::Escaped);
+    assert_eq!(
+        serial_mode_parse(&props, 1),
+        SerialMode::File("/var/log/vms/worker-1,ttyS0.log".into())
+    );
+    assert_eq!(
+        serial_mode_parse(&props, 2),
+        SerialMode::Other("tcpserver,2000".into())
+    );
+    assert_eq!(serial_mode_parse(&props, 3), SerialMode::Off);
+    assert_eq!(serial_mode_parse(&props, 4), SerialMode::Off);
+
+    let (recorder, mut machine) = recorded_machine();
+    machine
+        .configure_serial(1, &SerialMode::File("/tmp/ttyS0.log".into()))
+        .unwrap();
+    assert!(machine.configure_serial(5, &SerialMode::Off).is_err());
+    assert_eq!(
+        recorder.0.borrow().last().unwrap(),
+        "modifyvm worker-1 --uart1 0x3f8 4 --uartmode1 file /tmp/ttyS0.log"
+    );
+}
+
+#[test]
+fn test_property_parse() {
+    assert_eq!(
+        property_parse(
+            "Name: /job/id, value: 42, a, timestamp: 1591964223000000000, flags: TRANSIENT"
+        ),
+        Some(GuestProperty::new("/job/id", "42, a"))
+    );
+    assert_eq!(
+        property_parse("Name: /job/done, value: yes, flags: "),
+        Some(GuestProperty::new("/job/done", "yes"))
+    );
+    assert_eq!(
+        property_parse("/job/id = 'it's 42' @ 2023-05-10T09:12:01.123000000Z [TRANSIENT]"),
+        Some(GuestProperty::new("/job/id", "it's 42"))
+    );
+    assert_eq!(
+        property_parse("Time out or interruption while waiting for a notification."),
+        None
+    );
+}
+
+#[test]
+fn test_processes_parse() {
+    let output = "\tSession #0   | ID=1   | User=ci   | Status=[Started] | Name=[job]\n\
+                  \t\tProcess #0   | PID=1234   | Status=[Started] | Name=/bin/sleep\n\
+                  \t\tProcess #1   | PID=1240   | Status=[Terminated normally] | Name=/bin/ls\n\
+                  \tSession #1   | ID=2   | User=root | Status=[Started] | Name=[]\n\
+                  \t\tProcess #0   | PID=77     | Status=[Started] | Name=/usr/bin/make\n";
+    let processes = processes_parse(output.lines().map(|s| s.to_string()));
+
+    assert_eq!(
+        processes,
+        vec![
+            (
+                1,
+                GuestProcess {
+                    pid: 1234,
+                    owner: "ci".into(),
+                    command: "/bin/sleep".into(),
+                }
+            ),
+            (
+                2,
+                GuestProcess {
+                    pid: 77,
+                    owner: "root".into(),
+                    command: "/usr/bin/make".into(),
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_export_extension() {
+    let (recorder, machine) = recorded_machine();
+
+    match machine.export("/exports/worker-1.ova", ExportFormat::Ovf) {
+        Err(Error(ErrorKind::InvalidConfig(_), _)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("exported with wrong extension"),
+    }
+    assert!(recorder.0.borrow().is_empty());
+}
+
+#[test]
+fn test_clone_to() {
+    let (recorder, template) = recorded_machine();
+
+    let clone = template.clone_to("worker-2", Some("clean"), true).unwrap();
+
+    assert_eq!(clone.name(), "worker-2");
+    match template.clone_to("worker-3", None, true) {
+        Err(Error(ErrorKind::InvalidConfig(_), _)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("linked clone without snapshot"),
+    }
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec!["clonevm worker-1 --name worker-2 --register --snapshot clean --options link"]
+    );
 }
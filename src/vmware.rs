@@ -1,9 +1,29 @@
-use super::uri::DriverFactory;
-use super::{command, CommandRunner, FromCommandRunner, Machine};
+use super::appliance::{ExportFormat, ImportOptions};
+use super::command::{self, Tool};
+use super::config::{BootDevice, MachineConfig};
+use super::disk::{DiskFormat, DiskOptions, DiskSlot, DiskStore};
+use super::guest::{Credentials, GuestProcess, GuestSession};
+use super::info::{MachineInfo, NicAttachment};
+use super::network::{self, NicConfig, PortForward, Protocol};
+use super::property::{self, GuestProperty, VariableScope};
+use super::serial::SerialMode;
+use super::shared_folder::SharedFolder;
+use super::uri::{DriverFactory, Listing, VmUri};
+use super::version::Version;
+use super::vmx::Vmx;
+use super::{CommandRunner, FromCommandRunner, Machine, MachineState};
 use std::borrow::Cow;
-use std::ffi::OsStr;
+use std::cell::RefCell;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::path::Path;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use super::error::*;
 
@@ -11,17 +31,219 @@ pub struct Driver<Cmd: CommandRunner> {
     inner: Rc<DriverImpl<Cmd>>,
 }
 
+/// VMware product `vmrun` talks to, its `-T` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostType {
+    /// Workstation.
+    Ws,
+    Fusion,
+    /// Workstation Player.
+    Player,
+    /// VMware Server 2.
+    Server,
+    /// ESXi host, requires `-h` remote host.
+    Esx,
+    /// vCenter, requires `-h` remote host.
+    Vc,
+}
+
+impl HostType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HostType::Ws => "ws",
+            HostType::Fusion => "fusion",
+            HostType::Player => "player",
+            HostType::Server => "server",
+            HostType::Esx => "esx",
+            HostType::Vc => "vc",
+        }
+    }
+}
+
+impl FromStr for HostType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ws" => Ok(HostType::Ws),
+            "fusion" => Ok(HostType::Fusion),
+            "player" => Ok(HostType::Player),
+            "server" => Ok(HostType::Server),
+            "esx" => Ok(HostType::Esx),
+            "vc" => Ok(HostType::Vc),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "unknown vmrun host type '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for HostType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Host selection flags passed to `vmrun` before command, `-T` host type and
+/// `-h`, `-u`, `-p` for its own remote mode (ESXi, vCenter, VMware Server).
+///
+/// Unset fields fall back to factory settings, host type is detected from
+/// `vmrun` help when neither sets it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VmrunHost {
+    pub host_type: Option<HostType>,
+    pub host: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+impl VmrunHost {
+    /// Reads `T`, `h` and `u` uri parameters, named after `vmrun` flags. The
+    /// password is never part of uris, it is set by `Factory::with_remote_host`
+    /// or taken from `VMCTRL_VMRUN_PASSWORD`.
+    ///
+    /// ```
+    /// use vmctrl::uri::VmUri;
+    /// use vmctrl::vmware::{HostType, VmrunHost};
+    ///
+    /// let uri: VmUri = "vmware:/vms/a.vmx?T=esx&h=https://esxi/sdk&u=root".parse().unwrap();
+    /// let host = VmrunHost::from_query(uri.query()).unwrap();
+    /// assert_eq!(host.host_type, Some(HostType::Esx));
+    /// assert_eq!(host.host.as_ref().map(|h| h.as_str()), Some("https://esxi/sdk"));
+    /// ```
+    pub fn from_query(query: &[(String, String)]) -> Result<Self> {
+        let param = |key: &str| query.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+        let host_type = match param("T") {
+            Some(host_type) => Some(host_type.parse()?),
+            None => None,
+        };
+        Ok(VmrunHost {
+            host_type,
+            host: param("h"),
+            user: param("u"),
+            password: None,
+        })
+    }
+
+    /// Fields of `self`, missing ones taken from `defaults`.
+    fn or(&self, defaults: &VmrunHost) -> VmrunHost {
+        VmrunHost {
+            host_type: self.host_type.or(defaults.host_type),
+            host: self.host.clone().or_else(|| defaults.host.clone()),
+            user: self.user.clone().or_else(|| defaults.user.clone()),
+            password: self.password.clone().or_else(|| defaults.password.clone()),
+        }
+    }
+
+    /// `vmrun` takes password only as argument, it is visible in process
+    /// listings of the host running it; errors never quote these arguments.
+    fn args(&self) -> Vec<OsString> {
+        let flags = [
+            ("-T", self.host_type.map(|t| t.as_str().to_string())),
+            ("-h", self.host.clone()),
+            ("-u", self.user.clone()),
+            ("-p", self.password.clone()),
+        ];
+        let mut args = Vec::new();
+        for (flag, value) in flags.iter() {
+            if let Some(value) = value {
+                args.push(flag.into());
+                args.push(value.into());
+            }
+        }
+        args
+    }
+}
+
 pub struct Factory<C: CommandRunner> {
+    vmrun_command: Tool,
+    ovftool_command: Cow<'static, OsStr>,
+    vdiskmanager_command: Cow<'static, OsStr>,
+    vm_dirs: Vec<String>,
+    nat_conf: Option<String>,
+    host: VmrunHost,
+    guest_credentials: Option<Credentials>,
     marker: PhantomData<C>,
 }
 
 #[inline]
 pub fn factory<C: CommandRunner>() -> Factory<C> {
     Factory {
+        vmrun_command: Tool::discover("VMCTRL_VMRUN", VMRUN_COMMANDS, &[]),
+        ovftool_command: Cow::Borrowed("ovftool".as_ref()),
+        vdiskmanager_command: Cow::Borrowed("vmware-vdiskmanager".as_ref()),
+        vm_dirs: Vec::new(),
+        nat_conf: None,
+        host: VmrunHost::default(),
+        guest_credentials: None,
         marker: PhantomData,
     }
 }
 
+impl<C: CommandRunner> Factory<C> {
+    /// Overrides path to `vmrun` binary on target host, by default it is taken
+    /// from `VMCTRL_VMRUN` for local host or searched for.
+    pub fn with_command<P: Into<OsString>>(mut self, vmrun_command: P) -> Self {
+        self.vmrun_command = Tool::explicit(vmrun_command);
+        self
+    }
+
+    /// Overrides path to `ovftool` binary used for appliance import and export.
+    pub fn with_ovftool<P: Into<OsString>>(mut self, ovftool_command: P) -> Self {
+        self.ovftool_command = Cow::Owned(ovftool_command.into());
+        self
+    }
+
+    /// Overrides path to `vmware-vdiskmanager` binary used for disk images.
+    pub fn with_vdiskmanager<P: Into<OsString>>(mut self, vdiskmanager_command: P) -> Self {
+        self.vdiskmanager_command = Cow::Owned(vdiskmanager_command.into());
+        self
+    }
+
+    /// Directory on target host scanned for `.vmx` files by `list_all`, in addition
+    /// to Workstation/Fusion inventory and default VM directory.
+    pub fn with_vm_dir<P: Into<String>>(mut self, vm_dir: P) -> Self {
+        self.vm_dirs.push(vm_dir.into());
+        self
+    }
+
+    /// Overrides path to NAT service configuration edited by port forwarding,
+    /// by default first existing of the Workstation/Fusion locations is used.
+    pub fn with_nat_conf<P: Into<String>>(mut self, nat_conf: P) -> Self {
+        self.nat_conf = Some(nat_conf.into());
+        self
+    }
+
+    /// Passes `-T host_type` to `vmrun` instead of detecting it, `T` uri
+    /// parameter still takes precedence.
+    pub fn with_host_type(mut self, host_type: HostType) -> Self {
+        self.host.host_type = Some(host_type);
+        self
+    }
+
+    /// Makes `vmrun` manage machines of remote ESXi/vCenter `host` (e.g.
+    /// `https://esxi.local/sdk`) itself, machine paths are then datastore paths
+    /// like `[datastore1] vm/vm.vmx`. Needs explicit host type.
+    pub fn with_remote_host<H, U, P>(mut self, host: H, user: U, password: P) -> Self
+    where
+        H: Into<String>,
+        U: Into<String>,
+        P: Into<String>,
+    {
+        self.host.host = Some(host.into());
+        self.host.user = Some(user.into());
+        self.host.password = Some(password.into());
+        self
+    }
+
+    /// Guest account of machines opened by this driver, kept out of their uris.
+    pub fn with_guest_credentials(mut self, credentials: Credentials) -> Self {
+        self.guest_credentials = Some(credentials);
+        self
+    }
+}
+
 impl<C: CommandRunner> command::FromCommandRunner for Factory<C> {
     type Command = C;
     type Output = Driver<C>;
@@ -30,7 +252,15 @@ impl<C: CommandRunner> command::FromCommandRunner for Factory<C> {
         Driver {
             inner: Rc::new(DriverImpl {
                 command_runner: cmd,
-                vmrun_command: Cow::Borrowed("vmrun".as_ref()),
+                vmrun_command: self.vmrun_command.clone(),
+                ovftool_command: self.ovftool_command.clone(),
+                vdiskmanager_command: self.vdiskmanager_command.clone(),
+                vm_dirs: self.vm_dirs.clone(),
+                nat_conf: self.nat_conf.clone(),
+                host: self.host.clone(),
+                guest_credentials: self.guest_credentials.clone(),
+                help: RefCell::new(None),
+                host_type: RefCell::new(None),
             }),
         }
     }
@@ -42,87 +272,813 @@ impl<C: CommandRunner> DriverImpl<C> {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        Ok(self
-            .command_runner
-            .run_with_output(&self.vmrun_command, args)?)
+        self.run_as(&VmrunHost::default(), args)
+    }
+
+    /// Runs `vmrun` against `host`, completed with factory settings.
+    fn run_as<I, S>(&self, host: &VmrunHost, args: I) -> Result<command::Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut host = host.or(&self.host);
+        if host.host_type.is_none() && host.host.is_none() {
+            host.host_type = self.host_type();
+        }
+        if host.host.is_some() && host.password.is_none() {
+            host.password = env::var(REMOTE_PASSWORD_VAR).ok();
+        }
+        let mut cmd_args = host.args();
+        cmd_args.extend(args.into_iter().map(|arg| arg.as_ref().to_os_string()));
+        let output = self
+            .vmrun_command
+            .run_with_output(&self.command_runner, &cmd_args)?;
+        // some vmrun versions exit with 0 after printing error.
+        match vmrun_error(vmrun_subcommand(&cmd_args), output.lines()) {
+            Some(message) => bail!(ErrorKind::Hypervisor(message)),
+            None => Ok(output),
+        }
+    }
+
+    /// Host type of local `vmrun`, detected once from its help and installed product.
+    fn host_type(&self) -> Option<HostType> {
+        if let Some(host_type) = *self.host_type.borrow() {
+            return host_type;
+        }
+        let installed = PRODUCT_FILES
+            .iter()
+            .find(|(path, _)| self.command_runner.file_exists(path).unwrap_or(false))
+            .map(|&(_, host_type)| host_type);
+        let host_type = host_type_detect(&self.help().unwrap_or_default(), installed);
+        *self.host_type.borrow_mut() = Some(host_type);
+        host_type
+    }
+
+    /// Help `vmrun` prints when run without arguments, starting with its
+    /// version and listing supported host types.
+    fn help(&self) -> Result<String> {
+        if let Some(ref help) = *self.help.borrow() {
+            return Ok(help.clone());
+        }
+        let no_args: [&str; 0] = [];
+        // vmrun exits with error after printing help.
+        let help = match self
+            .vmrun_command
+            .run_with_output(&self.command_runner, no_args)
+        {
+            Ok(output) => output.into_iter().collect::<Vec<_>>().join("\n"),
+            Err(Error(ErrorKind::Exec(_, _, stdout), _)) => {
+                String::from_utf8_lossy(stdout.as_bytes()).into_owned()
+            }
+            Err(e) => return Err(e),
+        };
+        *self.help.borrow_mut() = Some(help.clone());
+        Ok(help)
+    }
+
+    fn running_paths(&self, host: &VmrunHost) -> Result<Vec<String>> {
+        let output = self.run_as(host, ["list"])?;
+
+        let mut it = output.into_iter();
+
+        let n = match it.next() {
+            Some(ref line) => if let Some(nstr) = line.strip_prefix(VM_LIST_PREFIX) {
+                nstr.parse::<usize>()
+                    .chain_err(|| ErrorKind::InvalidResponse(line.to_string()))?
+            } else {
+                return Err(ErrorKind::InvalidResponse(line.to_string()).into());
+            },
+            None => return Err(ErrorKind::MissingSummary.into()),
+        };
+
+        Ok(it.take(n).collect())
+    }
+
+    /// Runs `ovftool`, forwarding its progress output to `options`.
+    fn ovftool<I, S>(&self, args: I, options: Option<&ImportOptions>) -> Result<command::Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command_runner
+            .run_with_progress(&self.ovftool_command, args, &mut |chunk| {
+                if let Some(options) = options {
+                    options.report(chunk)
+                }
+            })
+    }
+
+    fn vdiskmanager<I, S>(&self, args: I) -> Result<command::Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.command_runner
+            .run_with_output(&self.vdiskmanager_command, args)
+    }
+
+    /// Resolves path relative to user home directory on hypervisor host,
+    /// remote commands already start there.
+    fn home_path(&self, path: &str) -> String {
+        if self.command_runner.transport().is_some() {
+            return path.into();
+        }
+        match env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+            Some(home) => Path::new(&home).join(path).to_string_lossy().into_owned(),
+            None => path.into(),
+        }
+    }
+
+    /// Reads first existing file in vmx syntax.
+    fn read_settings(&self, candidates: &[&str]) -> Option<Vmx> {
+        candidates.iter().find_map(|path| {
+            let text = self.command_runner.read_file(&self.home_path(path)).ok()?;
+            Vmx::parse(&text).ok()
+        })
+    }
+
+    /// Running machines, machines from Workstation/Fusion inventory and `.vmx`
+    /// files found in VM directories.
+    fn registered_paths(&self, host: &VmrunHost) -> Result<Vec<String>> {
+        let mut paths = self.running_paths(host)?;
+
+        if let Some(inventory) = self.read_settings(INVENTORY_FILES) {
+            paths.extend(
+                inventory
+                    .entries()
+                    .filter(|(key, value)| {
+                        key.ends_with(".config") && value.to_ascii_lowercase().ends_with(".vmx")
+                    })
+                    .map(|(_, value)| value.to_string()),
+            );
+        }
+
+        for dir in &self.vm_dirs {
+            paths.extend(self.find_vmx(dir)?);
+        }
+        let default_dir = self
+            .read_settings(PREFERENCES_FILES)
+            .and_then(|prefs| prefs.get("prefvmx.defaultVMPath").map(|s| s.to_string()));
+        if let Some(dir) = default_dir {
+            // directory may not exist until first machine is created.
+            paths.extend(self.find_vmx(&dir).unwrap_or_default());
+        }
+
+        let mut seen = Vec::new();
+        paths.retain(|path| {
+            if seen.contains(path) {
+                false
+            } else {
+                seen.push(path.clone());
+                true
+            }
+        });
+        Ok(paths)
+    }
+
+    fn find_vmx(&self, dir: &str) -> Result<Vec<String>> {
+        self.command_runner.find_files(dir, 3, "vmx")
+    }
+
+    /// Path and contents of NAT service configuration.
+    fn read_nat_conf(&self) -> Result<(String, String)> {
+        let candidates = match self.nat_conf {
+            Some(ref path) => vec![path.as_str()],
+            None => NAT_FILES.to_vec(),
+        };
+        for path in candidates {
+            if let Ok(text) = self.command_runner.read_file(path) {
+                return Ok((path.to_string(), text));
+            }
+        }
+        bail!("NAT configuration not found, set its path with `with_nat_conf`")
+    }
+
+    /// `write_file` replaces the file atomically, NAT service never sees it
+    /// half written.
+    fn write_nat_conf(&self, path: &str, text: &str) -> Result<()> {
+        self.command_runner
+            .write_file(path, text)
+            .chain_err(|| format!("writing {}", path))
+    }
+
+    fn machine_uri(&self, path: &str, query: &[(String, String)]) -> VmUri {
+        let uri = VmUri::new("vmware", path).with_query(query.to_vec());
+        match self.command_runner.transport() {
+            Some(transport) => uri.over(&transport),
+            None => uri,
+        }
     }
 }
 
 struct DriverImpl<Cmd: CommandRunner> {
     command_runner: Cmd,
-    vmrun_command: Cow<'static, OsStr>,
+    vmrun_command: Tool,
+    ovftool_command: Cow<'static, OsStr>,
+    vdiskmanager_command: Cow<'static, OsStr>,
+    vm_dirs: Vec<String>,
+    nat_conf: Option<String>,
+    host: VmrunHost,
+    guest_credentials: Option<Credentials>,
+    /// `vmrun` help, read once for host type and version.
+    help: RefCell<Option<String>>,
+    /// Detected host type, `Some(None)` when detection found none.
+    host_type: RefCell<Option<Option<HostType>>>,
 }
 
 pub struct MachineRef<Cmd: CommandRunner> {
     driver_ref: Rc<DriverImpl<Cmd>>,
     path: String,
+    query: Vec<(String, String)>,
 }
 
 impl<Cmd: CommandRunner> Driver<Cmd> {
-    fn machine(&self, path: String) -> MachineRef<Cmd> {
+    fn machine(&self, path: String, query: Vec<(String, String)>) -> MachineRef<Cmd> {
         MachineRef {
             driver_ref: self.inner.clone(),
             path,
+            query,
         }
     }
 }
 
+impl<Cmd: CommandRunner> MachineRef<Cmd> {
+    /// Runs `vmrun` against host selected by machine uri.
+    fn run<I, S>(&self, args: I) -> Result<command::Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.driver_ref
+            .run_as(&VmrunHost::from_query(&self.query)?, args)
+    }
+}
+
+impl<Cmd: CommandRunner + 'static> MachineRef<Cmd> {
+    /// Reads machine `.vmx` file from hypervisor host.
+    pub fn vmx(&self) -> Result<Vmx> {
+        let text = self
+            .driver_ref
+            .command_runner
+            .read_file(&self.path)
+            .chain_err(|| format!("reading {}", self.path))?;
+        Vmx::parse(&text)
+    }
+
+    /// Edits `.vmx` file, machine must be powered off or VMware overwrites it.
+    /// Suspended machines, which vmrun reports as powered off, are refused too:
+    /// their saved state no longer matches edited hardware.
+    pub fn update_vmx<F: FnOnce(&mut Vmx)>(&mut self, f: F) -> Result<()> {
+        self.ensure_stopped()?;
+        let mut vmx = self.vmx()?;
+        let runner = &self.driver_ref.command_runner;
+        let saved_state = vmx
+            .get("checkpoint.vmState")
+            .filter(|path| !path.is_empty());
+        if saved_state.is_some() || runner.file_exists(&self.sibling_path("vmss"))? {
+            bail!(ErrorKind::MachineSuspended(self.path.clone()))
+        }
+        f(&mut vmx);
+        self.driver_ref
+            .command_runner
+            .write_file(&self.path, &vmx.to_string())
+            .chain_err(|| format!("writing {}", self.path))
+    }
+
+    /// Sibling file with `ext` extension, e.g. `.vmsd` next to `.vmx`.
+    fn sibling_path(&self, ext: &str) -> String {
+        match self.path.rfind('.') {
+            Some(pos) => format!("{}.{}", &self.path[..pos], ext),
+            None => format!("{}.{}", self.path, ext),
+        }
+    }
+
+    /// `vmrun` arguments reading or writing variable of property `name`.
+    fn variable_args(&self, command: &str, name: &str) -> Vec<String> {
+        let (scope, variable) = VariableScope::split(name);
+        let mut args = Vec::new();
+        if scope == VariableScope::GuestEnv {
+            args.extend(self.guest_args());
+        }
+        args.extend(vec![
+            command.to_string(),
+            self.path.clone(),
+            scope.as_str().into(),
+            variable.into(),
+        ]);
+        args
+    }
+
+    /// `-gu`, `-gp` options with guest credentials of the machine, if any.
+    /// Password is passed as argument, see `VmrunHost::args`.
+    fn guest_args(&self) -> Vec<String> {
+        match super::Machine::guest_credentials(self) {
+            Some(credentials) => vec![
+                "-gu".to_string(),
+                credentials.user,
+                "-gp".into(),
+                credentials.password,
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    /// `file_name` from `.vmx`, relative ones are resolved against its directory.
+    /// Windows hosts separate directories with `\`, which is kept.
+    fn host_path(&self, file_name: &str) -> String {
+        let absolute = file_name.starts_with(['/', '\\']) || file_name.contains(':');
+        match self.path.rfind(['/', '\\']) {
+            Some(pos) if !absolute => format!("{}{}", &self.path[..=pos], file_name),
+            _ => file_name.into(),
+        }
+    }
+
+    fn vmsd_path(&self) -> String {
+        self.sibling_path("vmsd")
+    }
+
+    /// Linked clones recorded in `.vmsd` whose `.vmx` still exists.
+    fn linked_clones(&self) -> Vec<String> {
+        let runner = &self.driver_ref.command_runner;
+        let vmsd = match runner.read_file(&self.vmsd_path()) {
+            Ok(text) => Vmx::parse(&text).unwrap_or_default(),
+            Err(_) => return Vec::new(),
+        };
+        vmsd.clones()
+            .into_iter()
+            .filter(|clone| runner.read_file(clone).is_ok())
+            .map(|clone| clone.to_string())
+            .collect()
+    }
+
+    /// Address VMware Tools report for the guest, machine must be running.
+    fn guest_ip(&self) -> Result<String> {
+        let output = self.run(["getGuestIPAddress", &self.path])?;
+        output
+            .into_iter()
+            .next()
+            .filter(|ip| !ip.is_empty())
+            .chain_err(|| ErrorKind::MissingSummary)
+    }
+
+    fn ensure_stopped(&self) -> Result<()> {
+        if super::Machine::state(self)? == MachineState::Running {
+            bail!(ErrorKind::MachineRunning(self.path.clone()))
+        }
+        Ok(())
+    }
+}
+
 impl<C: CommandRunner + Default> Default for Driver<C> {
     fn default() -> Self {
         Driver {
             inner: Rc::new(DriverImpl {
                 command_runner: C::default(),
-                vmrun_command: Cow::Borrowed("vmrun".as_ref()),
+                vmrun_command: Tool::discover("VMCTRL_VMRUN", VMRUN_COMMANDS, &[]),
+                ovftool_command: Cow::Borrowed("ovftool".as_ref()),
+                vdiskmanager_command: Cow::Borrowed("vmware-vdiskmanager".as_ref()),
+                vm_dirs: Vec::new(),
+                nat_conf: None,
+                host: VmrunHost::default(),
+                guest_credentials: None,
+                help: RefCell::new(None),
+                host_type: RefCell::new(None),
             }),
         }
     }
 }
 
+/// Environment variable with password for `vmrun -h` remote host.
+const REMOTE_PASSWORD_VAR: &str = "VMCTRL_VMRUN_PASSWORD";
+
+/// Locations of `vmrun` tried in order, Fusion keeps it inside app bundle.
+const VMRUN_COMMANDS: &[&str] = &[
+    "vmrun",
+    "/Applications/VMware Fusion.app/Contents/Library/vmrun",
+    "C:\\Program Files (x86)\\VMware\\VMware Workstation\\vmrun.exe",
+    "C:\\Program Files\\VMware\\VMware Workstation\\vmrun.exe",
+    "C:\\Program Files (x86)\\VMware\\VMware VIX\\vmrun.exe",
+];
+
 const VM_LIST_PREFIX: &str = "Total running VMs: ";
 const VM_SNAPSHOTS_PREFIX: &str = "Total snapshots: ";
 
-impl<Cmd: CommandRunner> super::Driver for Driver<Cmd> {
+/// Machine inventory of Workstation (Linux, Windows) and Fusion, relative to home.
+const INVENTORY_FILES: &[&str] = &[
+    ".vmware/inventory.vmls",
+    "AppData/Roaming/VMware/inventory.vmls",
+    "Library/Application Support/VMware Fusion/vmInventory",
+];
+
+/// User preferences holding `prefvmx.defaultVMPath`, relative to home.
+const PREFERENCES_FILES: &[&str] = &[
+    ".vmware/preferences",
+    "AppData/Roaming/VMware/preferences.ini",
+    "Library/Preferences/VMware Fusion/preferences",
+];
+
+/// VIX messages `vmrun` prints on failure, prefixed by `Error: ` in most
+/// versions but not all.
+const VMRUN_ERRORS: &[&str] = &[
+    "Unknown error",
+    "The operation is not supported",
+    "One of the parameters was invalid",
+    "Invalid argument",
+    "A file was not found",
+    "Cannot open VM",
+    "The virtual machine cannot be found",
+    "The virtual machine needs to be powered on",
+    "The virtual machine is not powered on",
+    "The virtual machine should not be powered on",
+    "The name does not uniquely identify one snapshot",
+    "The VMware Tools are not running in the virtual machine",
+    "Insufficient permissions in the host operating system",
+    "Insufficient permissions in the guest operating system",
+    "Invalid user name or password for the guest OS",
+    "The specified guest user must be logged in interactively",
+    "Cannot connect to host",
+    "Unable to connect to host",
+    "The operation was canceled",
+];
+
+/// `vmrun` command in arguments, following global options and their values.
+fn vmrun_subcommand(args: &[OsString]) -> &str {
+    let mut it = args.iter().map(|arg| arg.to_str().unwrap_or(""));
+    while let Some(arg) = it.next() {
+        if !arg.starts_with('-') {
+            return arg;
+        }
+        let _ = it.next();
+    }
+    ""
+}
+
+/// Error message in `vmrun` output of `command`, `Error: ` line or known
+/// message starting a line.
+///
+/// Commands printing data are only checked on the first line, when it does
+/// not look like their data; `readVariable` prints guest data only.
+fn vmrun_error(command: &str, lines: &[String]) -> Option<String> {
+    let is_data: fn(&str) -> bool = match command {
+        "list" => |line| line.starts_with(VM_LIST_PREFIX),
+        "listSnapshots" => |line| line.starts_with(VM_SNAPSHOTS_PREFIX),
+        "listProcessesInGuest" => |line| line.starts_with("Process list:"),
+        "getGuestIPAddress" => |line| line.parse::<IpAddr>().is_ok(),
+        "readVariable" => return None,
+        _ => return lines.iter().find_map(|line| line_error(line)),
+    };
+    lines
+        .first()
+        .filter(|line| !is_data(line.trim()))
+        .and_then(|line| line_error(line))
+}
+
+fn line_error(line: &str) -> Option<String> {
+    let line = line.trim();
+    if let Some(message) = line.strip_prefix("Error:") {
+        return Some(message.trim().to_string());
+    }
+    VMRUN_ERRORS
+        .iter()
+        .find(|known| line.starts_with(*known))
+        .map(|_| line.to_string())
+}
+
+/// Files telling which product is installed on the host, in order: Fusion,
+/// Workstation (which ships `vmplayer` too) and Player.
+const PRODUCT_FILES: &[(&str, HostType)] = &[
+    ("/Applications/VMware Fusion.app", HostType::Fusion),
+    ("/usr/bin/vmware", HostType::Ws),
+    (
+        "C:\\Program Files (x86)\\VMware\\VMware Workstation\\vmware.exe",
+        HostType::Ws,
+    ),
+    (
+        "C:\\Program Files\\VMware\\VMware Workstation\\vmware.exe",
+        HostType::Ws,
+    ),
+    ("/usr/bin/vmplayer", HostType::Player),
+    (
+        "C:\\Program Files (x86)\\VMware\\VMware Player\\vmplayer.exe",
+        HostType::Player,
+    ),
+    (
+        "C:\\Program Files (x86)\\VMware\\VMware Workstation\\vmplayer.exe",
+        HostType::Player,
+    ),
+];
+
+/// Host type for `vmrun -T`: the only one listed by the `-T <hostType>
+/// (ws|fusion|player)` line of its help, or the `installed` product when
+/// listed. The help is the same for every product, so without either `vmrun`
+/// is better left to pick the host type itself.
+fn host_type_detect(help: &str, installed: Option<HostType>) -> Option<HostType> {
+    let types: Vec<HostType> = help
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| line.starts_with("-T"))
+        .and_then(|line| {
+            let start = line.find('(')? + 1;
+            let end = line.rfind(')')?;
+            if start > end {
+                return None;
+            }
+            let list = &line[start..end];
+            Some(
+                list.split('|')
+                    .filter_map(|t| t.trim().parse().ok())
+                    .collect(),
+            )
+        })
+        .unwrap_or_default();
+    if types.len() == 1 {
+        return types.first().cloned();
+    }
+    installed.filter(|installed| types.is_empty() || types.contains(installed))
+}
+
+/// `.vmx` device name of `slot`, e.g. `scsi0:1`.
+fn vmx_device(slot: &DiskSlot) -> Result<String> {
+    if slot.device != 0 {
+        bail!(ErrorKind::InvalidConfig(format!(
+            "VMware addresses disks by controller and unit, device must be 0, not {}",
+            slot.device
+        )))
+    }
+    Ok(format!("{}:{}", slot.controller, slot.port))
+}
+
+/// NAT service configuration of Workstation (Linux, Windows) and Fusion.
+/// The Windows one uses CRLF line endings, which edits keep; it is read by
+/// local driver, over ssh only hosts with `cat` are supported (see
+/// `CommandRunner::read_file`).
+const NAT_FILES: &[&str] = &[
+    "/etc/vmware/vmnet8/nat/nat.conf",
+    "C:/ProgramData/VMware/vmnetnat.conf",
+    "/Library/Preferences/VMware Fusion/vmnet8/nat.conf",
+];
+
+/// Comment preceding NAT rules added by this crate, followed by
+/// `<slot> <name> <vmx path>`.
+const NAT_RULE_TAG: &str = "# vmctrl ";
+
+fn nat_section(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "[incomingtcp]",
+        Protocol::Udp => "[incomingudp]",
+    }
+}
+
+/// Splits `<slot> <name> <vmx path>` of rule tag.
+fn nat_tag_parse(line: &str) -> Option<(u32, &str, &str)> {
+    let mut it = line.strip_prefix(NAT_RULE_TAG)?.splitn(3, ' ');
+    let slot = it.next()?.parse().ok()?;
+    let name = it.next()?;
+    let path = it.next()?;
+    Some((slot, name, path))
+}
+
+/// Parses `host_port = guest_ip:guest_port` rule.
+fn nat_rule_parse(line: &str) -> Option<(u16, &str, u16)> {
+    let pos = line.find('=')?;
+    let host_port = line[..pos].trim().parse().ok()?;
+    let target = line[pos + 1..].trim();
+    let colon = target.rfind(':')?;
+    let guest_port = target[colon + 1..].parse().ok()?;
+    Some((host_port, &target[..colon], guest_port))
+}
+
+/// Rules tagged with `slot` and `vmx_path`, in order of appearance.
+fn nat_rules_parse(text: &str, slot: u32, vmx_path: &str) -> Vec<PortForward> {
+    let mut rules = Vec::new();
+    let mut protocol = None;
+    let mut lines = text.lines().map(|line| line.trim());
+
+    while let Some(line) = lines.next() {
+        if line.starts_with('[') {
+            protocol = match line {
+                "[incomingtcp]" => Some(Protocol::Tcp),
+                "[incomingudp]" => Some(Protocol::Udp),
+                _ => None,
+            };
+            continue;
+        }
+        let (protocol, name) = match (protocol, nat_tag_parse(line)) {
+            (Some(protocol), Some((s, name, path))) if s == slot && path == vmx_path => {
+                (protocol, name)
+            }
+            _ => continue,
+        };
+        if let Some((host_port, guest_ip, guest_port)) = lines.next().and_then(nat_rule_parse) {
+            rules.push(PortForward {
+                name: name.into(),
+                protocol,
+                host_ip: None,
+                host_port,
+                guest_ip: Some(guest_ip.into()),
+                guest_port,
+            });
+        }
+    }
+    rules
+}
+
+/// Line ending of NAT configuration, CRLF on Windows.
+fn nat_line_ending(text: &str) -> &'static str {
+    if text.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Adds tagged rule at the top of its protocol section, creating the section
+/// if missing. Rule names are unique per machine adapter.
+fn nat_rule_add(text: &str, slot: u32, vmx_path: &str, rule: &PortForward) -> Result<String> {
+    let section = nat_section(rule.protocol);
+    let mut in_section = false;
+    for line in text.lines().map(|line| line.trim()) {
+        if nat_tag_parse(line) == Some((slot, &rule.name, vmx_path)) {
+            bail!(ErrorKind::InvalidConfig(format!(
+                "port forwarding rule '{}' already exists",
+                rule.name
+            )))
+        }
+        if line.starts_with('[') {
+            in_section = line == section;
+        } else if in_section
+            && nat_rule_parse(line).is_some_and(|(port, _, _)| port == rule.host_port)
+        {
+            bail!(ErrorKind::InvalidConfig(format!(
+                "host port {} already forwarded",
+                rule.host_port
+            )))
+        }
+    }
+
+    let eol = nat_line_ending(text);
+    let entry = format!(
+        "{}{} {} {}{}{} = {}:{}{}",
+        NAT_RULE_TAG,
+        slot,
+        rule.name,
+        vmx_path,
+        eol,
+        rule.host_port,
+        rule.guest_ip.as_ref().map_or("", |ip| ip.as_str()),
+        rule.guest_port,
+        eol
+    );
+    let mut result = String::new();
+    let mut added = false;
+    for line in text.lines() {
+        result.push_str(line);
+        result.push_str(eol);
+        if !added && line.trim() == section {
+            result.push_str(&entry);
+            added = true;
+        }
+    }
+    if !added {
+        result.push_str(&format!("{}{}{}{}", eol, section, eol, entry));
+    }
+    Ok(result)
+}
+
+/// Drops tagged rule `name` and the rule line following it.
+fn nat_rule_remove(text: &str, slot: u32, vmx_path: &str, name: &str) -> Option<String> {
+    let eol = nat_line_ending(text);
+    let mut result = String::new();
+    let mut removed = false;
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        match nat_tag_parse(line.trim()) {
+            Some((s, n, path)) if s == slot && n == name && path == vmx_path => {
+                let _ = lines.next();
+                removed = true;
+            }
+            _ => {
+                result.push_str(line);
+                result.push_str(eol);
+            }
+        }
+    }
+    if removed {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+impl<Cmd: CommandRunner + 'static> super::Driver for Driver<Cmd> {
     type Machine = MachineRef<Cmd>;
 
     fn list_running(&self) -> Result<Vec<MachineRef<Cmd>>> {
-        let output = self.inner.run(&["list"])?;
+        self.inner
+            .running_paths(&VmrunHost::default())?
+            .into_iter()
+            .map(|path| self.from_path(&path))
+            .collect()
+    }
 
-        let mut it = output.into_iter();
+    fn list_all(&self) -> Result<Vec<MachineRef<Cmd>>> {
+        self.inner
+            .registered_paths(&VmrunHost::default())?
+            .into_iter()
+            .map(|path| self.from_path(&path))
+            .collect()
+    }
 
-        let n = match it.next() {
-            Some(ref line) => if line.starts_with(VM_LIST_PREFIX) {
-                let nstr: &str = line[VM_LIST_PREFIX.len()..].as_ref();
-                nstr.parse::<usize>()
-                    .chain_err(|| ErrorKind::InvalidResponse(line.to_string()))?
-            } else {
-                return Err(ErrorKind::InvalidResponse(line.to_string()).into());
-            },
-            None => return Err(ErrorKind::MissingSummary.into()),
+    fn register(&self, path: &str) -> Result<MachineRef<Cmd>> {
+        let _ = self.inner.run(["register", path])?;
+        self.from_path(path)
+    }
+
+    /// Machine is created as `<base_folder>/<name>/<name>.vmx`, name defaults
+    /// to appliance file name.
+    fn import_appliance(&self, path: &str, options: &ImportOptions) -> Result<MachineRef<Cmd>> {
+        let base_folder = match options.base_folder {
+            Some(ref base_folder) => base_folder.trim_end_matches('/'),
+            None => bail!("importing {} requires base folder", path),
+        };
+        let name = match options.name {
+            Some(ref name) => name.clone(),
+            None => Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .chain_err(|| ErrorKind::InvalidUri(path.into()))?,
         };
 
-        //let mut vms: Vec<Self::Machine> = Vec::with_capacity(n);
+        let mut args = vec![format!("--name={}", name)];
+        if options.accept_eulas {
+            args.push("--acceptAllEulas".into());
+        }
+        if let Some(cpus) = options.cpus {
+            args.push(format!("--numberOfCpus:*={}", cpus));
+        }
+        if let Some(memory_mb) = options.memory_mb {
+            args.push(format!("--memorySize:*={}", memory_mb));
+        }
+        args.push(path.into());
+        args.push(base_folder.into());
+        let _ = self.inner.ovftool(args, Some(options))?;
 
-        it.take(n).map(|path| self.from_path(&path)).collect()
+        self.from_path(&format!("{}/{}/{}.vmx", base_folder, name, name))
     }
 
     fn from_path(&self, path: &str) -> Result<MachineRef<Cmd>> {
-        Ok(self.machine(path.to_string()))
+        Ok(self.machine(path.to_string(), Vec::new()))
+    }
+
+    /// Version of `vmrun` itself (VIX API), not of Workstation/Fusion.
+    fn version(&self) -> Result<Version> {
+        let help = self.inner.help()?;
+        let line = help
+            .lines()
+            .find(|line| line.starts_with("vmrun version"))
+            .chain_err(|| ErrorKind::MissingSummary)?;
+        Version::find(line).chain_err(|| ErrorKind::InvalidResponse(line.into()))
     }
 }
 
-impl<Cmd: CommandRunner> super::Machine for MachineRef<Cmd> {
+impl<Cmd: CommandRunner + 'static> super::Machine for MachineRef<Cmd> {
     fn name(&self) -> &str {
         self.path.as_ref()
     }
 
-    fn list_snapshots(&self) -> Result<Vec<String>> {
-        let mut lines = self
+    fn uri(&self) -> VmUri {
+        self.driver_ref.machine_uri(&self.path, &self.query)
+    }
+
+    /// vmrun only reports running machines, suspended ones show up as powered off.
+    fn state(&self) -> Result<MachineState> {
+        if self
             .driver_ref
-            .run(&["listSnapshots", &self.path])?
-            .into_iter();
+            .running_paths(&VmrunHost::from_query(&self.query)?)?
+            .contains(&self.path)
+        {
+            Ok(MachineState::Running)
+        } else {
+            Ok(MachineState::PoweredOff)
+        }
+    }
+
+    fn info(&self) -> Result<MachineInfo> {
+        let mut info = self.vmx()?.to_info(&self.path, self.state()?);
+        // .vmsd is missing until first snapshot is taken.
+        if let Ok(text) = self.driver_ref.command_runner.read_file(&self.vmsd_path()) {
+            if let Ok(vmsd) = Vmx::parse(&text) {
+                info.current_snapshot = vmsd.current_snapshot().map(|s| s.to_string());
+            }
+        }
+        Ok(info)
+    }
+
+    fn list_snapshots(&self) -> Result<Vec<String>> {
+        let mut lines = self.run(["listSnapshots", &self.path])?.into_iter();
         let summary: String = lines.next().chain_err(|| ErrorKind::MissingSummary)?;
-        let _n = if summary.starts_with(VM_SNAPSHOTS_PREFIX) {
-            let s: &str = summary[VM_SNAPSHOTS_PREFIX.len()..].as_ref();
+        let _n = if let Some(s) = summary.strip_prefix(VM_SNAPSHOTS_PREFIX) {
             s.parse::<usize>()
                 .chain_err(|| ErrorKind::InvalidResponse(summary.clone()))?
         } else {
@@ -132,55 +1088,877 @@ impl<Cmd: CommandRunner> super::Machine for MachineRef<Cmd> {
     }
 
     fn stop(&mut self) -> Result<()> {
-        let _ = self
-            .driver_ref
-            .run(&["stop", &self.path, "hard"])?
-            .into_iter();
+        let _ = self.run(["stop", &self.path, "hard"])?.into_iter();
         Ok(())
     }
 
     fn start(&mut self) -> Result<()> {
-        let _ = self
-            .driver_ref
-            .run(&["start", &self.path, "nogui"])?
-            .into_iter();
+        let _ = self.run(["start", &self.path, "nogui"])?.into_iter();
         Ok(())
     }
 
     fn revert_to(&mut self, snapshot_name: &str) -> Result<()> {
-        let _ = self
-            .driver_ref
-            .run(&["revertToSnapshot", &self.path, snapshot_name.as_ref()])?;
+        let _ = self.run(["revertToSnapshot", &self.path, snapshot_name])?;
         self.start()
     }
 
     fn create_snapshot(&mut self, snapshot_name: &str) -> Result<()> {
-        let _ = self
-            .driver_ref
-            .run(&["snapshot", &self.path, snapshot_name.as_ref()])?;
+        let _ = self.run(["snapshot", &self.path, snapshot_name])?;
+        Ok(())
+    }
+
+    fn delete_snapshot(&mut self, snapshot_name: &str) -> Result<()> {
+        let _ = self.run(["deleteSnapshot", &self.path, snapshot_name])?;
+        Ok(())
+    }
+
+    fn clone_to(
+        &self,
+        target: &str,
+        from_snapshot: Option<&str>,
+        linked: bool,
+    ) -> Result<Box<dyn Machine>> {
+        let mut args = vec![
+            "clone".to_string(),
+            self.path.clone(),
+            target.to_string(),
+            if linked { "linked" } else { "full" }.to_string(),
+        ];
+        if let Some(snapshot) = from_snapshot {
+            args.push(format!("-snapshot={}", snapshot));
+        }
+        let _ = self.run(args)?;
+
+        Ok(Box::new(MachineRef {
+            driver_ref: self.driver_ref.clone(),
+            path: target.into(),
+            query: self.query.clone(),
+        }))
+    }
+
+    fn unregister(&mut self) -> Result<()> {
+        self.ensure_stopped()?;
+        let _ = self.run(["unregister", &self.path])?;
+        Ok(())
+    }
+
+    /// Edits `.vmx`, VMware requires memory size to be multiple of 4 MB.
+    fn configure(&mut self, config: &MachineConfig) -> Result<()> {
+        config.validate()?;
+        if config.memory_mb.is_some_and(|memory_mb| memory_mb % 4 != 0) {
+            bail!(ErrorKind::InvalidConfig(
+                "memory must be multiple of 4 MB".into()
+            ))
+        }
+        if config.is_empty() {
+            return Ok(());
+        }
+
+        self.update_vmx(|vmx| {
+            if let Some(cpus) = config.cpus {
+                vmx.set_numvcpus(cpus);
+            }
+            if let Some(memory_mb) = config.memory_mb {
+                vmx.set_memsize(memory_mb);
+            }
+            if let Some(firmware) = config.firmware {
+                vmx.set("firmware", firmware.as_str());
+            }
+            if let Some(ref devices) = config.boot_order {
+                let order: Vec<&str> = devices
+                    .iter()
+                    .map(|device| match *device {
+                        BootDevice::Floppy => "floppy",
+                        BootDevice::Dvd => "cdrom",
+                        BootDevice::Disk => "hdd",
+                        BootDevice::Net => "ethernet",
+                    })
+                    .collect();
+                vmx.set("bios.bootOrder", order.join(","));
+            }
+        })
+    }
+
+    /// Edits `.vmx`, of running machine only the cable can be (dis)connected.
+    ///
+    /// Named networks use `custom` connection with `vnet` set to the name (e.g.
+    /// `VMnet2`), internal networks are LAN segments identified by `pvnID`.
+    fn configure_nic(&mut self, slot: u32, config: &NicConfig) -> Result<()> {
+        config.validate()?;
+        if config.is_empty() {
+            return Ok(());
+        }
+        if self.state()? == MachineState::Running {
+            if config.attachment.is_some() || config.network.is_some() || config.mac.is_some() {
+                bail!(ErrorKind::MachineRunning(self.path.clone()))
+            }
+            if let Some(connected) = config.cable_connected {
+                let command = if connected {
+                    "connectNamedDevice"
+                } else {
+                    "disconnectNamedDevice"
+                };
+                let device = format!("ethernet{}", slot);
+                let _ = self.run([command, &self.path, &device])?;
+            }
+            return Ok(());
+        }
+
+        let connection_type = match config.attachment {
+            Some(NicAttachment::None) if config.network.is_some() => bail!(
+                ErrorKind::InvalidConfig("disconnected adapter has no network".into())
+            ),
+            Some(NicAttachment::None) => None,
+            Some(NicAttachment::Nat) => Some("nat"),
+            Some(NicAttachment::Bridged) => Some("bridged"),
+            Some(NicAttachment::HostOnly) => Some("hostonly"),
+            Some(NicAttachment::Internal) => Some("pvn"),
+            Some(ref other) => bail!(ErrorKind::InvalidConfig(format!(
+                "{} adapter not supported by VMware",
+                other
+            ))),
+            None => None,
+        };
+        let mac = match config.mac {
+            Some(ref mac) => {
+                let digits = network::mac_digits(mac)?;
+                let octets: Vec<&str> = (0..6).map(|i| &digits[i * 2..i * 2 + 2]).collect();
+                Some(octets.join(":"))
+            }
+            None => None,
+        };
+
+        self.update_vmx(|vmx| {
+            if config.attachment == Some(NicAttachment::None) {
+                vmx.set_ethernet(slot, "present", "FALSE");
+            } else if config.attachment.is_some() && !vmx.ethernet_slots().contains(&slot) {
+                vmx.set_ethernet(slot, "present", "TRUE");
+                if vmx.ethernet(slot, "virtualDev").is_none() {
+                    vmx.set_ethernet(slot, "virtualDev", "e1000");
+                }
+            }
+            if let Some(connection_type) = connection_type {
+                vmx.set_ethernet(slot, "connectionType", connection_type);
+            }
+            if let Some(ref network) = config.network {
+                if vmx.ethernet(slot, "connectionType") == Some("pvn") {
+                    vmx.set_ethernet(slot, "pvnID", network);
+                } else {
+                    vmx.set_ethernet(slot, "connectionType", "custom");
+                    vmx.set_ethernet(slot, "vnet", network);
+                }
+            }
+            if let Some(ref mac) = mac {
+                vmx.set_ethernet(slot, "addressType", "static");
+                vmx.set_ethernet(slot, "address", mac);
+            }
+            if let Some(connected) = config.cable_connected {
+                vmx.set_ethernet(
+                    slot,
+                    "startConnected",
+                    if connected { "TRUE" } else { "FALSE" },
+                );
+            }
+        })
+    }
+
+    /// Rules this crate added to NAT service configuration for the machine.
+    fn port_forwards(&self, slot: u32) -> Result<Vec<PortForward>> {
+        let (_, text) = self.driver_ref.read_nat_conf()?;
+        Ok(nat_rules_parse(&text, slot, &self.path))
+    }
+
+    /// Adds rule to NAT service configuration shared by all machines on `vmnet8`,
+    /// guest address defaults to the one reported by VMware Tools. NAT service
+    /// reads its configuration on start, restart it to apply the rule (e.g.
+    /// `vmware-networks --stop && vmware-networks --start` on Linux).
+    fn add_port_forward(&mut self, slot: u32, rule: &PortForward) -> Result<()> {
+        rule.validate()?;
+        if rule.host_ip.is_some() {
+            bail!(ErrorKind::InvalidConfig(
+                "VMware NAT listens on all host addresses".into()
+            ))
+        }
+        if self.vmx()?.ethernet(slot, "connectionType") != Some("nat") {
+            bail!(ErrorKind::InvalidConfig(format!(
+                "adapter in slot {} is not attached to NAT",
+                slot
+            )))
+        }
+        let mut rule = rule.clone();
+        if rule.guest_ip.is_none() {
+            rule.guest_ip = Some(self.guest_ip()?);
+        }
+
+        let (path, text) = self.driver_ref.read_nat_conf()?;
+        let text = nat_rule_add(&text, slot, &self.path, &rule)?;
+        self.driver_ref.write_nat_conf(&path, &text)
+    }
+
+    fn remove_port_forward(&mut self, slot: u32, name: &str) -> Result<()> {
+        let (path, text) = self.driver_ref.read_nat_conf()?;
+        match nat_rule_remove(&text, slot, &self.path, name) {
+            Some(text) => self.driver_ref.write_nat_conf(&path, &text),
+            None => bail!(ErrorKind::InvalidConfig(format!(
+                "no port forwarding rule '{}'",
+                name
+            ))),
+        }
+    }
+
+    /// Adds `<controller>:<port>` device to `.vmx`, creating the controller if
+    /// missing. Relative `path` is resolved against the `.vmx` directory.
+    fn attach_disk(&mut self, slot: &DiskSlot, path: &str) -> Result<()> {
+        let device = vmx_device(slot)?;
+        let controller = slot.controller.clone();
+        self.update_vmx(|vmx| {
+            let present = vmx.get(&format!("{}.present", controller));
+            if !present.is_some_and(|v| v.eq_ignore_ascii_case("true")) {
+                vmx.set(format!("{}.present", controller), "TRUE");
+                if controller.starts_with("scsi") {
+                    vmx.set(format!("{}.virtualDev", controller), "lsilogic");
+                }
+            }
+            vmx.set(format!("{}.present", device), "TRUE");
+            // type of previously attached cdrom, VMware infers disks from file.
+            let _ = vmx.remove(&format!("{}.deviceType", device));
+            vmx.set_disk_file(&device, path);
+        })
+    }
+
+    fn detach_disk(&mut self, slot: &DiskSlot) -> Result<()> {
+        let device = vmx_device(slot)?;
+        self.update_vmx(|vmx| {
+            vmx.set(format!("{}.present", device), "FALSE");
+            let _ = vmx.remove(&format!("{}.fileName", device));
+        })
+    }
+
+    fn shared_folders(&self) -> Result<Vec<SharedFolder>> {
+        Ok(self.vmx()?.shared_folders())
+    }
+
+    /// Requires running machine, VMware Tools mount folders under the hgfs root
+    /// so mount point cannot be chosen.
+    fn add_shared_folder(&mut self, folder: &SharedFolder) -> Result<()> {
+        if folder.mount_point.is_some() || folder.transient {
+            bail!(ErrorKind::InvalidConfig(
+                "VMware shared folders have no mount point and are never transient".into()
+            ))
+        }
+        let _ = self.run([
+            "addSharedFolder",
+            &self.path,
+            &folder.name,
+            &folder.host_path,
+        ])?;
+        if !folder.writable {
+            let _ = self.run([
+                "setSharedFolderState",
+                &self.path,
+                &folder.name,
+                &folder.host_path,
+                "readonly",
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn remove_shared_folder(&mut self, name: &str) -> Result<()> {
+        let _ = self.run(["removeSharedFolder", &self.path, name])?;
+        Ok(())
+    }
+
+    fn set_shared_folders_enabled(&mut self, enabled: bool) -> Result<()> {
+        let command = if enabled {
+            "enableSharedFolders"
+        } else {
+            "disableSharedFolders"
+        };
+        let _ = self.run([command, &self.path])?;
+        Ok(())
+    }
+
+    /// `vmrun` requires guest credentials to capture screen, see
+    /// `Machine::guest_credentials`.
+    fn screenshot(&self, path: &str) -> Result<Vec<u8>> {
+        let mut args = self.guest_args();
+        args.extend(vec!["captureScreen".into(), self.path.clone(), path.into()]);
+        let _ = self.run(args)?;
+        let runner = &self.driver_ref.command_runner;
+        let image = runner.read_bytes(path);
+        let _ = runner.remove_file(path);
+        image
+    }
+
+    fn guest_property(&self, name: &str) -> Result<Option<String>> {
+        let output = self.run(self.variable_args("readVariable", name))?;
+        let value = output.into_iter().collect::<Vec<_>>().join("\n");
+        Ok(Some(value).filter(|value| !value.is_empty()))
+    }
+
+    fn set_guest_property(&mut self, name: &str, value: &str) -> Result<()> {
+        let mut args = self.variable_args("writeVariable", name);
+        args.push(value.into());
+        let _ = self.run(args)?;
+        Ok(())
+    }
+
+    /// Variables cannot be removed, their value is emptied.
+    fn delete_guest_property(&mut self, name: &str) -> Result<()> {
+        self.set_guest_property(name, "")
+    }
+
+    /// Polls the variable every second, `pattern` must be single variable name.
+    fn wait_for_property(&self, pattern: &str, timeout: Duration) -> Result<Option<GuestProperty>> {
+        if !property::is_literal(pattern) {
+            bail!(ErrorKind::InvalidConfig(
+                "VMware variables cannot be matched by pattern".into()
+            ))
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(value) = self.guest_property(pattern)? {
+                return Ok(Some(GuestProperty::new(pattern, value)));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Ok(None);
+            }
+            thread::sleep(remaining.min(Duration::from_secs(1)));
+        }
+    }
+
+    /// Relative file names are kept relative to the machine directory.
+    fn configure_serial(&mut self, port: u32, mode: &SerialMode) -> Result<()> {
+        mode.validate()?;
+        self.update_vmx(|vmx| vmx.set_serial_mode(port, mode))
+    }
+
+    fn serial_mode(&self, port: u32) -> Result<SerialMode> {
+        Ok(self.vmx()?.serial_mode(port))
+    }
+
+    fn read_serial(&self, port: u32, offset: u64) -> Result<Vec<u8>> {
+        match self.serial_mode(port)? {
+            SerialMode::File(path) => self
+                .driver_ref
+                .command_runner
+                .read_bytes_from(&self.host_path(&path), offset),
+            _ => bail!(ErrorKind::InvalidConfig(format!(
+                "serial port {} is not connected to file",
+                port
+            ))),
+        }
+    }
+
+    fn export(&self, path: &str, format: ExportFormat) -> Result<()> {
+        if !format.matches(path) {
+            bail!(ErrorKind::InvalidConfig(format!(
+                "{} does not end with .{}",
+                path,
+                format.extension()
+            )))
+        }
+        self.ensure_stopped()?;
+        let _ = self.driver_ref.ovftool([self.path.as_str(), path], None)?;
+        Ok(())
+    }
+
+    /// Without disks machine is unregistered and only configuration files
+    /// (`.vmx`, `.vmsd`, `.vmxf`, `.nvram`) are removed.
+    fn delete(&mut self, with_disks: bool) -> Result<()> {
+        self.ensure_stopped()?;
+        if !with_disks {
+            // machines opened by path need not be registered.
+            let _ = self.run(["unregister", &self.path]);
+            for ext in &["vmsd", "vmxf", "nvram"] {
+                self.driver_ref
+                    .command_runner
+                    .remove_file(&self.sibling_path(ext))?;
+            }
+            return self.driver_ref.command_runner.remove_file(&self.path);
+        }
+
+        let clones = self.linked_clones();
+        if !clones.is_empty() {
+            bail!(ErrorKind::HasLinkedClones(self.path.clone(), clones))
+        }
+        let _ = self.run(["deleteVM", &self.path])?;
+        Ok(())
+    }
+
+    fn guest_credentials(&self) -> Option<Credentials> {
+        Credentials::resolve(self.driver_ref.guest_credentials.as_ref(), &self.uri())
+    }
+
+    fn guest_session<'a>(
+        &'a self,
+        credentials: &Credentials,
+    ) -> Result<Box<dyn GuestSession + 'a>> {
+        Ok(Box::new(GuestRef {
+            machine: self,
+            credentials: credentials.clone(),
+        }))
+    }
+}
+
+struct GuestRef<'a, Cmd: CommandRunner + 'a> {
+    machine: &'a MachineRef<Cmd>,
+    credentials: Credentials,
+}
+
+impl<'a, Cmd: CommandRunner> GuestRef<'a, Cmd> {
+    /// Unlike `VBoxManage`, `vmrun` has no way to read guest password from
+    /// file or stdin, `-gp` is visible in process listings of the host.
+    fn run(&self, command: &str, args: &[&str]) -> Result<command::Output> {
+        let mut cmd_args = vec![
+            "-gu",
+            &self.credentials.user,
+            "-gp",
+            &self.credentials.password,
+            command,
+            &self.machine.path,
+        ];
+        cmd_args.extend_from_slice(args);
+        self.machine.run(cmd_args)
+    }
+}
+
+impl<'a, Cmd: CommandRunner> GuestSession for GuestRef<'a, Cmd> {
+    fn exec(&self, program: &str, args: &[&str]) -> Result<command::Output> {
+        let mut cmd_args = vec![program];
+        cmd_args.extend_from_slice(args);
+        let _ = self.run("runProgramInGuest", &cmd_args)?;
+        Ok(command::Output::new(Vec::new()))
+    }
+
+    fn copy_to_guest(&self, host_path: &str, guest_path: &str) -> Result<()> {
+        let _ = self.run("CopyFileFromHostToGuest", &[host_path, guest_path])?;
+        Ok(())
+    }
+
+    fn copy_from_guest(&self, guest_path: &str, host_path: &str) -> Result<()> {
+        let _ = self.run("CopyFileFromGuestToHost", &[guest_path, host_path])?;
+        Ok(())
+    }
+
+    fn list_processes(&self) -> Result<Vec<GuestProcess>> {
+        processes_parse(self.run("listProcessesInGuest", &[])?)
+    }
+
+    fn kill(&self, pid: u32) -> Result<()> {
+        let _ = self.run("killProcessInGuest", &[&pid.to_string()])?;
         Ok(())
     }
 }
 
+/// `listProcessesInGuest` output, `pid=N, owner=user, cmd=command line` after
+/// `Process list: N` summary.
+fn processes_parse<I: IntoIterator<Item = String>>(lines: I) -> Result<Vec<GuestProcess>> {
+    let mut processes = Vec::new();
+    for line in lines {
+        if line.starts_with("Process list:") || line.is_empty() {
+            continue;
+        }
+        let process = line.strip_prefix("pid=").and_then(|rest| {
+            let (pid, rest) = rest.split_once(", owner=")?;
+            let (owner, command) = rest.split_once(", cmd=")?;
+            Some(GuestProcess {
+                pid: pid.parse().ok()?,
+                owner: owner.into(),
+                command: command.into(),
+            })
+        });
+        processes.push(process.chain_err(|| ErrorKind::InvalidResponse(line.clone()))?);
+    }
+    Ok(processes)
+}
+
 impl<Cmd: CommandRunner + 'static> DriverFactory for Driver<Cmd> {
-    fn machine_for_uri(&self, uri: &str) -> Option<Box<Machine>> {
-        Some(Box::new(self.machine(uri.into())))
+    fn machine_for_uri(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        if uri.path().is_empty() {
+            bail!(ErrorKind::InvalidUri(uri.to_string()))
+        }
+        Ok(Box::new(
+            self.machine(uri.path().into(), uri.query().to_vec()),
+        ))
+    }
+
+    fn list_running(&self, uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        let host = VmrunHost::from_query(uri.query())?;
+        Ok(Listing::new(self.inner.running_paths(&host)?)
+            .map(|path| Box::new(self.machine(path, uri.query().to_vec())) as Box<dyn Machine>))
+    }
+
+    fn list_all(&self, uri: &VmUri) -> Result<Listing<Box<dyn Machine>>> {
+        let host = VmrunHost::from_query(uri.query())?;
+        Ok(Listing::new(self.inner.registered_paths(&host)?)
+            .map(|path| Box::new(self.machine(path, uri.query().to_vec())) as Box<dyn Machine>))
+    }
+
+    fn register(&self, uri: &VmUri) -> Result<Box<dyn Machine>> {
+        if uri.path().is_empty() {
+            bail!(ErrorKind::InvalidUri(uri.to_string()))
+        }
+        let machine = self.machine(uri.path().into(), uri.query().to_vec());
+        let _ = machine.run(["register", uri.path()])?;
+        Ok(Box::new(machine))
+    }
+
+    fn import_appliance(&self, uri: &VmUri, options: &ImportOptions) -> Result<Box<dyn Machine>> {
+        if uri.path().is_empty() {
+            bail!(ErrorKind::InvalidUri(uri.to_string()))
+        }
+        let mut machine = super::Driver::import_appliance(self, uri.path(), options)?;
+        machine.query = uri.query().to_vec();
+        Ok(Box::new(machine))
+    }
+
+    fn version(&self, _uri: &VmUri) -> Result<Version> {
+        super::Driver::version(self)
+    }
+
+    fn disk_store(&self, _uri: &VmUri) -> Result<Box<dyn DiskStore>> {
+        Ok(Box::new(Driver {
+            inner: self.inner.clone(),
+        }))
+    }
+}
+
+/// Disks are single file VMDKs on LSI Logic adapter, other formats are rejected.
+impl<Cmd: CommandRunner> DiskStore for Driver<Cmd> {
+    fn create(&self, path: &str, options: &DiskOptions) -> Result<()> {
+        options.validate()?;
+        if options.format != DiskFormat::Vmdk {
+            bail!(ErrorKind::InvalidConfig(format!(
+                "VMware disks must be VMDK, not {}",
+                options.format
+            )))
+        }
+        let size = format!("{}MB", options.size_mb);
+        // 0: single growable file, 2: single preallocated file.
+        let kind = if options.fixed { "2" } else { "0" };
+        let _ = self
+            .inner
+            .vdiskmanager(["-c", "-s", &size, "-a", "lsilogic", "-t", kind, path])?;
+        Ok(())
+    }
+
+    fn resize(&self, path: &str, size_mb: u64) -> Result<()> {
+        let size = format!("{}MB", size_mb);
+        let _ = self.inner.vdiskmanager(["-x", &size, path])?;
+        Ok(())
+    }
+
+    fn compact(&self, path: &str) -> Result<()> {
+        let _ = self.inner.vdiskmanager(["-k", path])?;
+        Ok(())
+    }
+
+    /// Removes descriptor and `-flat` extent of preallocated disk.
+    fn delete(&self, path: &str) -> Result<()> {
+        let runner = &self.inner.command_runner;
+        if let Some(stem) = path.strip_suffix(".vmdk") {
+            runner.remove_file(&format!("{}-flat.vmdk", stem))?;
+        }
+        runner.remove_file(path)
     }
 }
 
-pub fn local_driver() -> Box<DriverFactory> {
+pub fn local_driver() -> Box<dyn DriverFactory> {
     Box::new(factory().from_cmd(command::local()))
 }
 
-pub fn remote_driver() -> Box<DriverFactory> {
+pub fn remote_driver() -> Box<dyn DriverFactory> {
+    factory::<command::Ssh>().into()
+}
 
-    factory().into()
+#[cfg(feature = "http")]
+pub fn http_driver() -> Box<dyn DriverFactory> {
+    factory::<::http::Http>().into()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    /// Serves `vmrun list`, `find` and files from memory, as if over ssh.
+    struct FakeHost(Vec<(&'static str, &'static str)>);
+
+    impl CommandRunner for FakeHost {
+        fn run_with_output<C, I, S>(&self, cmd: C, args: I) -> Result<command::Output>
+        where
+            C: AsRef<OsStr>,
+            I: IntoIterator<Item = S>,
+            S: AsRef<OsStr>,
+        {
+            let args: Vec<String> = args
+                .into_iter()
+                .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+                .collect();
+            let output = match cmd.as_ref().to_str().unwrap() {
+                "vmrun" if args.is_empty() => vec![
+                    "vmrun version 1.17.0 build-21581411",
+                    "",
+                    "   -T <hostType> (ws|fusion|player)",
+                ],
+                // vmrun 1.x reports missing snapshot with exit status 0.
+                "vmrun" if args.contains(&"revertToSnapshot".to_string()) => {
+                    vec!["Error: The name does not uniquely identify one snapshot"]
+                }
+                "vmrun" => vec!["Total running VMs: 1", "/vms/running.vmx"],
+                "find" if args[0] == "/home/u/vmware" => {
+                    vec!["/home/u/vmware/a/a.vmx", "/vms/running.vmx"]
+                }
+                _ => bail!(ErrorKind::Exec(1, Vec::new().into(), Vec::new().into())),
+            };
+            Ok(command::Output::new(
+                output.into_iter().map(|s| s.to_string()).collect(),
+            ))
+        }
+
+        fn transport(&self) -> Option<VmUri> {
+            Some(VmUri::new("ssh", "").with_authority(None::<String>, "vmhost", None))
+        }
+
+        fn read_file(&self, path: &str) -> Result<String> {
+            match self.0.iter().find(|(p, _)| *p == path) {
+                Some((_, text)) => Ok(text.to_string()),
+                None => bail!(ErrorKind::Exec(1, Vec::new().into(), Vec::new().into())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_list_all() {
+        let host = FakeHost(vec![
+            (
+                ".vmware/inventory.vmls",
+                ".encoding = \"UTF-8\"\nvmlist1.config = \"/vms/stopped.vmx\"\n\
+                 vmlist2.config = \"folder0\"\nvmlist3.config = \"/vms/running.vmx\"\n",
+            ),
+            (
+                ".vmware/preferences",
+                "prefvmx.defaultVMPath = \"/home/u/vmware\"\n",
+            ),
+        ]);
+        let driver = factory().from_cmd(host);
+
+        let names: Vec<String> = super::super::Driver::list_all(&driver)
+            .unwrap()
+            .iter()
+            .map(|m| m.name().to_string())
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "/vms/running.vmx",
+                "/vms/stopped.vmx",
+                "/home/u/vmware/a/a.vmx"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_host_type_detect() {
+        let help = "vmrun version 1.17.0 build-21581411\n\n\
+                    AUTHENTICATION-FLAGS\n\
+                    --------------------\n\
+                    \x20  -h <hostName>  (not needed for Fusion)\n\
+                    \x20  -T <hostType> (ws|fusion|player)\n";
+        assert_eq!(host_type_detect(help, None), None);
+        assert_eq!(
+            host_type_detect(help, Some(HostType::Player)),
+            Some(HostType::Player)
+        );
+        assert_eq!(host_type_detect(help, Some(HostType::Esx)), None);
+        assert_eq!(
+            host_type_detect("   -T <hostType> (fusion)", None),
+            Some(HostType::Fusion)
+        );
+        assert_eq!(host_type_detect("Total running VMs: 0", None), None);
+        assert_eq!(host_type_detect("-T <hostType> ) fusion (", None), None);
+        // FakeHost has no product files, vmrun picks host type itself.
+        let driver = factory().from_cmd(FakeHost(Vec::new()));
+        assert_eq!(driver.inner.host_type(), None);
+
+        let driver = factory()
+            .with_host_type(HostType::Player)
+            .from_cmd(FakeHost(Vec::new()));
+        let machine = driver.machine("/vms/a.vmx".into(), vec![("T".into(), "esx".into())]);
+        assert_eq!(
+            machine.driver_ref.host.or(&VmrunHost::default()).args(),
+            vec!["-T", "player"]
+        );
+        assert_eq!(
+            VmrunHost::from_query(&machine.query)
+                .unwrap()
+                .or(&machine.driver_ref.host)
+                .args(),
+            vec!["-T", "esx"]
+        );
+        assert!(VmrunHost::from_query(&[("T".into(), "workstation".into())]).is_err());
+    }
+
+    #[test]
+    fn test_vmrun_error() {
+        let lines = |text: &str| text.lines().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        for known in VMRUN_ERRORS {
+            let prefixed = lines(&format!("Error: {}", known));
+            assert_eq!(vmrun_error("start", &prefixed), Some(known.to_string()));
+            assert!(vmrun_error("start", &lines(known)).is_some());
+            assert!(vmrun_error("listSnapshots", &lines(known)).is_some());
+        }
+        let cannot_open = "Cannot open VM: /vms/a.vmx, unknown file suffix";
+        assert_eq!(
+            vmrun_error("start", &lines(&format!("Error: {}", cannot_open))),
+            Some(cannot_open.to_string())
+        );
+        assert_eq!(
+            vmrun_error("listSnapshots", &lines("Total snapshots: 1\nclean")),
+            None
+        );
+        assert_eq!(
+            vmrun_error("listSnapshots", &lines("Total snapshots: 1\nError: broken")),
+            None
+        );
+        assert_eq!(vmrun_error("readVariable", &lines("Error: 42")), None);
+        assert_eq!(
+            vmrun_error("getGuestIPAddress", &lines("192.168.80.128")),
+            None
+        );
+        assert!(vmrun_error("getGuestIPAddress", &lines("Error: Unknown error")).is_some());
+        assert_eq!(vmrun_error("start", &[]), None);
+
+        let args: Vec<OsString> = vec!["-T".into(), "ws".into(), "-gu".into(), "u".into()];
+        let mut with_command = args.clone();
+        with_command.extend(vec!["readVariable".into(), "/vms/a.vmx".into()]);
+        assert_eq!(vmrun_subcommand(&with_command), "readVariable");
+        assert_eq!(vmrun_subcommand(&args), "");
+
+        let driver = factory().from_cmd(FakeHost(Vec::new()));
+        let mut machine = super::super::Driver::from_path(&driver, "/vms/a.vmx").unwrap();
+        match machine.revert_to("missing") {
+            Err(Error(ErrorKind::Hypervisor(ref message), _)) => {
+                assert!(message.starts_with("The name does not uniquely"))
+            }
+            other => panic!("unexpected result: {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    #[test]
+    fn test_update_suspended() {
+        let host = FakeHost(vec![(
+            "/vms/suspended.vmx",
+            "memsize = \"1024\"\ncheckpoint.vmState = \"suspended.vmss\"\n",
+        )]);
+        let driver = factory().from_cmd(host);
+        let mut machine = super::super::Driver::from_path(&driver, "/vms/suspended.vmx").unwrap();
+
+        match machine.update_vmx(|vmx| vmx.set_memsize(2048)) {
+            Err(Error(ErrorKind::MachineSuspended(_), _)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("suspended machine edited"),
+        }
+    }
+
+    #[test]
+    fn test_host_path() {
+        let driver = factory().from_cmd(FakeHost(Vec::new()));
+        let machine = super::super::Driver::from_path(&driver, "/vms/a.vmx").unwrap();
+        assert_eq!(machine.host_path("a.vmem"), "/vms/a.vmem");
+        assert_eq!(machine.host_path("/disks/a.vmem"), "/disks/a.vmem");
+
+        let machine = super::super::Driver::from_path(&driver, "C:\\vms\\a.vmx").unwrap();
+        assert_eq!(machine.host_path("a.vmem"), "C:\\vms\\a.vmem");
+        assert_eq!(machine.host_path("D:\\a.vmem"), "D:\\a.vmem");
+    }
+
+    #[test]
+    fn test_export_extension() {
+        let driver = factory().from_cmd(FakeHost(Vec::new()));
+        let machine = super::super::Driver::from_path(&driver, "/vms/a.vmx").unwrap();
+
+        match machine.export("/exports/a.ovf", ExportFormat::Ova) {
+            Err(Error(ErrorKind::InvalidConfig(_), _)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("exported with wrong extension"),
+        }
+    }
+
+    #[test]
+    fn test_version() {
+        let driver = factory().from_cmd(FakeHost(Vec::new()));
+        let version = super::super::Driver::version(&driver).unwrap();
+
+        assert_eq!(version.to_string(), "1.17.0 build 21581411");
+        let help = driver.inner.help().unwrap();
+        assert_eq!(
+            host_type_detect(&help, Some(HostType::Ws)),
+            Some(HostType::Ws)
+        );
+    }
+
+    #[test]
+    fn test_processes_parse() {
+        let output = "Process list: 2\n\
+                      pid=1, owner=root, cmd=/sbin/init splash\n\
+                      pid=4, owner=NT AUTHORITY\\SYSTEM, cmd=\"C:\\job.exe\" -a, -b\n";
+        let processes = processes_parse(output.lines().map(|s| s.to_string())).unwrap();
+
+        assert_eq!(processes.len(), 2);
+        assert_eq!(processes[1].pid, 4);
+        assert_eq!(processes[1].owner, "NT AUTHORITY\\SYSTEM");
+        assert_eq!(processes[1].command, "\"C:\\job.exe\" -a, -b");
+        assert!(processes_parse(vec!["pid=x, owner=a, cmd=b".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_nat_rules() {
+        let conf = "[host]\nip = 192.168.80.2\n\n[incomingtcp]\n# WEB\n8080 = 192.168.80.5:80\n";
+        let rule = PortForward::tcp("ssh", 2222, 22).guest_ip("192.168.80.128");
+
+        let added = nat_rule_add(conf, 0, "/vms/a.vmx", &rule).unwrap();
+        assert!(
+            added.contains("[incomingtcp]\n# vmctrl 0 ssh /vms/a.vmx\n2222 = 192.168.80.128:22\n")
+        );
+        assert_eq!(nat_rules_parse(&added, 0, "/vms/a.vmx"), vec![rule.clone()]);
+        assert!(nat_rules_parse(&added, 1, "/vms/a.vmx").is_empty());
+        assert!(nat_rules_parse(&added, 0, "/vms/b.vmx").is_empty());
+        assert!(nat_rule_add(&added, 0, "/vms/b.vmx", &PortForward::tcp("x", 8080, 80)).is_err());
+        let renamed = PortForward::tcp("ssh", 2200, 22).guest_ip("192.168.80.128");
+        assert!(nat_rule_add(&added, 0, "/vms/a.vmx", &renamed).is_err());
+        assert!(nat_rule_add(&added, 1, "/vms/a.vmx", &renamed).is_ok());
+
+        let windows = conf.replace('\n', "\r\n");
+        let added_windows = nat_rule_add(&windows, 0, "/vms/a.vmx", &rule).unwrap();
+        assert_eq!(added_windows, added.replace('\n', "\r\n"));
+        assert_eq!(
+            nat_rule_remove(&added_windows, 0, "/vms/a.vmx", "ssh").unwrap(),
+            windows
+        );
+
+        let udp = nat_rule_add(
+            conf,
+            0,
+            "/vms/a.vmx",
+            &PortForward::udp("dns", 5353, 53).guest_ip("192.168.80.128"),
+        )
+        .unwrap();
+        assert!(
+            udp.ends_with("\n[incomingudp]\n# vmctrl 0 dns /vms/a.vmx\n5353 = 192.168.80.128:53\n")
+        );
+
+        assert_eq!(
+            nat_rule_remove(&added, 0, "/vms/a.vmx", "ssh").unwrap(),
+            conf
+        );
+        assert!(nat_rule_remove(&added, 0, "/vms/a.vmx", "web").is_none());
+    }
+
     #[test]
     fn test_cow() {
         let c: Cow<'static, str> = "vmrun".into();
@@ -0,0 +1,591 @@
+//! VMware `.vmx` (and `.vmsd`) configuration files.
+//!
+//! Files are kept as list of lines, so writing back preserves ordering,
+//! comments and formatting of entries that were not modified. Keys are
+//! case insensitive, values use `|XX` hex escapes for `"` and `|`.
+
+use super::info::{MachineInfo, MediumAttachment, NicAttachment, NicInfo, StorageController};
+use super::serial::SerialMode;
+use super::shared_folder::SharedFolder;
+use super::MachineState;
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+use super::error::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Entry {
+        key: String,
+        value: String,
+        /// Original text, dropped when the value changes.
+        raw: Option<String>,
+    },
+    Other(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vmx {
+    lines: Vec<Line>,
+}
+
+/// Disk or cdrom attached to `scsiN:M`, `sataN:M`, `ideN:M` or `nvmeN:M`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disk {
+    /// Device name, e.g. `scsi0:1`.
+    pub device: String,
+    pub controller: String,
+    pub unit: u32,
+    pub file_name: Option<String>,
+    pub device_type: Option<String>,
+}
+
+const CONTROLLERS: &[&str] = &["ide", "scsi", "sata", "nvme"];
+
+/// Decodes `|XX` escapes, sequences of them form UTF-8 characters.
+fn unescape(value: &str) -> String {
+    let mut result = Vec::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('|') {
+        result.extend_from_slice(&rest.as_bytes()[..pos]);
+        let code = rest
+            .get(pos + 1..pos + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match code {
+            Some(code) => {
+                result.push(code);
+                rest = &rest[pos + 3..];
+            }
+            None => {
+                result.push(b'|');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    result.extend_from_slice(rest.as_bytes());
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+fn escape(value: &str) -> String {
+    value.replace('|', "|7C").replace('"', "|22")
+}
+
+fn is_true(value: &str) -> bool {
+    value.eq_ignore_ascii_case("true")
+}
+
+impl Vmx {
+    pub fn parse(text: &str) -> Result<Self> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r#"^\s*([^=\s]+)\s*=\s*(?:"([^"]*)"|(\S*))\s*$"#).unwrap();
+        }
+
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.trim_start().starts_with('#') || line.trim().is_empty() {
+                lines.push(Line::Other(line.into()));
+                continue;
+            }
+            match RE.captures(line) {
+                Some(caps) => lines.push(Line::Entry {
+                    key: caps[1].into(),
+                    value: unescape(caps.get(2).or_else(|| caps.get(3)).unwrap().as_str()),
+                    raw: Some(line.into()),
+                }),
+                None => bail!(ErrorKind::InvalidResponse(line.into())),
+            }
+        }
+        Ok(Vmx { lines })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().rev().find_map(|line| match *line {
+            Line::Entry {
+                key: ref k,
+                ref value,
+                ..
+            } if k.eq_ignore_ascii_case(key) => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Updates entry in place, new keys are appended at the end.
+    pub fn set<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        let (key, value) = (key.into(), value.into());
+        for line in self.lines.iter_mut().rev() {
+            if let Line::Entry {
+                key: ref k,
+                value: ref mut v,
+                ref mut raw,
+            } = *line
+            {
+                if k.eq_ignore_ascii_case(&key) {
+                    if *v != value {
+                        *v = value;
+                        *raw = None;
+                    }
+                    return;
+                }
+            }
+        }
+        self.lines.push(Line::Entry {
+            key,
+            value,
+            raw: None,
+        });
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let pos = self.lines.iter().position(|line| match *line {
+            Line::Entry { key: ref k, .. } => k.eq_ignore_ascii_case(key),
+            _ => false,
+        })?;
+        match self.lines.remove(pos) {
+            Line::Entry { value, .. } => Some(value),
+            Line::Other(_) => None,
+        }
+    }
+
+    /// All entries in file order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match *line {
+            Line::Entry {
+                ref key, ref value, ..
+            } => Some((key.as_str(), value.as_str())),
+            Line::Other(_) => None,
+        })
+    }
+
+    fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).map(is_true)
+    }
+
+    pub fn display_name(&self) -> Option<&str> {
+        self.get("displayName")
+    }
+
+    pub fn set_display_name(&mut self, name: &str) {
+        self.set("displayName", name)
+    }
+
+    /// Memory size in MB.
+    pub fn memsize(&self) -> Option<u64> {
+        self.get("memsize").and_then(|v| v.parse().ok())
+    }
+
+    pub fn set_memsize(&mut self, memsize_mb: u64) {
+        self.set("memsize", memsize_mb.to_string())
+    }
+
+    pub fn numvcpus(&self) -> Option<u32> {
+        self.get("numvcpus").and_then(|v| v.parse().ok())
+    }
+
+    pub fn set_numvcpus(&mut self, numvcpus: u32) {
+        self.set("numvcpus", numvcpus.to_string())
+    }
+
+    pub fn guest_os(&self) -> Option<&str> {
+        self.get("guestOS")
+    }
+
+    pub fn set_guest_os(&mut self, guest_os: &str) {
+        self.set("guestOS", guest_os)
+    }
+
+    /// Slots of present `ethernetN` adapters.
+    pub fn ethernet_slots(&self) -> Vec<u32> {
+        let mut slots: Vec<u32> = self
+            .entries()
+            .filter_map(|(key, value)| {
+                let key = key.to_ascii_lowercase();
+                let slot = key.strip_prefix("ethernet")?.strip_suffix(".present")?;
+                if is_true(value) {
+                    slot.parse().ok()
+                } else {
+                    None
+                }
+            })
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+        slots
+    }
+
+    /// `ethernetN.<field>`, e.g. `ethernet(0, "connectionType")`.
+    pub fn ethernet(&self, slot: u32, field: &str) -> Option<&str> {
+        self.get(&format!("ethernet{}.{}", slot, field))
+    }
+
+    pub fn set_ethernet(&mut self, slot: u32, field: &str, value: &str) {
+        self.set(format!("ethernet{}.{}", slot, field), value)
+    }
+
+    /// Host end of `serialN` port, `fileName` as written in the file.
+    pub fn serial_mode(&self, port: u32) -> SerialMode {
+        let field = |name: &str| self.get(&format!("serial{}.{}", port, name));
+        if !field("present").is_some_and(is_true) {
+            return SerialMode::Off;
+        }
+        if field("startConnected").is_some_and(|value| !is_true(value)) {
+            return SerialMode::Disconnected;
+        }
+        // VMware picks a host serial device when file type is missing.
+        match (field("fileType").unwrap_or("device"), field("fileName")) {
+            ("file", Some(path)) => SerialMode::File(path.into()),
+            ("pipe", Some(path)) => SerialMode::Pipe(path.into()),
+            (other, _) => SerialMode::Other(other.into()),
+        }
+    }
+
+    pub fn set_serial_mode(&mut self, port: u32, mode: &SerialMode) {
+        let key = |name: &str| format!("serial{}.{}", port, name);
+        if *mode == SerialMode::Off {
+            self.set(key("present"), "FALSE");
+            return;
+        }
+        self.set(key("present"), "TRUE");
+        self.set(
+            key("startConnected"),
+            if *mode == SerialMode::Disconnected {
+                "FALSE"
+            } else {
+                "TRUE"
+            },
+        );
+        match *mode {
+            SerialMode::File(ref path) => {
+                self.set(key("fileType"), "file");
+                self.set(key("fileName"), path.as_str());
+            }
+            SerialMode::Pipe(ref path) => {
+                self.set(key("fileType"), "pipe");
+                self.set(key("fileName"), path.as_str());
+                self.set(key("pipe.endPoint"), "server");
+            }
+            _ => {}
+        }
+    }
+
+    /// Present `sharedFolderN` entries, in slot order.
+    pub fn shared_folders(&self) -> Vec<SharedFolder> {
+        let mut slots: Vec<u32> = self
+            .entries()
+            .filter_map(|(key, value)| {
+                let key = key.to_ascii_lowercase();
+                let slot = key.strip_prefix("sharedfolder")?.strip_suffix(".present")?;
+                if is_true(value) {
+                    slot.parse().ok()
+                } else {
+                    None
+                }
+            })
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+
+        slots
+            .into_iter()
+            .filter_map(|slot| {
+                let field = |name: &str| self.get(&format!("sharedFolder{}.{}", slot, name));
+                let mut folder = SharedFolder::new(field("guestName")?, field("hostPath")?);
+                folder.writable = field("writeAccess").is_some_and(is_true);
+                folder.auto_mount = true;
+                folder.transient = field("expiration") == Some("session");
+                Some(folder)
+            })
+            .collect()
+    }
+
+    /// Present devices on all disk controllers, in file order.
+    pub fn disks(&self) -> Vec<Disk> {
+        let mut devices: Vec<String> = Vec::new();
+        for (key, value) in self.entries() {
+            let device = match key.strip_suffix(".present") {
+                Some(device) if device.contains(':') && is_true(value) => device,
+                _ => continue,
+            };
+            if !devices.iter().any(|d| d.eq_ignore_ascii_case(device)) {
+                devices.push(device.into());
+            }
+        }
+
+        devices
+            .into_iter()
+            .filter_map(|device| {
+                let (controller, unit) = {
+                    let mut it = device.splitn(2, ':');
+                    (it.next()?.to_string(), it.next()?.parse().ok()?)
+                };
+                let kind = controller.trim_end_matches(|c: char| c.is_ascii_digit());
+                if !CONTROLLERS.contains(&kind.to_ascii_lowercase().as_str()) {
+                    return None;
+                }
+                Some(Disk {
+                    file_name: self.disk_file(&device).map(|s| s.to_string()),
+                    device_type: self
+                        .get(&format!("{}.deviceType", device))
+                        .map(|s| s.to_string()),
+                    device,
+                    controller,
+                    unit,
+                })
+            })
+            .collect()
+    }
+
+    /// `fileName` of device such as `scsi0:0`.
+    pub fn disk_file(&self, device: &str) -> Option<&str> {
+        self.get(&format!("{}.fileName", device))
+    }
+
+    pub fn set_disk_file(&mut self, device: &str, file_name: &str) {
+        self.set(format!("{}.fileName", device), file_name)
+    }
+
+    /// Machine summary, `state` is not stored in `.vmx` so it must be supplied.
+    pub fn to_info(&self, fallback_name: &str, state: MachineState) -> MachineInfo {
+        let mut info = MachineInfo::new(self.display_name().unwrap_or(fallback_name));
+        info.uuid = self.get("uuid.bios").map(|s| s.to_string());
+        info.os_type = self.guest_os().map(|s| s.to_string());
+        info.cpus = self.numvcpus();
+        info.memory_mb = self.memsize();
+        info.state = state;
+        info.snapshot_folder = self.get("workingDir").map(|s| s.to_string());
+
+        for slot in self.ethernet_slots() {
+            let attachment = match self.ethernet(slot, "connectionType") {
+                // VMware default for adapters without explicit type.
+                None | Some("bridged") => NicAttachment::Bridged,
+                Some("nat") => NicAttachment::Nat,
+                Some("hostonly") => NicAttachment::HostOnly,
+                Some("pvn") => NicAttachment::Internal,
+                Some(other) => NicAttachment::Other(other.into()),
+            };
+            let network = self
+                .ethernet(slot, "vnet")
+                .or_else(|| self.ethernet(slot, "pvnID"));
+            let mac = self
+                .ethernet(slot, "address")
+                .or_else(|| self.ethernet(slot, "generatedAddress"));
+            info.nics.push(NicInfo {
+                slot,
+                attachment,
+                network: network.map(|s| s.to_string()),
+                mac: mac.map(|s| s.to_string()),
+                cable_connected: self.get_bool(&format!("ethernet{}.startConnected", slot)),
+            });
+        }
+
+        for disk in self.disks() {
+            let attachment = MediumAttachment {
+                port: disk.unit,
+                device: 0,
+                medium: disk.file_name.unwrap_or_else(|| "emptydrive".into()),
+                uuid: None,
+            };
+            let controller = disk.controller;
+            match info.storage.iter_mut().find(|c| c.name == controller) {
+                Some(existing) => existing.attachments.push(attachment),
+                None => info.storage.push(StorageController {
+                    controller_type: self
+                        .get(&format!("{}.virtualDev", controller))
+                        .map(|s| s.to_string()),
+                    name: controller,
+                    attachments: vec![attachment],
+                }),
+            }
+        }
+
+        info
+    }
+
+    /// Display name of current snapshot, `self` being `.vmsd` file.
+    pub fn current_snapshot(&self) -> Option<&str> {
+        let uid = self.get("snapshot.current")?;
+        let prefix = self.entries().find_map(|(key, value)| {
+            if value == uid {
+                key.strip_suffix(".uid")
+            } else {
+                None
+            }
+        })?;
+        self.get(&format!("{}.displayName", prefix))
+    }
+
+    /// `.vmx` paths of linked clones made from snapshots, `self` being `.vmsd` file.
+    pub fn clones(&self) -> Vec<&str> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"(?i)^snapshot\d+\.clone\d+$").unwrap();
+        }
+
+        self.entries()
+            .filter(|(key, _)| RE.is_match(key))
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+impl FromStr for Vmx {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Vmx::parse(s)
+    }
+}
+
+impl fmt::Display for Vmx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match *line {
+                Line::Entry {
+                    raw: Some(ref raw), ..
+                }
+                | Line::Other(ref raw) => writeln!(f, "{}", raw)?,
+                Line::Entry {
+                    ref key,
+                    ref value,
+                    raw: None,
+                } => writeln!(f, "{} = \"{}\"", key, escape(value))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VMX: &str = r#".encoding = "UTF-8"
+config.version = "8"
+# build worker
+displayName = "ubuntu |22a|22"
+guestOS = "ubuntu-64"
+memsize = "2048"
+numvcpus = "2"
+uuid.bios = "56 4d 12 34 56 78 9a bc-de f0 12 34 56 78 9a bc"
+scsi0.present = "TRUE"
+scsi0.virtualDev = "lsilogic"
+scsi0:0.present = "TRUE"
+scsi0:0.fileName = "ubuntu.vmdk"
+sata0.present = "TRUE"
+sata0:1.present = "TRUE"
+sata0:1.deviceType = "cdrom-raw"
+ethernet0.present = "TRUE"
+ethernet0.connectionType = "nat"
+ethernet0.generatedAddress = "00:0c:29:12:34:56"
+ethernet1.present = "FALSE"
+ethernet2.present = "TRUE"
+ethernet2.connectionType = "custom"
+ethernet2.vnet = "VMnet2"
+ethernet2.startConnected = "FALSE"
+sharedFolder0.present = "TRUE"
+sharedFolder0.enabled = "TRUE"
+sharedFolder0.readAccess = "TRUE"
+sharedFolder0.writeAccess = "FALSE"
+sharedFolder0.hostPath = "/home/ci/src"
+sharedFolder0.guestName = "src"
+sharedFolder0.expiration = "never"
+serial0.present = "TRUE"
+serial0.fileType = "file"
+serial0.fileName = "serial.log"
+"#;
+
+    #[test]
+    fn test_parse() {
+        let vmx = Vmx::parse(VMX).unwrap();
+
+        assert_eq!(vmx.display_name(), Some("ubuntu \"a\""));
+        assert_eq!(vmx.get("DISPLAYNAME"), vmx.display_name());
+        assert_eq!(vmx.memsize(), Some(2048));
+        assert_eq!(vmx.numvcpus(), Some(2));
+        assert_eq!(vmx.guest_os(), Some("ubuntu-64"));
+        assert_eq!(vmx.ethernet_slots(), vec![0, 2]);
+        assert_eq!(vmx.disk_file("scsi0:0"), Some("ubuntu.vmdk"));
+        assert_eq!(vmx.disks().len(), 2);
+        assert_eq!(vmx.disks()[1].device_type.as_ref().unwrap(), "cdrom-raw");
+        assert_eq!(
+            vmx.shared_folders(),
+            vec![SharedFolder::new("src", "/home/ci/src")
+                .read_only()
+                .auto_mount()]
+        );
+        assert!(Vmx::parse("no equals sign").is_err());
+        assert_eq!(unescape("Z|C3|BCrich |7C 50|"), "Zürich | 50|");
+    }
+
+    #[test]
+    fn test_serial_mode() {
+        let mut vmx = Vmx::parse(VMX).unwrap();
+        assert_eq!(vmx.serial_mode(0), SerialMode::File("serial.log".into()));
+        assert_eq!(vmx.serial_mode(1), SerialMode::Off);
+
+        vmx.set_serial_mode(1, &SerialMode::Pipe("/tmp/worker-1.sock".into()));
+        vmx.set_serial_mode(0, &SerialMode::Disconnected);
+        assert_eq!(
+            vmx.serial_mode(1),
+            SerialMode::Pipe("/tmp/worker-1.sock".into())
+        );
+        assert_eq!(vmx.get("serial1.pipe.endPoint"), Some("server"));
+        assert_eq!(vmx.serial_mode(0), SerialMode::Disconnected);
+    }
+
+    #[test]
+    fn test_write_preserves_layout() {
+        let mut vmx = Vmx::parse(VMX).unwrap();
+        assert_eq!(vmx.to_string(), VMX);
+
+        vmx.set_memsize(4096);
+        vmx.set_display_name("a|b");
+        vmx.set("tools.syncTime", "TRUE");
+        let text = vmx.to_string();
+
+        assert!(text.contains("# build worker\ndisplayName = \"a|7Cb\"\n"));
+        assert!(text.contains("\nmemsize = \"4096\"\nnumvcpus = \"2\"\n"));
+        assert!(text.ends_with("tools.syncTime = \"TRUE\"\n"));
+        assert_eq!(Vmx::parse(&text).unwrap().display_name(), Some("a|b"));
+    }
+
+    #[test]
+    fn test_info() {
+        let info = Vmx::parse(VMX)
+            .unwrap()
+            .to_info("/vms/ubuntu.vmx", MachineState::PoweredOff);
+
+        assert_eq!(info.name, "ubuntu \"a\"");
+        assert_eq!(info.memory_mb, Some(2048));
+        assert_eq!(info.nics.len(), 2);
+        assert_eq!(info.nics[0].attachment, NicAttachment::Nat);
+        assert_eq!(info.nics[0].mac.as_ref().unwrap(), "00:0c:29:12:34:56");
+        assert_eq!(info.nics[1].network.as_ref().unwrap(), "VMnet2");
+        assert_eq!(info.nics[1].cable_connected, Some(false));
+        assert_eq!(info.storage.len(), 2);
+        assert_eq!(
+            info.storage[0].controller_type.as_ref().unwrap(),
+            "lsilogic"
+        );
+        assert_eq!(info.storage[1].attachments[0].medium, "emptydrive");
+    }
+
+    #[test]
+    fn test_current_snapshot() {
+        let vmsd = Vmx::parse(
+            "snapshot.current = \"2\"\nsnapshot0.uid = \"1\"\nsnapshot0.displayName = \"base\"\n\
+             snapshot1.uid = \"2\"\nsnapshot1.displayName = \"clean\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(vmsd.current_snapshot(), Some("clean"));
+        assert!(vmsd.clones().is_empty());
+
+        let vmsd = Vmx::parse(
+            "snapshot0.numClones = \"1\"\nsnapshot0.clone0 = \"/vms/worker-1/worker-1.vmx\"\n",
+        )
+        .unwrap();
+        assert_eq!(vmsd.clones(), vec!["/vms/worker-1/worker-1.vmx"]);
+    }
+}